@@ -0,0 +1,167 @@
+//! Join benchmarks over a large world, run with `cargo +nightly bench --bench query`
+
+#![feature(test)]
+
+extern crate test;
+
+use test::Bencher;
+
+use mela::ecs::join::{Maybe, Without};
+use mela::ecs::system::{Read, ReadStorage, SystemData, Write, WriteStorage};
+use mela::ecs::world::{DefaultWorld, World, WorldStorage};
+use mela::ecs::{Component, ComponentStorage, Join, JoinCache, ReadAccess};
+
+const ENTITIES: usize = 50_000;
+
+#[derive(Debug)]
+struct Position(f32, f32);
+impl Component for Position {}
+
+#[derive(Debug)]
+struct Velocity(f32, f32);
+impl Component for Velocity {}
+
+#[derive(Debug)]
+struct Tag;
+impl Component for Tag {}
+
+/// every entity has a Position, every third a Velocity, every fifth a Tag
+fn world() -> DefaultWorld {
+    let mut world = DefaultWorld::new()
+        .register::<Position>()
+        .register::<Velocity>()
+        .register::<Tag>();
+
+    for i in 0..ENTITIES {
+        let mut builder = world.add_entity().with_component(Position(i as f32, 0.));
+
+        if i % 3 == 0 {
+            builder = builder.with_component(Velocity(1., 1.));
+        }
+
+        if i % 5 == 0 {
+            builder = builder.with_component(Tag);
+        }
+
+        world = builder.build();
+    }
+
+    world
+}
+
+/// what the joins are measured against, the `VecReader`s of the storages without any join
+#[bench]
+fn baseline(b: &mut Bencher) {
+    let world = world();
+    let positions = WorldStorage::<Position>::storage(&world).read();
+    let velocities = WorldStorage::<Velocity>::storage(&world).read();
+
+    b.iter(|| {
+        let mut sum = 0.;
+        for (entity, position) in positions.iter() {
+            if let Some(velocity) = velocities.fetch(entity) {
+                sum += position.0 + velocity.0;
+            }
+        }
+        sum
+    });
+}
+
+#[bench]
+fn join_read(b: &mut Bencher) {
+    let world = world();
+    let (positions, velocities): (ReadStorage<_, Position>, ReadStorage<_, Velocity>) =
+        SystemData::get(&world);
+
+    b.iter(|| {
+        let mut sum = 0.;
+        for (_, (position, velocity)) in (&positions, &velocities).join() {
+            sum += position.0 + velocity.0;
+        }
+        sum
+    });
+}
+
+#[bench]
+fn join(b: &mut Bencher) {
+    let world = world();
+    let (mut positions, velocities, tags): (Write<Position>, Read<Velocity>, Read<Tag>) =
+        SystemData::get(&world);
+
+    b.iter(|| {
+        for (_, (mut position, velocity, _)) in (&mut positions, &velocities, Maybe(&tags)).join() {
+            position.0 += velocity.0;
+            position.1 += velocity.1;
+        }
+    });
+}
+
+#[bench]
+fn join_cached(b: &mut Bencher) {
+    let world = world();
+    let (mut positions, velocities, tags): (Write<Position>, Read<Velocity>, Read<Tag>) =
+        SystemData::get(&world);
+    let mut cache = JoinCache::new();
+
+    b.iter(|| {
+        for (_, (mut position, velocity, _)) in
+            (&mut positions, &velocities, Maybe(&tags)).join_cached(&mut cache)
+        {
+            position.0 += velocity.0;
+            position.1 += velocity.1;
+        }
+    });
+}
+
+#[bench]
+fn join_storage(b: &mut Bencher) {
+    let world = world();
+    let (mut positions, velocities, tags): (
+        WriteStorage<_, Position>,
+        ReadStorage<_, Velocity>,
+        ReadStorage<_, Tag>,
+    ) = SystemData::get(&world);
+
+    b.iter(|| {
+        for (_, (mut position, velocity, _)) in (&mut positions, &velocities, Maybe(&tags)).join() {
+            position.0 += velocity.0;
+            position.1 += velocity.1;
+        }
+    });
+}
+
+#[bench]
+fn join_storage_cached(b: &mut Bencher) {
+    let world = world();
+    let (mut positions, velocities, tags): (
+        WriteStorage<_, Position>,
+        ReadStorage<_, Velocity>,
+        ReadStorage<_, Tag>,
+    ) = SystemData::get(&world);
+    let mut cache = JoinCache::new();
+
+    b.iter(|| {
+        for (_, (mut position, velocity, _)) in
+            (&mut positions, &velocities, Maybe(&tags)).join_cached(&mut cache)
+        {
+            position.0 += velocity.0;
+            position.1 += velocity.1;
+        }
+    });
+}
+
+#[bench]
+fn without_cached(b: &mut Bencher) {
+    let world = world();
+    let (positions, tags): (ReadStorage<_, Position>, ReadStorage<_, Tag>) =
+        SystemData::get(&world);
+    let mut cache = JoinCache::new();
+
+    b.iter(|| {
+        let mut sum = 0.;
+        for (_, (position, _)) in (&positions, Without(&tags)).join_cached(&mut cache) {
+            sum += position.0;
+        }
+        sum
+    });
+}
@@ -3,7 +3,7 @@
 use crate::states::States;
 use mela::application::Application;
 use mela::debug::DebugContext;
-use mela::game::Playable;
+use mela::game::{IoState, Playable};
 use mela::gfx::RenderContext;
 use mela::state::State;
 use std::time::Duration;
@@ -28,12 +28,13 @@ impl Playable for Hello2dGame {
     fn update(
         self,
         delta: Duration,
+        io_state: &IoState,
         render_ctx: &mut RenderContext,
         debug_ctx: &mut DebugContext,
     ) -> Self {
         let Hello2dGame { state } = self;
 
-        let new_state = state.update(delta, render_ctx, debug_ctx);
+        let new_state = state.update(delta, io_state, render_ctx, debug_ctx);
 
         Hello2dGame { state: new_state }
     }
@@ -20,17 +20,19 @@ impl Play {
             .unwrap()
             .create_default_view();
 
-        let sampler = render_ctx.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: Default::default(),
-            address_mode_v: Default::default(),
-            address_mode_w: Default::default(),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: Default::default(),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare_function: wgpu::CompareFunction::Never,
-        });
+        let sampler = render_ctx
+            .device()
+            .create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: Default::default(),
+                address_mode_v: Default::default(),
+                address_mode_w: Default::default(),
+                mag_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: Default::default(),
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare_function: wgpu::CompareFunction::Never,
+            });
 
         let projection = nalgebra::Matrix4::new_nonuniform_scaling(&Vector3::new(1., 16. / 9., 1.))
             .append_translation(&Vector3::new(-1., -1., 0.));
@@ -45,14 +47,14 @@ impl Play {
         };
 
         let transforms_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM)
             .fill_from_slice(&[transformations]);
 
         let bind_group = render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &render_ctx.pipelines.pixel.1,
+                layout: &render_ctx.pipelines().pixel.1,
                 bindings: &[
                     wgpu::Binding {
                         binding: 0,
@@ -98,20 +100,22 @@ impl State for Play {
         let (mut vertices, indices) = quad.vertices_and_indices2d([0., 0.], [1., 1., 1., 1.]);
 
         let vertex_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(vertices.len(), wgpu::BufferUsage::VERTEX)
             .fill_from_slice(&vertices);
 
         let index_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(indices.len(), wgpu::BufferUsage::INDEX)
             .fill_from_slice(&indices);
 
+        let frame = render_ctx.frame();
+        let pipelines = render_ctx.pipelines();
         let mut rpass = render_ctx
-            .encoder
+            .encoder()
             .begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &render_ctx.frame,
+                    attachment: frame,
                     resolve_target: None,
                     load_op: wgpu::LoadOp::Clear,
                     store_op: wgpu::StoreOp::Store,
@@ -120,7 +124,7 @@ impl State for Play {
                 depth_stencil_attachment: None,
             });
 
-        rpass.set_pipeline(&render_ctx.pipelines.pixel.0);
+        rpass.set_pipeline(&pipelines.pixel.0);
         rpass.set_bind_group(0, &self.bind_group, &[]);
         rpass.set_index_buffer(&index_buf, 0);
         rpass.set_vertex_buffers(0, &[(&vertex_buf, 0)]);
@@ -10,25 +10,23 @@ use mela::application::Application;
 use mela::debug::DebugContext;
 use mela::game::{IoState, Playable};
 use mela::gfx::RenderContext;
+use mela::input::replay::Replay;
 use mela::state::State;
 
 use crate::states::States;
 
-mod components;
 mod states;
 mod systems;
 mod world;
 
 struct Hello3dGame {
     state: States,
-    io_state: IoState,
 }
 
 impl Hello3dGame {
     pub fn new() -> Hello3dGame {
         Hello3dGame {
             state: States::new(),
-            io_state: IoState::default(),
         }
     }
 }
@@ -37,21 +35,28 @@ impl Playable for Hello3dGame {
     fn update(
         self,
         delta: Duration,
+        io_state: &IoState,
         render_ctx: &mut RenderContext,
         debug_ctx: &mut DebugContext,
     ) -> Self {
-        let Hello3dGame {
-            state,
-            mut io_state,
-        } = self;
+        let Hello3dGame { state } = self;
 
-        let new_state = state.update(delta, &io_state, render_ctx, debug_ctx);
+        Hello3dGame {
+            state: state.update(delta, io_state, render_ctx, debug_ctx),
+        }
+    }
 
-        io_state.update();
+    fn fixed_update(
+        self,
+        step: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) -> Self {
+        let Hello3dGame { state } = self;
 
         Hello3dGame {
-            state: new_state,
-            io_state,
+            state: state.fixed_update(step, io_state, render_ctx, debug_ctx),
         }
     }
 
@@ -61,16 +66,6 @@ impl Playable for Hello3dGame {
                 event: WindowEvent::CloseRequested,
                 ..
             } => Some(ControlFlow::Exit),
-            Event::WindowEvent {
-                event: WindowEvent::KeyboardInput { input, .. },
-                ..
-            } => {
-                self.io_state.set_key(
-                    input.scancode,
-                    input.state == winit::event::ElementState::Pressed,
-                );
-                None
-            }
             _ => None,
         }
     }
@@ -80,9 +75,19 @@ impl Playable for Hello3dGame {
     }
 }
 
+/// `--record <file>` records the session, `--replay <file>` plays one back
 pub fn main() {
     let game = Hello3dGame::new();
     let app = Application::new(game, "Hello 3D");
 
+    let args: Vec<String> = std::env::args().collect();
+    let app = match (args.get(1).map(String::as_str), args.get(2)) {
+        (Some("--record"), Some(path)) => app.record_to(path),
+        (Some("--replay"), Some(path)) => {
+            app.with_replay(Replay::load(path).expect("failed to load replay"))
+        }
+        _ => app,
+    };
+
     app.run();
 }
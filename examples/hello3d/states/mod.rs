@@ -19,6 +19,20 @@ impl States {
     pub fn new() -> States {
         States::Loading(Loading::new())
     }
+
+    /// advances the current state by one fixed tick
+    pub fn fixed_update(
+        self,
+        step: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) -> States {
+        match self {
+            States::Play(s) => s.fixed_update(step, io_state, render_ctx, debug_ctx),
+            s => s,
+        }
+    }
 }
 
 impl State for States {
@@ -3,29 +3,33 @@ use std::time::Duration;
 use nalgebra::Vector3;
 
 use mela::debug::{DebugContext, DebugDrawable};
+use mela::ecs::schedule::stage;
 use mela::ecs::system::physics::{PhysicsSystem, PhysicsWorld};
 use mela::ecs::system::scene::SceneSystem;
-use mela::ecs::system::SystemCaller;
+use mela::ecs::system::transform::{TransformHistorySystem, TransformSystem};
+use mela::ecs::world::World;
+use mela::ecs::Schedule;
 use mela::game::IoState;
 use mela::gfx::RenderContext;
+use mela::input::InputMap;
 use mela::state::State;
 
 use crate::states::loading::GameAssets;
 use crate::states::States;
 use crate::systems::{CameraUnclipper, InputSystem};
-use crate::world::MyWorld;
-use std::rc::Rc;
-use std::sync::RwLock;
+use crate::world::{self, MyWorld};
 
 pub struct Play {
     paused: bool,
     world: MyWorld,
-    systems: Vec<Box<dyn SystemCaller<MyWorld>>>,
+    systems: Schedule<MyWorld>,
+    /// systems run every fixed tick
+    tick_systems: Schedule<MyWorld>,
 }
 
 impl Play {
     pub fn new(assets: GameAssets, render_ctx: &mut RenderContext) -> Play {
-        let mut world = MyWorld::default();
+        let mut world = world::new_world();
 
         let (scene_system, new_world) = SceneSystem::from_gltf(
             assets.scene.document,
@@ -35,23 +39,51 @@ impl Play {
         );
         world = new_world;
 
-        let physics_world = Rc::new(RwLock::new(PhysicsWorld::new(Vector3::z() * -9.81_f32)));
+        let world = world.with_resource(PhysicsWorld::new(Vector3::z() * -9.81_f32));
 
-        let systems = vec![
-            Box::new(InputSystem::new(Rc::clone(&physics_world))) as Box<dyn SystemCaller<MyWorld>>,
-            Box::new(PhysicsSystem::new(Rc::clone(&physics_world)))
-                as Box<dyn SystemCaller<MyWorld>>,
-            Box::new(CameraUnclipper::new(Rc::clone(&physics_world)))
-                as Box<dyn SystemCaller<MyWorld>>,
-            Box::new(scene_system) as Box<dyn SystemCaller<MyWorld>>,
-        ];
+        let systems = Schedule::new()
+            .with(stage::PRE_UPDATE, InputSystem::new())
+            .with(stage::UPDATE, CameraUnclipper::new())
+            .with_parallel(stage::POST_UPDATE, TransformSystem::<f32>::new())
+            .with(stage::RENDER_PREP, scene_system);
+
+        let tick_systems = Schedule::new()
+            .with_parallel(stage::UPDATE, TransformHistorySystem::<f32>::new())
+            .with(stage::UPDATE, PhysicsSystem::<f32>::new());
 
         Play {
             world,
             systems,
+            tick_systems,
             paused: true,
         }
     }
+
+    pub fn fixed_update(
+        self,
+        step: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) -> States {
+        if self.paused {
+            return States::Play(self);
+        }
+
+        let Play {
+            world,
+            mut tick_systems,
+            ..
+        } = self;
+
+        tick_systems.tick(&world, step, io_state, render_ctx, debug_ctx);
+
+        States::Play(Play {
+            world,
+            tick_systems,
+            ..self
+        })
+    }
 }
 
 impl State for Play {
@@ -68,8 +100,16 @@ impl State for Play {
         render_ctx: &mut RenderContext,
         debug_ctx: &mut DebugContext,
     ) -> Self::Wrapper {
-        if io_state.pressed(0x19) {
-            render_ctx.window.set_cursor_visible(!self.paused);
+        let pause = self
+            .world
+            .resources()
+            .read::<InputMap>()
+            .map_or(false, |input_map| input_map.pressed("pause", io_state));
+
+        if pause {
+            if let Some(window) = render_ctx.window {
+                window.set_cursor_visible(!self.paused);
+            }
             return States::Play(Play {
                 paused: !self.paused,
                 ..self
@@ -84,9 +124,9 @@ impl State for Play {
             world, mut systems, ..
         } = self;
 
-        for system in &mut systems {
-            system.dispatch(&world, delta, io_state, render_ctx, debug_ctx);
-        }
+        systems.dispatch(&world, delta, io_state, render_ctx, debug_ctx);
+
+        let world = world.clear_events().apply_commands();
 
         States::Play(Play {
             world,
@@ -96,9 +136,7 @@ impl State for Play {
     }
 
     fn redraw(&self, render_ctx: &mut RenderContext, _debug_ctx: &mut DebugContext) {
-        for system in &self.systems {
-            system.render(render_ctx);
-        }
+        self.systems.render(render_ctx);
     }
 }
 
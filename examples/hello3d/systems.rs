@@ -3,34 +3,35 @@
 use crate::world::MyWorld;
 use imgui::{im_str, Window};
 use mela::debug::DebugContext;
-use mela::ecs::component::{OrbitCamera, PhysicsBody, Transform};
+use mela::ecs::component::{Forces, OrbitCamera, Transform, Velocity};
 use mela::ecs::system::physics::PhysicsWorld;
 use mela::ecs::system::{Read, Write};
+use mela::ecs::ReadRes;
 use mela::ecs::System;
 use mela::game::IoState;
 use mela::gfx::RenderContext;
-use mela::nphysics3d::object::{Body, RigidBody};
+use mela::input::InputMap;
 use nalgebra::{Isometry3, Rotation3, Vector3};
 use ncollide3d::pipeline::CollisionGroups;
 use ncollide3d::query::Ray;
-use nphysics3d::algebra::{Force3, ForceType};
-use std::ops::DerefMut;
-use std::rc::Rc;
-use std::sync::RwLock;
+use nphysics3d::algebra::Force3;
 use std::time::Duration;
 
-pub struct InputSystem {
-    physics_world: Rc<RwLock<PhysicsWorld<f32>>>,
-}
+pub struct InputSystem;
 
 impl InputSystem {
-    pub fn new(physics_world: Rc<RwLock<PhysicsWorld<f32>>>) -> InputSystem {
-        InputSystem { physics_world }
+    pub fn new() -> InputSystem {
+        InputSystem
     }
 }
 
 impl System<MyWorld> for InputSystem {
-    type SystemData<'a> = (Write<'a, OrbitCamera>, Read<'a, PhysicsBody<f32>>);
+    type SystemData<'a> = (
+        Write<'a, OrbitCamera>,
+        Read<'a, Velocity<f32>>,
+        Write<'a, Forces<f32>>,
+        ReadRes<'a, InputMap>,
+    );
 
     fn name(&self) -> &'static str {
         "InputSystem"
@@ -38,7 +39,7 @@ impl System<MyWorld> for InputSystem {
 
     fn update<'f>(
         &mut self,
-        (mut camera_writer, body_reader): Self::SystemData<'f>,
+        (mut camera_writer, velocity_reader, mut forces_writer, input_map): Self::SystemData<'f>,
         delta: Duration,
         io_state: &IoState,
         _render_ctx: &mut RenderContext,
@@ -46,31 +47,22 @@ impl System<MyWorld> for InputSystem {
     ) -> () {
         // move camera
         let rotation_speed = std::f32::consts::PI * delta.as_secs_f32();
-        let (entity, camera) = camera_writer.iter_mut().next().expect("no camera");
-        let body_component = body_reader.fetch(entity).unwrap();
-
-        if let Some(body_handle) = body_component.handle {
-            let mut physics_world = self.physics_world.write().unwrap();
-            let body: &mut RigidBody<f32> = physics_world
-                .bodies
-                .get_mut(body_handle)
-                .unwrap()
-                .downcast_mut()
-                .unwrap();
-
-            Window::new(im_str!("Ball speed")).build(&debug_ctx.ui, || {
-                debug_ctx
-                    .ui
-                    .text(im_str!("Ball speed: {:?}", body.velocity().linear));
-            });
+        let (entity, mut camera) = camera_writer.iter_mut().next().expect("no camera");
+
+        if let Some(velocity) = velocity_reader.fetch(entity) {
+            if let Some(ui) = &debug_ctx.ui {
+                Window::new(im_str!("Ball speed")).build(ui, || {
+                    ui.text(im_str!("Ball speed: {:?}", velocity.linear));
+                });
+            }
 
             let (roll, pitch, yaw) = camera.rotation.euler_angles();
-            let speed = body.velocity().linear.norm();
+            let speed = velocity.linear.norm();
             if speed > 0.01 {
                 // camera direction = ball direction
                 let forward = Vector3::y();
                 let target_yaw =
-                    Rotation3::rotation_between(&forward, &body.velocity().linear.normalize())
+                    Rotation3::rotation_between(&forward, &velocity.linear.normalize())
                         .and_then(|r| Some(r.euler_angles().2))
                         .unwrap_or(0.);
 
@@ -84,21 +76,11 @@ impl System<MyWorld> for InputSystem {
                 camera.set_rotation(new_rotation);
             } else {
                 // player control
-                let pitch_delta = if io_state.is_down(0x11) {
-                    -rotation_speed
-                } else if io_state.is_down(0x1f) {
-                    rotation_speed
-                } else {
-                    0.
-                };
-
-                let yaw_delta = if io_state.is_down(0x1e) {
-                    -rotation_speed
-                } else if io_state.is_down(0x20) {
-                    rotation_speed
-                } else {
-                    0.
-                };
+                // mouse motion is already per frame, and in radians after scaling
+                let pitch_delta = input_map.axis("pitch", io_state) * rotation_speed
+                    + input_map.axis("look_pitch", io_state);
+                let yaw_delta = input_map.axis("yaw", io_state) * rotation_speed
+                    + input_map.axis("look_yaw", io_state);
 
                 camera.set_rotation(Rotation3::from_euler_angles(
                     roll + pitch_delta,
@@ -108,33 +90,33 @@ impl System<MyWorld> for InputSystem {
             }
 
             // hit ball
-            if io_state.pressed(0x39) {
+            if input_map.pressed("hit", io_state) {
                 println!("pushing ball");
                 let (_, _, yaw) = camera.rotation.euler_angles();
                 let direction = Rotation3::new(Vector3::z() * yaw).transform_vector(&Vector3::y());
-                body.apply_force(
-                    0,
-                    &Force3::new(direction * 0.136, nalgebra::zero()),
-                    ForceType::Impulse,
-                    true,
-                );
+                forces_writer
+                    .fetch_mut(entity)
+                    .unwrap()
+                    .add_impulse(Force3::new(direction * 0.136, nalgebra::zero()));
             }
         }
     }
 }
 
-pub struct CameraUnclipper {
-    physics_world: Rc<RwLock<PhysicsWorld<f32>>>,
-}
+pub struct CameraUnclipper;
 
 impl CameraUnclipper {
-    pub fn new(physics_world: Rc<RwLock<PhysicsWorld<f32>>>) -> CameraUnclipper {
-        CameraUnclipper { physics_world }
+    pub fn new() -> CameraUnclipper {
+        CameraUnclipper
     }
 }
 
 impl System<MyWorld> for CameraUnclipper {
-    type SystemData<'a> = (Write<'a, OrbitCamera>, Read<'a, Transform<f32>>);
+    type SystemData<'a> = (
+        ReadRes<'a, PhysicsWorld<f32>>,
+        Write<'a, OrbitCamera>,
+        Read<'a, Transform<f32>>,
+    );
 
     fn name(&self) -> &'static str {
         "CameraUnclipper"
@@ -142,7 +124,7 @@ impl System<MyWorld> for CameraUnclipper {
 
     fn update<'f>(
         &mut self,
-        (mut camera_writer, transform_reader): Self::SystemData<'f>,
+        (physics_world, mut camera_writer, transform_reader): Self::SystemData<'f>,
         _delta: Duration,
         _io_state: &IoState,
         _render_ctx: &mut RenderContext,
@@ -150,44 +132,15 @@ impl System<MyWorld> for CameraUnclipper {
     ) -> () {
         let collision_group = CollisionGroups::new().with_blacklist(&[1]);
 
-        let mut physics_world_guard = self.physics_world.write().unwrap();
-        let &mut PhysicsWorld {
-            ref mut geometrical_world,
-            ref colliders,
-            ..
-        } = physics_world_guard.deref_mut();
-
-        let (entity, camera) = camera_writer.iter_mut().next().unwrap();
+        let (entity, mut camera) = camera_writer.iter_mut().next().unwrap();
         let transform = transform_reader.fetch(entity).unwrap();
         let isometry: Isometry3<f32> = nalgebra::try_convert_ref(&transform.0).unwrap();
         let direction = camera.rotation.transform_vector(&(Vector3::y() * -1.));
 
         let ray = Ray::new(isometry.translation.vector.into(), direction);
 
-        let interferences = geometrical_world.interferences_with_ray(
-            colliders,
-            &ray,
-            camera.max_distance,
-            &collision_group,
-        );
-
-        let nearest_intersection = {
-            let mut nearest = camera.max_distance;
-            for (_, _, intersection) in interferences {
-                //println!("collider: {:?}", obj.body());
-
-                if intersection.toi < nearest {
-                    nearest = intersection.toi;
-                }
-            }
-
-            nearest
-        };
-
-        camera.distance = if nearest_intersection < camera.max_distance {
-            nearest_intersection
-        } else {
-            camera.max_distance
-        }
+        camera.distance = physics_world
+            .cast_ray(&ray, camera.max_distance, &collision_group)
+            .map_or(camera.max_distance, |hit| hit.toi);
     }
 }
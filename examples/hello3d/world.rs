@@ -1,97 +1,35 @@
 //! ECS world definition
 
-use mela::ecs::component::{LightComponent, MeshComponent, OrbitCamera, PhysicsBody, Transform};
-use mela::ecs::world::WorldStorage;
-use mela::ecs::{entity::EntityBuilder, world::World, Entity, VecStorage};
+use mela::ecs::component::{
+    Children, Forces, GlobalTransform, LightComponent, MeshComponent, MultibodyLink, OrbitCamera,
+    Parent, PhysicsBody, PreviousTransform, Transform, Velocity,
+};
+use mela::ecs::resource::Time;
+use mela::ecs::system::physics::CollisionEvent;
+use mela::ecs::world::DefaultWorld;
+use mela::game::IoState;
 use mela::gfx::DefaultMesh;
-
-use crate::components::MyComponents;
-
-#[derive(Default)]
-pub(crate) struct MyWorld {
-    next_entity_id: usize,
-    entities: Vec<Entity>,
-    components: MyComponents,
-}
-
-impl World for MyWorld {
-    fn entities(&self) -> &[Entity] {
-        &self.entities
-    }
-
-    fn add_entity(self) -> EntityBuilder<Self> {
-        let MyWorld {
-            next_entity_id,
-            mut entities,
-            ..
-        } = self;
-
-        let new_entity = next_entity_id.into();
-        entities.push(new_entity);
-
-        EntityBuilder::new(
-            new_entity,
-            MyWorld {
-                next_entity_id: next_entity_id + 1,
-                entities,
-                ..self
-            },
-        )
-    }
-
-    fn remove_entity(self, entity: Entity) -> Self {
-        let MyWorld { mut entities, .. } = self;
-
-        entities.retain(|e| *e != entity);
-
-        MyWorld { entities, ..self }
-    }
-
-    fn remove_dead(self) -> Self {
-        let MyWorld { mut entities, .. } = self;
-
-        entities.retain(|e| !e.is_dead());
-
-        MyWorld { entities, ..self }
-    }
-}
-
-impl WorldStorage<PhysicsBody<f32>> for MyWorld {
-    type Storage = VecStorage<PhysicsBody<f32>>;
-
-    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
-        &self.components.physics_bodies
-    }
-}
-
-impl WorldStorage<Transform<f32>> for MyWorld {
-    type Storage = VecStorage<Transform<f32>>;
-
-    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
-        &self.components.transformations
-    }
-}
-
-impl WorldStorage<MeshComponent<DefaultMesh>> for MyWorld {
-    type Storage = VecStorage<MeshComponent<DefaultMesh>>;
-
-    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
-        &self.components.meshes
-    }
-}
-
-impl WorldStorage<LightComponent> for MyWorld {
-    type Storage = VecStorage<LightComponent>;
-
-    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
-        &self.components.lights
-    }
-}
-
-impl WorldStorage<OrbitCamera> for MyWorld {
-    type Storage = VecStorage<OrbitCamera>;
-
-    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
-        &self.components.cameras
-    }
+use mela::input::InputMap;
+
+pub(crate) type MyWorld = DefaultWorld;
+
+/// creates a new world with all the components and resources this example uses
+pub(crate) fn new_world() -> MyWorld {
+    DefaultWorld::new()
+        .with_resource(Time::default())
+        .with_resource(IoState::default())
+        .with_resource(InputMap::load("input.json").expect("failed to load input.json"))
+        .with_event_channel::<CollisionEvent>()
+        .register::<Transform<f32>>()
+        .register::<PreviousTransform<f32>>()
+        .register::<GlobalTransform<f32>>()
+        .register::<Parent>()
+        .register::<Children>()
+        .register::<PhysicsBody<f32>>()
+        .register::<MultibodyLink<f32>>()
+        .register::<Velocity<f32>>()
+        .register::<Forces<f32>>()
+        .register::<MeshComponent<DefaultMesh>>()
+        .register::<LightComponent>()
+        .register::<OrbitCamera>()
 }
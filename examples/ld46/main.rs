@@ -7,7 +7,7 @@ use mela::game::{IoState, Playable};
 use mela::gfx::RenderContext;
 use mela::state::State;
 use std::time::Duration;
-use winit::event::{ElementState, Event, WindowEvent};
+use winit::event::{Event, WindowEvent};
 use winit::event_loop::ControlFlow;
 
 mod components;
@@ -17,14 +17,12 @@ mod world;
 
 struct Ld46Game {
     state: States,
-    io_state: IoState,
 }
 
 impl Ld46Game {
     pub fn new() -> Ld46Game {
         Ld46Game {
             state: States::new(),
-            io_state: IoState::default(),
         }
     }
 }
@@ -33,22 +31,15 @@ impl Playable for Ld46Game {
     fn update(
         self,
         delta: Duration,
+        io_state: &IoState,
         render_ctx: &mut RenderContext,
         debug_ctx: &mut DebugContext,
     ) -> Self {
-        let Ld46Game {
-            state,
-            mut io_state,
-        } = self;
+        let Ld46Game { state } = self;
 
-        io_state.update();
+        let new_state = state.update(delta, io_state, render_ctx, debug_ctx);
 
-        let new_state = state.update(delta, &io_state, render_ctx, debug_ctx);
-
-        Ld46Game {
-            state: new_state,
-            io_state,
-        }
+        Ld46Game { state: new_state }
     }
 
     fn push_event<T>(&mut self, event: &Event<T>) -> Option<ControlFlow> {
@@ -57,19 +48,6 @@ impl Playable for Ld46Game {
                 event: WindowEvent::CloseRequested,
                 ..
             } => Some(ControlFlow::Exit),
-            Event::WindowEvent {
-                event:
-                    WindowEvent::KeyboardInput {
-                        device_id,
-                        input,
-                        is_synthetic,
-                    },
-                ..
-            } => {
-                self.io_state
-                    .set_key(input.scancode, input.state == ElementState::Pressed);
-                None
-            }
             _ => None,
         }
     }
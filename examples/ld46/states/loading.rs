@@ -4,6 +4,7 @@ use mela::asset::{Asset, AssetState, Bytes};
 use mela::debug::{DebugContext, DebugDrawable};
 use mela::game::IoState;
 use mela::gfx::{RenderContext, Texture};
+use mela::input::InputMap;
 use mela::state::State;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -13,9 +14,9 @@ pub struct RequiredGameAssets {
     textures: Vec<(&'static str, Box<dyn Asset<Texture>>)>,
 }
 
-#[derive(Default)]
 pub struct GameAssets {
     pub textures: HashMap<String, Texture>,
+    pub input_map: InputMap,
 }
 
 pub struct Loading {
@@ -42,7 +43,11 @@ impl Loading {
                     ),
                 ],
             },
-            loaded: GameAssets::default(),
+            loaded: GameAssets {
+                textures: HashMap::new(),
+                input_map: InputMap::load("assets/input/ld46.json")
+                    .expect("failed to load input config"),
+            },
         }
     }
 }
@@ -10,7 +10,7 @@ use mela::asset::tilemap::layers::ObjectLayer;
 use mela::asset::tilemap::{Orthogonal, Tilemap};
 use mela::debug::{DebugContext, DebugDrawable};
 use mela::ecs::world::World;
-use mela::ecs::{ComponentStorage, ReadAccess, System};
+use mela::ecs::{ComponentStorage, Dispatcher, ReadAccess};
 use mela::game::IoState;
 use mela::gfx::light::{Light, Lights};
 use mela::gfx::primitives::{Quad, Vertex, MVP};
@@ -23,7 +23,7 @@ pub struct Play {
     assets: GameAssets,
     tilemap: Tilemap<Orthogonal, MyWorld>,
     world: MyWorld,
-    systems: Vec<Box<dyn System<MyWorld>>>,
+    systems: Dispatcher<MyWorld>,
     render_targets: (wgpu::Texture, wgpu::Texture, wgpu::Texture),
     sampler: wgpu::Sampler,
     ui_batch: Spritebatch,
@@ -91,71 +91,80 @@ impl Play {
             }
         }
 
-        let systems: Vec<Box<dyn System<MyWorld>>> = vec![
-            Box::new(SpriteSystem::new(
+        world.resources_mut().insert(assets.input_map.clone());
+
+        let systems = Dispatcher::new()
+            .with(SpriteSystem::new(
                 assets.textures.get("spritesheet").unwrap().clone(),
                 assets.textures.get("material").unwrap().clone(),
-            )),
-            Box::new(PlayerMvmtSystem {}),
-            Box::new(MoveToPlayer {}),
-            Box::new(LogBurner {}),
-            Box::new(FireSystem {}),
-            Box::new(PlayerKiller {}),
-        ];
+            ))
+            .with(PlayerMvmtSystem {})
+            .with(MoveToPlayer {})
+            .with(LogBurner {})
+            .with(FireSystem {})
+            .with(PlayerKiller {});
+
+        let sampler = render_ctx
+            .device()
+            .create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: Default::default(),
+                address_mode_v: Default::default(),
+                address_mode_w: Default::default(),
+                mag_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: Default::default(),
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare_function: wgpu::CompareFunction::Never,
+            });
 
-        let sampler = render_ctx.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: Default::default(),
-            address_mode_v: Default::default(),
-            address_mode_w: Default::default(),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: Default::default(),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare_function: wgpu::CompareFunction::Never,
-        });
-
-        let color_target = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: 768,
-                height: 576,
-                depth: 1,
-            },
-            array_layer_count: 1,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-        });
-
-        let material_target = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: 768,
-                height: 576,
-                depth: 1,
-            },
-            array_layer_count: 1,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-        });
-
-        let final_target = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: 768,
-                height: 576,
-                depth: 1,
-            },
-            array_layer_count: 1,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-        });
+        let color_target = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: 768,
+                    height: 576,
+                    depth: 1,
+                },
+                array_layer_count: 1,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            });
+
+        let material_target = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: 768,
+                    height: 576,
+                    depth: 1,
+                },
+                array_layer_count: 1,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            });
+
+        let final_target = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: 768,
+                    height: 576,
+                    depth: 1,
+                },
+                array_layer_count: 1,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            });
 
         let ui_batch = Spritebatch::new(assets.textures.get("spritesheet").unwrap().clone());
 
@@ -198,11 +207,10 @@ impl State for Play {
             layer.update(render_ctx);
         }
 
-        for system in &mut systems {
-            world = system.update(delta, world, io_state, render_ctx);
-        }
+        systems.dispatch(&world, delta, io_state, render_ctx, debug_ctx);
 
-        world = world.remove_dead();
+        // removals are applied after the events are cleared, so systems see them next frame
+        world = world.clear_events().apply_commands();
 
         let enemies_left = world.components.enemies.read().iter().count();
         let players_left = world.components.players.read().iter().count();
@@ -262,9 +270,8 @@ impl State for Play {
                 layer.draw_to(&projection, &[&render_views.0, &render_views.1], render_ctx);
             }
 
-            for system in &self.systems {
-                system.draw_to(&[&render_views.0, &render_views.1], render_ctx);
-            }
+            self.systems
+                .render_to(&[&render_views.0, &render_views.1], render_ctx);
         }
 
         // TODO: move to system
@@ -331,14 +338,14 @@ impl State for Play {
         ]);
 
         let light_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM)
             .fill_from_slice(&[lights]);
 
         let bind_group = render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &render_ctx.pipelines.raycast2d.1,
+                layout: &render_ctx.pipelines().raycast2d.1,
                 bindings: &[
                     wgpu::Binding {
                         binding: 0,
@@ -366,18 +373,19 @@ impl State for Play {
         let (vertices, indices) = quad.vertices_and_indices2d([-1., -1.], [1., 1., 1., 1.]);
 
         let vertex_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(vertices.len(), wgpu::BufferUsage::VERTEX)
             .fill_from_slice(&vertices);
 
         let index_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(indices.len(), wgpu::BufferUsage::INDEX)
             .fill_from_slice(&indices);
 
         {
+            let pipelines = render_ctx.pipelines();
             let mut rpass = render_ctx
-                .encoder
+                .encoder()
                 .begin_render_pass(&wgpu::RenderPassDescriptor {
                     color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
                         attachment: &render_views.2,
@@ -389,7 +397,7 @@ impl State for Play {
                     depth_stencil_attachment: None,
                 });
 
-            rpass.set_pipeline(&render_ctx.pipelines.raycast2d.0);
+            rpass.set_pipeline(&pipelines.raycast2d.0);
             rpass.set_bind_group(0, &bind_group, &[]);
             rpass.set_index_buffer(&index_buf, 0);
             rpass.set_vertex_buffers(0, &[(&vertex_buf, 0)]);
@@ -400,14 +408,14 @@ impl State for Play {
             let transformations: [[f32; 4]; 4] = nalgebra::Matrix4::identity().into();
 
             let transforms_buffer = render_ctx
-                .device
+                .device()
                 .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM)
                 .fill_from_slice(&[transformations]);
 
             let bind_group = render_ctx
-                .device
+                .device()
                 .create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &render_ctx.pipelines.pixel.1,
+                    layout: &render_ctx.pipelines().pixel.1,
                     bindings: &[
                         wgpu::Binding {
                             binding: 0,
@@ -428,19 +436,22 @@ impl State for Play {
                 });
 
             {
-                let mut rpass = render_ctx
-                    .encoder
-                    .begin_render_pass(&wgpu::RenderPassDescriptor {
-                        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                            attachment: &render_ctx.frame,
-                            resolve_target: None,
-                            load_op: wgpu::LoadOp::Clear,
-                            store_op: wgpu::StoreOp::Store,
-                            clear_color: wgpu::Color::BLACK,
-                        }],
-                        depth_stencil_attachment: None,
-                    });
-                rpass.set_pipeline(&render_ctx.pipelines.pixel.0);
+                let frame = render_ctx.frame();
+                let pipelines = render_ctx.pipelines();
+                let mut rpass =
+                    render_ctx
+                        .encoder()
+                        .begin_render_pass(&wgpu::RenderPassDescriptor {
+                            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                                attachment: frame,
+                                resolve_target: None,
+                                load_op: wgpu::LoadOp::Clear,
+                                store_op: wgpu::StoreOp::Store,
+                                clear_color: wgpu::Color::BLACK,
+                            }],
+                            depth_stencil_attachment: None,
+                        });
+                rpass.set_pipeline(&pipelines.pixel.0);
                 rpass.set_bind_group(0, &bind_group, &[]);
                 rpass.set_index_buffer(&index_buf, 0);
                 rpass.set_vertex_buffers(0, &[(&vertex_buf, 0)]);
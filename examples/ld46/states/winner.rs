@@ -32,17 +32,19 @@ impl Winner {
 
         let background_view = background.create_default_view();
 
-        let sampler = render_ctx.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: Default::default(),
-            address_mode_v: Default::default(),
-            address_mode_w: Default::default(),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: Default::default(),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare_function: wgpu::CompareFunction::Never,
-        });
+        let sampler = render_ctx
+            .device()
+            .create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: Default::default(),
+                address_mode_v: Default::default(),
+                address_mode_w: Default::default(),
+                mag_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: Default::default(),
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare_function: wgpu::CompareFunction::Never,
+            });
 
         let transformations: [[f32; 4]; 4] = nalgebra::Matrix4::new_nonuniform_scaling(
             &nalgebra::Vector3::new(1. / 768., 1. / 576., 1.),
@@ -52,14 +54,14 @@ impl Winner {
         .into();
 
         let transforms_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM)
             .fill_from_slice(&[transformations]);
 
         let bind_group = render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &render_ctx.pipelines.pixel.1,
+                layout: &render_ctx.pipelines().pixel.1,
                 bindings: &[
                     wgpu::Binding {
                         binding: 0,
@@ -83,12 +85,12 @@ impl Winner {
         let (vertices, indices) = quad.vertices_and_indices2d([0., 0.], [1., 1., 1., 1.]);
 
         let vertex_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(vertices.len(), wgpu::BufferUsage::VERTEX)
             .fill_from_slice(&vertices);
 
         let index_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(indices.len(), wgpu::BufferUsage::INDEX)
             .fill_from_slice(&indices);
 
@@ -121,11 +123,13 @@ impl State for Winner {
     }
 
     fn redraw(&self, render_ctx: &mut RenderContext, _debug_ctx: &mut DebugContext) {
+        let frame = render_ctx.frame();
+        let pipelines = render_ctx.pipelines();
         let mut rpass = render_ctx
-            .encoder
+            .encoder()
             .begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &render_ctx.frame,
+                    attachment: frame,
                     resolve_target: None,
                     load_op: wgpu::LoadOp::Load,
                     store_op: wgpu::StoreOp::Store,
@@ -134,7 +138,7 @@ impl State for Winner {
                 depth_stencil_attachment: None,
             });
 
-        rpass.set_pipeline(&render_ctx.pipelines.pixel.0);
+        rpass.set_pipeline(&pipelines.pixel.0);
         rpass.set_bind_group(0, &self.bind_group, &[]);
         rpass.set_index_buffer(&self.buffers.1, 0);
         rpass.set_vertex_buffers(0, &[(&self.buffers.0, 0)]);
@@ -167,17 +171,19 @@ impl Loser {
 
         let background_view = background.create_default_view();
 
-        let sampler = render_ctx.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: Default::default(),
-            address_mode_v: Default::default(),
-            address_mode_w: Default::default(),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: Default::default(),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare_function: wgpu::CompareFunction::Never,
-        });
+        let sampler = render_ctx
+            .device()
+            .create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: Default::default(),
+                address_mode_v: Default::default(),
+                address_mode_w: Default::default(),
+                mag_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: Default::default(),
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare_function: wgpu::CompareFunction::Never,
+            });
 
         let transformations: [[f32; 4]; 4] = nalgebra::Matrix4::new_nonuniform_scaling(
             &nalgebra::Vector3::new(1. / 768., 1. / 576., 1.),
@@ -187,14 +193,14 @@ impl Loser {
         .into();
 
         let transforms_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM)
             .fill_from_slice(&[transformations]);
 
         let bind_group = render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &render_ctx.pipelines.pixel.1,
+                layout: &render_ctx.pipelines().pixel.1,
                 bindings: &[
                     wgpu::Binding {
                         binding: 0,
@@ -218,12 +224,12 @@ impl Loser {
         let (vertices, indices) = quad.vertices_and_indices2d([0., 0.], [1., 1., 1., 1.]);
 
         let vertex_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(vertices.len(), wgpu::BufferUsage::VERTEX)
             .fill_from_slice(&vertices);
 
         let index_buf = render_ctx
-            .device
+            .device()
             .create_buffer_mapped(indices.len(), wgpu::BufferUsage::INDEX)
             .fill_from_slice(&indices);
 
@@ -253,7 +259,7 @@ impl State for Loser {
         render_ctx: &mut RenderContext,
         _debug_ctx: &mut DebugContext,
     ) -> States {
-        if io_state.pressed(57) {
+        if self.assets.input_map.pressed("restart", io_state) {
             let Loser { assets, .. } = self;
 
             States::Play(Play::new(assets, render_ctx))
@@ -263,11 +269,13 @@ impl State for Loser {
     }
 
     fn redraw(&self, render_ctx: &mut RenderContext, _debug_ctx: &mut DebugContext) {
+        let frame = render_ctx.frame();
+        let pipelines = render_ctx.pipelines();
         let mut rpass = render_ctx
-            .encoder
+            .encoder()
             .begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &render_ctx.frame,
+                    attachment: frame,
                     resolve_target: None,
                     load_op: wgpu::LoadOp::Load,
                     store_op: wgpu::StoreOp::Store,
@@ -276,7 +284,7 @@ impl State for Loser {
                 depth_stencil_attachment: None,
             });
 
-        rpass.set_pipeline(&render_ctx.pipelines.pixel.0);
+        rpass.set_pipeline(&pipelines.pixel.0);
         rpass.set_bind_group(0, &self.bind_group, &[]);
         rpass.set_index_buffer(&self.buffers.1, 0);
         rpass.set_vertex_buffers(0, &[(&self.buffers.0, 0)]);
@@ -1,13 +1,15 @@
 //! my systems :)
 
-use crate::components::{Fire, LightC, Player, Position, Sprite};
+use crate::components::{Enemy, Fire, LightC, Player, Position, Sprite};
 use crate::world::MyWorld;
-use mela::ecs::world::WorldStorage;
-use mela::ecs::{ComponentStorage, ReadAccess, System, WriteAccess};
+use mela::debug::DebugContext;
+use mela::ecs::system::{Read, Write};
+use mela::ecs::{Commands, ReadRes, System};
 use mela::game::IoState;
 use mela::gfx::light::Light;
 use mela::gfx::primitives::Quad;
 use mela::gfx::{RenderContext, Spritebatch, Texture};
+use mela::input::InputMap;
 use nalgebra::Point2;
 use std::cmp::Ordering;
 use std::time::Duration;
@@ -27,43 +29,41 @@ impl SpriteSystem {
 }
 
 impl System<MyWorld> for SpriteSystem {
+    type SystemData<'a> = (Read<'a, Sprite>, Read<'a, Position>);
+
     fn name(&self) -> &'static str {
         "SpriteSystem"
     }
 
     fn update<'f>(
         &mut self,
-        delta: Duration,
-        world: MyWorld,
+        (sprite_reader, position_reader): Self::SystemData<'f>,
+        _delta: Duration,
         _io_state: &IoState,
         render_ctx: &mut RenderContext,
-    ) -> MyWorld {
-        let MyWorld { components, .. } = world;
-
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
         self.spritebatch.clear();
         self.material_batch.clear();
 
         let default_pos = Position(Point2::new(0., 0.));
 
         // collect all sprites so we can sort them
-        let mut sprites: Vec<(Sprite, nalgebra::Point2<f32>)> = components
-            .sprites
-            .read()
+        let mut sprites: Vec<(Sprite, nalgebra::Point2<f32>)> = sprite_reader
             .iter()
             .map(|(e, s)| {
-                let position = components
-                    .positions
-                    .read()
-                    .fetch(e)
-                    .unwrap_or(&default_pos)
-                    .0
-                    .clone();
+                let position = position_reader.fetch(e).unwrap_or(&default_pos).0.clone();
 
                 (s.clone(), position)
             })
             .collect();
 
-        sprites.sort_by(|a, b| a.1.coords.y.partial_cmp(&b.1.coords.y).unwrap());
+        sprites.sort_by(|a, b| {
+            a.1.coords
+                .y
+                .partial_cmp(&b.1.coords.y)
+                .unwrap_or(Ordering::Equal)
+        });
 
         for (sprite, position) in &sprites {
             self.spritebatch
@@ -74,11 +74,6 @@ impl System<MyWorld> for SpriteSystem {
 
         self.spritebatch.update(render_ctx);
         self.material_batch.update(render_ctx);
-
-        MyWorld {
-            components,
-            ..world
-        }
     }
 
     fn draw(&self, render_ctx: &mut RenderContext) {
@@ -97,49 +92,47 @@ impl System<MyWorld> for SpriteSystem {
 pub struct PlayerMvmtSystem {}
 
 impl System<MyWorld> for PlayerMvmtSystem {
+    type SystemData<'a> = (
+        Write<'a, Player>,
+        Write<'a, Position>,
+        ReadRes<'a, InputMap>,
+    );
+
     fn name(&self) -> &'static str {
         "PlayerMvmtSystem"
     }
 
     fn update<'f>(
         &mut self,
+        (mut player_writer, mut position_writer, input_map): Self::SystemData<'f>,
         delta: Duration,
-        world: MyWorld,
         io_state: &IoState,
-        render_ctx: &mut RenderContext,
-    ) -> MyWorld {
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
         let speed = (50., 50.);
 
-        let MyWorld { mut components, .. } = world;
-
-        let (entity, player_component) = components
-            .players
-            .read()
+        let (entity, player_component) = player_writer
             .iter()
             .next()
             .and_then(|(entity, pc)| Some((entity, pc.clone())))
             .expect("no player?");
 
-        let position = components
-            .positions
-            .read()
+        let position = position_writer
             .fetch(entity)
             .expect("player has no position?")
             .0
             .clone();
 
-        let mut movement_vector = nalgebra::Vector2::new(0., 0.);
+        let (move_x, move_y) = (
+            input_map.axis("move_x", io_state),
+            input_map.axis("move_y", io_state),
+        );
 
-        if io_state.is_down(32) {
-            movement_vector.x = speed.0 * delta.as_secs_f32();
-        } else if io_state.is_down(30) {
-            movement_vector.x = -speed.0 * delta.as_secs_f32();
-        }
-        if io_state.is_down(31) {
-            movement_vector.y = speed.1 * delta.as_secs_f32();
-        } else if io_state.is_down(17) {
-            movement_vector.y = -speed.1 * delta.as_secs_f32();
-        }
+        let mut movement_vector = nalgebra::Vector2::new(
+            move_x * speed.0 * delta.as_secs_f32(),
+            move_y * speed.1 * delta.as_secs_f32(),
+        );
 
         let direction = if movement_vector.norm() > 0. {
             movement_vector.normalize_mut();
@@ -164,7 +157,7 @@ impl System<MyWorld> for PlayerMvmtSystem {
             ..player_component
         };
 
-        components.players.write().set(entity, new_player_component);
+        player_writer.set(entity, new_player_component);
 
         let mut new_pos = position + movement_vector;
         if new_pos.coords.x < 0. {
@@ -180,65 +173,63 @@ impl System<MyWorld> for PlayerMvmtSystem {
             new_pos.coords.y = 576.
         }
 
-        components.positions.write().set(entity, Position(new_pos));
-
-        MyWorld {
-            components,
-            ..world
-        }
+        position_writer.set(entity, Position(new_pos));
     }
 }
 
 pub struct MoveToPlayer {}
 
 impl System<MyWorld> for MoveToPlayer {
+    type SystemData<'a> = (
+        Write<'a, Player>,
+        Read<'a, Enemy>,
+        Read<'a, Fire>,
+        Write<'a, Position>,
+        Write<'a, Sprite>,
+    );
+
     fn name(&self) -> &'static str {
         "MoveToPlayer"
     }
 
-    fn update(
+    fn update<'f>(
         &mut self,
+        (mut players, enemies, fires, mut positions, mut sprites): Self::SystemData<'f>,
         delta: Duration,
-        world: MyWorld,
-        io_state: &IoState,
-        render_ctx: &mut RenderContext,
-    ) -> MyWorld {
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
         let speed = 25.;
         let hurt_distance = 4.;
         let angry_quad = Quad::new(0., 448., 17., 27., 512., 512.);
         let idle_quad = Quad::new(32., 448., 17., 27., 512., 512.);
 
-        let MyWorld { mut components, .. } = world;
-
-        let (player_entity, player_component) = components
-            .players
-            .read()
+        let (player_entity, player_component) = players
             .iter()
             .next()
             .and_then(|(e, c)| Some((e, c.clone())))
             .expect("no player?");
 
-        let fires_left = components.fires.read().iter().count();
+        let fires_left = fires.iter().count();
         let aggro_range = if fires_left == 0 {
             10000.
         } else {
             400. - (fires_left as f32).min(6.) * 66.
         };
 
-        let player_position = components
-            .positions
-            .read()
+        let player_position = positions
             .fetch(player_entity)
             .expect("player has no position?")
             .0
             .clone();
 
-        for (enemy, _) in components.enemies.read().iter() {
-            let enemy_position = components.positions.read().fetch(enemy).unwrap().0.clone();
+        for (enemy, _) in enemies.iter() {
+            let enemy_position = positions.fetch(enemy).unwrap().0.clone();
             let pos_diff = &player_position - enemy_position;
 
             if pos_diff.norm() < hurt_distance && player_component.invulnerable_timer.is_none() {
-                components.players.write().set(
+                players.set(
                     player_entity,
                     Player {
                         health: player_component.health - 1,
@@ -250,15 +241,15 @@ impl System<MyWorld> for MoveToPlayer {
 
             if pos_diff.norm() < aggro_range {
                 let new_pos = &enemy_position + pos_diff.normalize() * speed * delta.as_secs_f32();
-                components.positions.write().set(enemy, Position(new_pos));
-                components.sprites.write().set(
+                positions.set(enemy, Position(new_pos));
+                sprites.set(
                     enemy,
                     Sprite {
                         quad: angry_quad.clone(),
                     },
                 );
             } else {
-                components.sprites.write().set(
+                sprites.set(
                     enemy,
                     Sprite {
                         quad: idle_quad.clone(),
@@ -266,38 +257,38 @@ impl System<MyWorld> for MoveToPlayer {
                 );
             }
         }
-
-        MyWorld {
-            components,
-            ..world
-        }
     }
 }
 
 pub struct LogBurner {}
 
 impl System<MyWorld> for LogBurner {
+    type SystemData<'a> = (
+        Write<'a, Enemy>,
+        Write<'a, Fire>,
+        Read<'a, Position>,
+        Write<'a, Sprite>,
+    );
+
     fn name(&self) -> &'static str {
         "LogBurner"
     }
 
-    fn update(
+    fn update<'f>(
         &mut self,
-        delta: Duration,
-        world: MyWorld,
-        io_state: &IoState,
-        render_ctx: &mut RenderContext,
-    ) -> MyWorld {
+        (mut enemy_writer, mut fire_writer, position_reader, mut sprite_writer): Self::SystemData<
+            'f,
+        >,
+        _delta: Duration,
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
         let idle_quad = Quad::new(32., 448., 17., 27., 512., 512.);
 
-        let MyWorld { mut components, .. } = world;
-
         // check that campfire is still burning
-        if components.fires.read().iter().next().is_none() {
-            return MyWorld {
-                components,
-                ..world
-            };
+        if fire_writer.iter().next().is_none() {
+            return;
         }
 
         let campfire_pos = nalgebra::Point2::new(380., 226.);
@@ -308,9 +299,8 @@ impl System<MyWorld> for LogBurner {
         // TODO: manual offset bad.
         let enemy_pos_offset = nalgebra::Vector2::new(8., 16.);
 
-        for (enemy, _) in components.enemies.read().iter() {
-            let enemy_position =
-                &components.positions.read().fetch(enemy).unwrap().0 + enemy_pos_offset;
+        for (enemy, _) in enemy_writer.iter() {
+            let enemy_position = &position_reader.fetch(enemy).unwrap().0 + enemy_pos_offset;
             let pos_diff = &campfire_pos - enemy_position;
 
             if pos_diff.norm() < campfire_radius {
@@ -320,72 +310,61 @@ impl System<MyWorld> for LogBurner {
 
         for enemy in to_log.into_iter() {
             // remove enemy components from log
-            components.enemies.write().unset(enemy);
-            components.fires.write().set(
+            enemy_writer.unset(enemy);
+            fire_writer.set(
                 enemy,
                 Fire {
                     time_left: Duration::new(30, 0),
                 },
             );
-            components.sprites.write().set(
+            sprite_writer.set(
                 enemy,
                 Sprite {
                     quad: idle_quad.clone(),
                 },
             );
         }
-
-        MyWorld {
-            components,
-            ..world
-        }
     }
 }
 
 pub struct FireSystem {}
 
 impl System<MyWorld> for FireSystem {
+    type SystemData<'a> = (
+        Write<'a, Fire>,
+        Read<'a, Position>,
+        Write<'a, Sprite>,
+        Write<'a, LightC>,
+        Commands<'a, MyWorld>,
+    );
+
     fn name(&self) -> &'static str {
         "FireSystem"
     }
 
-    fn update(
+    fn update<'f>(
         &mut self,
+        (mut fires, positions, mut sprites, mut lights, commands): Self::SystemData<'f>,
         delta: Duration,
-        world: MyWorld,
-        io_state: &IoState,
-        render_ctx: &mut RenderContext,
-    ) -> MyWorld {
-        let MyWorld {
-            mut components,
-            mut entities,
-            ..
-        } = world;
-
-        let mut dead_fires = Vec::new();
-
-        for (entity, fire) in components.fires.write().iter_mut() {
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
+        for (entity, mut fire) in fires.iter_mut() {
             if fire.time_left <= delta {
-                entities
-                    .iter_mut()
-                    .find(|e| **e == entity)
-                    .and_then(|e| {
-                        *e = e.kill();
-                        Some(())
-                    })
-                    .expect("could not find entity");
-                dead_fires.push(entity);
+                // burnt out fires are removed with all their components
+                commands.despawn(entity);
                 continue;
             }
 
             fire.time_left -= delta;
 
-            let pos = components.positions.read().fetch(entity).unwrap().0.clone();
+            let pos = positions.fetch(entity).unwrap().0.clone();
             let str = fire.strength();
             let quad = fire.quad();
 
-            components.sprites.write().set(entity, Sprite { quad });
-            components.lights.write().set(
+            sprites.set(entity, Sprite { quad });
+            lights.set(
                 entity,
                 LightC {
                     light: Light {
@@ -402,70 +381,31 @@ impl System<MyWorld> for FireSystem {
                 },
             )
         }
-
-        for entity in dead_fires.into_iter() {
-            components.fires.write().unset(entity);
-            components.sprites.write().unset(entity);
-            components.positions.write().unset(entity);
-            components.lights.write().unset(entity);
-        }
-
-        MyWorld {
-            components,
-            entities,
-            ..world
-        }
     }
 }
 
 pub struct PlayerKiller {}
 
 impl System<MyWorld> for PlayerKiller {
+    type SystemData<'a> = (Read<'a, Player>, Commands<'a, MyWorld>);
+
     fn name(&self) -> &'static str {
         "PlayerKiller"
     }
 
-    fn update(
+    fn update<'f>(
         &mut self,
-        delta: Duration,
-        world: MyWorld,
-        io_state: &IoState,
-        render_ctx: &mut RenderContext,
-    ) -> MyWorld {
-        let MyWorld {
-            mut components,
-            mut entities,
-            ..
-        } = world;
-
-        let (entity, pc) = components
-            .players
-            .read()
-            .iter()
-            .next()
-            .and_then(|(e, c)| Some((e, c.clone())))
-            .expect("no player :(");
+        (player_reader, commands): Self::SystemData<'f>,
+        _delta: Duration,
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
+        let (entity, pc) = player_reader.iter().next().expect("no player :(");
 
         if pc.health <= 0 {
             // player is dead :(
-            components.players.write().unset(entity);
-            components.sprites.write().unset(entity);
-            components.positions.write().unset(entity);
-
-            entities
-                .iter_mut()
-                .find(|e| **e == entity)
-                .and_then(|e| {
-                    *e = e.kill();
-                    Some(())
-                })
-                .expect("could not find player??");
-        }
-
-        MyWorld {
-            components,
-            entities,
-            ..world
+            commands.despawn(entity);
         }
     }
 }
@@ -1,21 +1,31 @@
 //! its my world :)
 
 use crate::components::{Enemy, Fire, Ld46Components, LightC, Player, Position, Sprite};
+use mela::ecs::command::CommandBuffer;
 use mela::ecs::entity::EntityBuilder;
 use mela::ecs::world::{World, WorldStorage};
-use mela::ecs::{Entity, VecStorage};
+use mela::ecs::{
+    ComponentStorage, Entity, EntityAllocator, Event, Resources, VecStorage, WriteAccess,
+};
 
 pub struct MyWorld {
-    pub next_entity_id: usize,
+    pub allocator: EntityAllocator,
     pub entities: Vec<Entity>,
+    pub events: Vec<Event>,
+    pub resources: Resources,
     pub components: Ld46Components,
 }
 
 impl MyWorld {
     pub fn new() -> MyWorld {
+        let mut resources = Resources::new();
+        resources.insert(CommandBuffer::<MyWorld>::new());
+
         MyWorld {
-            next_entity_id: 0,
+            allocator: EntityAllocator::new(),
             entities: Vec::new(),
+            events: Vec::new(),
+            resources,
             components: Ld46Components::default(),
         }
     }
@@ -28,109 +38,199 @@ impl World for MyWorld {
 
     fn add_entity(self) -> EntityBuilder<Self> {
         let MyWorld {
-            next_entity_id,
+            mut allocator,
             mut entities,
+            mut events,
             ..
         } = self;
 
-        let new_entity = next_entity_id.into();
+        let new_entity = allocator.allocate();
         entities.push(new_entity);
+        events.push(Event::EntityAdded(new_entity));
 
         EntityBuilder::new(
             new_entity,
             MyWorld {
-                next_entity_id: next_entity_id + 1,
+                allocator,
+                entities,
+                events,
+                ..self
+            },
+        )
+    }
+
+    fn reserve_entity(&self) -> Entity {
+        self.allocator.reserve()
+    }
+
+    fn add_reserved_entity(self, entity: Entity) -> EntityBuilder<Self> {
+        let MyWorld {
+            mut allocator,
+            mut entities,
+            mut events,
+            ..
+        } = self;
+
+        allocator.flush();
+        entities.push(entity);
+        events.push(Event::EntityAdded(entity));
+
+        EntityBuilder::new(
+            entity,
+            MyWorld {
+                allocator,
                 entities,
+                events,
                 ..self
             },
         )
     }
 
+    fn release_reserved_entity(self, entity: Entity) -> Self {
+        let MyWorld { mut allocator, .. } = self;
+
+        if !self.entities.iter().any(|e| e.is_same(entity)) {
+            allocator.free(entity);
+        }
+
+        MyWorld { allocator, ..self }
+    }
+
     fn remove_entity(self, entity: Entity) -> Self {
+        let MyWorld {
+            mut allocator,
+            mut entities,
+            mut events,
+            resources,
+            components,
+        } = self;
+
+        if allocator.free(entity) {
+            let entity = Entity::with_generation(entity.index(), entity.generation());
+
+            entities.retain(|e| !e.is_same(entity));
+            components.sprites.write().unset(entity);
+            components.positions.write().unset(entity);
+            components.players.write().unset(entity);
+            components.enemies.write().unset(entity);
+            components.lights.write().unset(entity);
+            components.fires.write().unset(entity);
+            events.push(Event::EntityRemoved(entity));
+        }
+
+        MyWorld {
+            allocator,
+            entities,
+            events,
+            resources,
+            components,
+        }
+    }
+
+    fn kill_entity(self, entity: Entity) -> Self {
         let MyWorld { mut entities, .. } = self;
 
-        entities.retain(|e| *e != entity);
+        if let Some(e) = entities.iter_mut().find(|e| e.is_same(entity)) {
+            *e = e.kill();
+        }
 
         MyWorld { entities, ..self }
     }
 
     fn remove_dead(self) -> Self {
-        let MyWorld { mut entities, .. } = self;
+        let dead: Vec<Entity> = self
+            .entities
+            .iter()
+            .filter(|e| e.is_dead())
+            .cloned()
+            .collect();
 
-        entities.retain(|e| !e.is_dead());
+        dead.into_iter()
+            .fold(self, |world, entity| world.remove_entity(entity))
+    }
 
-        MyWorld { entities, ..self }
+    fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    fn clear_events(self) -> Self {
+        let MyWorld {
+            mut events,
+            components,
+            ..
+        } = self;
+
+        events.clear();
+
+        components.sprites.changes().maintain();
+        components.positions.changes().maintain();
+        components.players.changes().maintain();
+        components.enemies.changes().maintain();
+        components.lights.changes().maintain();
+        components.fires.changes().maintain();
+
+        self.resources.maintain();
+
+        MyWorld {
+            events,
+            components,
+            ..self
+        }
+    }
+
+    fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
     }
 }
 
 impl WorldStorage<Sprite> for MyWorld {
     type Storage = VecStorage<Sprite>;
 
-    fn storage(&self) -> &Self::Storage {
+    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
         &self.components.sprites
     }
-
-    fn mut_storage(&mut self) -> &mut Self::Storage {
-        &mut self.components.sprites
-    }
 }
 
 impl WorldStorage<Position> for MyWorld {
     type Storage = VecStorage<Position>;
 
-    fn storage(&self) -> &Self::Storage {
+    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
         &self.components.positions
     }
-
-    fn mut_storage(&mut self) -> &mut Self::Storage {
-        &mut self.components.positions
-    }
 }
 
 impl WorldStorage<Player> for MyWorld {
     type Storage = VecStorage<Player>;
 
-    fn storage(&self) -> &Self::Storage {
+    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
         &self.components.players
     }
-
-    fn mut_storage(&mut self) -> &mut Self::Storage {
-        &mut self.components.players
-    }
 }
 
 impl WorldStorage<Enemy> for MyWorld {
     type Storage = VecStorage<Enemy>;
 
-    fn storage(&self) -> &Self::Storage {
+    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
         &self.components.enemies
     }
-
-    fn mut_storage(&mut self) -> &mut Self::Storage {
-        &mut self.components.enemies
-    }
 }
 
 impl WorldStorage<LightC> for MyWorld {
     type Storage = VecStorage<LightC>;
 
-    fn storage(&self) -> &Self::Storage {
+    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
         &self.components.lights
     }
-
-    fn mut_storage(&mut self) -> &mut Self::Storage {
-        &mut self.components.lights
-    }
 }
 
 impl WorldStorage<Fire> for MyWorld {
     type Storage = VecStorage<Fire>;
 
-    fn storage(&self) -> &Self::Storage {
+    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
         &self.components.fires
     }
-
-    fn mut_storage(&mut self) -> &mut Self::Storage {
-        &mut self.components.fires
-    }
 }
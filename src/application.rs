@@ -2,6 +2,7 @@
 
 use std::fs::File;
 use std::io::BufReader;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 use futures::executor::block_on;
@@ -9,20 +10,31 @@ use replace_with::replace_with_or_abort;
 use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalSize;
 use winit::{
-    event::Event,
+    event::{ElementState, Event, KeyboardInput, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
 use crate::debug::DebugContext;
-use crate::game::Playable;
+use crate::game::{IoState, Playable};
 use crate::gfx::{default_render_pipelines, RenderContext};
-use winit::event::WindowEvent;
+use crate::input::replay::{push_input, Recorder, Replay};
+#[cfg(feature = "gamepad")]
+use crate::input::Gamepads;
+use crate::input::InputEvent;
 
 fn default_max_fps() -> u32 {
     300
 }
 
+fn default_tick_rate() -> Option<u32> {
+    Some(60)
+}
+
+fn default_max_ticks_per_frame() -> u32 {
+    8
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Settings {
     pub window_size: [f32; 2],
@@ -30,6 +42,13 @@ pub struct Settings {
     pub vsync: bool,
     #[serde(default = "default_max_fps")]
     pub max_fps: u32,
+    /// fixed ticks per second, see `Playable::fixed_update`. null turns fixed ticks off.
+    #[serde(default = "default_tick_rate")]
+    pub tick_rate: Option<u32>,
+    /// most fixed ticks run in one frame. When the game falls further behind than this, the
+    /// time it couldn't catch up on is dropped, so slow frames don't make the next ones slower.
+    #[serde(default = "default_max_ticks_per_frame")]
+    pub max_ticks_per_frame: u32,
 }
 
 impl Default for Settings {
@@ -38,6 +57,8 @@ impl Default for Settings {
             window_size: [1280., 720.],
             vsync: true,
             max_fps: 300,
+            tick_rate: default_tick_rate(),
+            max_ticks_per_frame: default_max_ticks_per_frame(),
         }
     }
 }
@@ -46,6 +67,8 @@ pub struct Application<G: 'static + Playable> {
     title: String,
     game: G,
     settings: Settings,
+    record_path: Option<PathBuf>,
+    replay: Option<Replay>,
 }
 
 impl<G: 'static + Playable> Application<G> {
@@ -54,6 +77,8 @@ impl<G: 'static + Playable> Application<G> {
             game,
             title: title.into(),
             settings: Application::<G>::load_settings(),
+            record_path: None,
+            replay: None,
         }
     }
 
@@ -75,6 +100,24 @@ impl<G: 'static + Playable> Application<G> {
             game,
             title: title.into(),
             settings,
+            record_path: None,
+            replay: None,
+        }
+    }
+
+    /// records input and frame deltas to a file at path, see `input::replay`
+    pub fn record_to<P: Into<PathBuf>>(self, path: P) -> Application<G> {
+        Application {
+            record_path: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// plays replay back instead of live input, until it runs out
+    pub fn with_replay(self, replay: Replay) -> Application<G> {
+        Application {
+            replay: Some(replay),
+            ..self
         }
     }
 
@@ -157,9 +200,20 @@ impl<G: 'static + Playable> Application<G> {
             self.settings.window_size[0] as u32,
             self.settings.window_size[1] as u32,
         );
+        let mut replay = self.replay;
+        let seed = replay.as_ref().map_or_else(rand::random, Replay::seed);
+        let mut recorder = self
+            .record_path
+            .map(|path| Recorder::create(path, seed).expect("failed to create recording"));
         let mut game = self.game;
+        game.set_seed(seed);
         let mut last_update = Instant::now();
         let update_interval = Duration::from_secs_f64(1. / self.settings.max_fps as f64);
+        let mut tick_timer = TickTimer::new(&self.settings);
+        let mut io_state = IoState::default();
+        // no gamepad support on this platform is no reason not to run
+        #[cfg(feature = "gamepad")]
+        let mut gamepads = Gamepads::new().ok();
         event_loop.run(move |event, _, control_flow| {
             *control_flow = ControlFlow::WaitUntil(last_update + update_interval);
 
@@ -185,94 +239,258 @@ impl<G: 'static + Playable> Application<G> {
 
                         imgui_ctx.io_mut().update_delta_time(last_update.elapsed());
 
-                        let delta = last_update.elapsed();
-                        last_update = Instant::now();
+                        // replays replace live input and time until they run out
+                        let replay_frame = replay.as_mut().and_then(Replay::next_frame);
 
-                        let (_, update_buffer, draw_buffer) = {
-                            let mut render_ctx = RenderContext {
-                                screen_size,
-                                frame: &frame.output.view,
-                                encoder: update_encoder,
-                                device: &device,
-                                pipelines: &render_pipelines,
-                                window: &window,
-                            };
-
-                            platform
-                                .prepare_frame(imgui_ctx.io_mut(), &window)
-                                .expect("Failed to prepare imgui frame");
-                            let ui = imgui_ctx.frame();
-
-                            let mut debug_ctx = DebugContext {
-                                ui,
-                                ui_renderer: &mut imgui_renderer,
-                            };
-
-                            replace_with_or_abort(&mut game, |game| {
-                                game.update(delta, &mut render_ctx, &mut debug_ctx)
-                            });
+                        let delta = match &replay_frame {
+                            Some(replay_frame) => replay_frame.delta,
+                            None => last_update.elapsed(),
+                        };
+                        last_update = Instant::now();
 
-                            let RenderContext { encoder, .. } = render_ctx;
-
-                            let update_buffer = encoder.finish();
-
-                            let mut render_ctx = RenderContext {
-                                encoder: draw_encoder,
-                                ..render_ctx
-                            };
-
-                            game.redraw(&mut render_ctx, &mut debug_ctx);
-
-                            let DebugContext { ui, ui_renderer } = debug_ctx;
-
-                            {
-                                let mut imgui_rpass = render_ctx.encoder.begin_render_pass(
-                                    &wgpu::RenderPassDescriptor {
-                                        color_attachments: &[
-                                            wgpu::RenderPassColorAttachmentDescriptor {
-                                                attachment: &render_ctx.frame,
-                                                resolve_target: None,
-                                                ops: wgpu::Operations {
-                                                    load: wgpu::LoadOp::Load,
-                                                    store: true,
-                                                },
-                                            },
-                                        ],
-                                        depth_stencil_attachment: None,
-                                    },
-                                );
-
-                                imgui_renderer
-                                    .render(ui.render(), &queue, &device, &mut imgui_rpass)
-                                    .unwrap();
+                        for event in replay_frame.iter().flat_map(|frame| &frame.events) {
+                            if let Some(recorder) = &mut recorder {
+                                recorder.record(*event);
                             }
 
-                            let RenderContext { frame, encoder, .. } = render_ctx;
+                            if let Some(flow) = push_input(&mut game, &mut io_state, event) {
+                                *control_flow = flow;
+                            }
+                        }
 
-                            (frame, update_buffer, encoder.finish())
-                        };
+                        let render_ctx = RenderContext::new(
+                            &frame.output.view,
+                            update_encoder,
+                            &device,
+                            &render_pipelines,
+                            screen_size,
+                            Some(&window),
+                        );
+
+                        platform
+                            .prepare_frame(imgui_ctx.io_mut(), &window)
+                            .expect("Failed to prepare imgui frame");
+
+                        let debug_ctx = DebugContext::new(imgui_ctx.frame(), &mut imgui_renderer);
+
+                        #[cfg(feature = "gamepad")]
+                        {
+                            if let (Some(gamepads), None) = (&mut gamepads, &replay_frame) {
+                                for event in gamepads.poll(&mut io_state) {
+                                    if let Some(recorder) = &mut recorder {
+                                        recorder.record(event);
+                                    }
+                                }
+                            }
+                        }
 
-                        queue.submit(vec![update_buffer, draw_buffer])
+                        let (update_buffer, draw_buffer) = run_frame(
+                            &mut game,
+                            delta,
+                            &mut io_state,
+                            &mut tick_timer,
+                            render_ctx,
+                            draw_encoder,
+                            debug_ctx,
+                            &queue,
+                        );
+
+                        queue.submit(vec![update_buffer, draw_buffer]);
+
+                        if let Some(recorder) = &mut recorder {
+                            recorder
+                                .end_frame(delta)
+                                .expect("failed to write recording");
+                        }
                     }
                 }
-                event
-                @
-                Event::WindowEvent {
-                    event: WindowEvent::MouseInput { .. },
-                    ..
-                } => {
-                    if !imgui_ctx.io().want_capture_mouse {
+                event @ _ => {
+                    let input = InputEvent::from_event(&event);
+                    let replaying = replay.as_ref().map_or(false, |replay| !replay.is_empty());
+
+                    // live input would make the replay play out differently
+                    let ignored =
+                        (replaying && input.is_some()) || captured_by_imgui(&event, imgui_ctx.io());
+
+                    if !ignored {
+                        if let Some(input) = input {
+                            if let Some(recorder) = &mut recorder {
+                                recorder.record(input);
+                            }
+
+                            io_state.apply(&input);
+                        }
+
                         match game.push_event(&event) {
                             Some(flow) => *control_flow = flow,
                             None => (),
                         }
                     }
                 }
-                event @ _ => match game.push_event(&event) {
-                    Some(flow) => *control_flow = flow,
-                    None => (),
-                },
             }
         });
     }
 }
+
+/// true if event is input imgui wants for itself, like clicks on debug windows. Releases are never
+/// captured, so nothing is left held down when imgui grabs the input mid-press.
+fn captured_by_imgui<T>(event: &Event<T>, imgui_io: &imgui::Io) -> bool {
+    match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                ..
+            }
+            | WindowEvent::MouseWheel { .. } => imgui_io.want_capture_mouse,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            }
+            | WindowEvent::ReceivedCharacter(_) => imgui_io.want_capture_keyboard,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Splits frame time into fixed ticks, see `Settings::tick_rate`
+pub(crate) struct TickTimer {
+    interval: Option<Duration>,
+    max_ticks_per_frame: u32,
+    timer: Duration,
+}
+
+impl TickTimer {
+    pub(crate) fn new(settings: &Settings) -> TickTimer {
+        TickTimer {
+            interval: settings
+                .tick_rate
+                .map(|tick_rate| Duration::from_secs_f64(1. / tick_rate as f64)),
+            max_ticks_per_frame: settings.max_ticks_per_frame,
+            timer: Duration::new(0, 0),
+        }
+    }
+
+    /// length of one tick
+    pub(crate) fn step(&self) -> Duration {
+        self.interval.unwrap_or_default()
+    }
+
+    /// advances the timer by a frame of delta, returns how many ticks to run and the alpha of
+    /// the frame, see `RenderContext::alpha`
+    pub(crate) fn advance(&mut self, delta: Duration) -> (u32, f32) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return (0, 1.),
+        };
+
+        self.timer += delta;
+
+        let mut ticks = 0;
+        while self.timer >= interval {
+            self.timer -= interval;
+            ticks += 1;
+        }
+
+        // too far behind, drop the ticks we couldn't catch up on
+        let ticks = ticks.min(self.max_ticks_per_frame);
+
+        (ticks, self.timer.as_secs_f32() / interval.as_secs_f32())
+    }
+}
+
+/// Runs the fixed ticks and update of one frame, then ends the frame of io_state. Needs no gpu,
+/// so null contexts will do.
+pub(crate) fn update_frame<G: Playable>(
+    game: &mut G,
+    delta: Duration,
+    io_state: &mut IoState,
+    tick_timer: &mut TickTimer,
+    render_ctx: &mut RenderContext,
+    debug_ctx: &mut DebugContext,
+) -> () {
+    let (ticks, alpha) = tick_timer.advance(delta);
+    let step = tick_timer.step();
+
+    for _ in 0..ticks {
+        io_state.begin_tick();
+
+        replace_with_or_abort(game, |game| {
+            game.fixed_update(step, io_state, render_ctx, debug_ctx)
+        });
+
+        io_state.end_tick();
+    }
+
+    render_ctx.alpha = alpha;
+
+    replace_with_or_abort(game, |game| {
+        game.update(delta, io_state, render_ctx, debug_ctx)
+    });
+
+    io_state.update();
+}
+
+/// Runs the fixed ticks, update and redraw of one frame, and draws the debug ui on top, then ends
+/// the frame of io_state. Returns the command buffers of update and redraw, to be submitted in
+/// that order.
+pub(crate) fn run_frame<G: Playable>(
+    game: &mut G,
+    delta: Duration,
+    io_state: &mut IoState,
+    tick_timer: &mut TickTimer,
+    mut render_ctx: RenderContext,
+    draw_encoder: wgpu::CommandEncoder,
+    mut debug_ctx: DebugContext,
+    queue: &wgpu::Queue,
+) -> (wgpu::CommandBuffer, wgpu::CommandBuffer) {
+    update_frame(
+        game,
+        delta,
+        io_state,
+        tick_timer,
+        &mut render_ctx,
+        &mut debug_ctx,
+    );
+
+    let update_buffer = render_ctx.replace_encoder(draw_encoder).finish();
+
+    game.redraw(&mut render_ctx, &mut debug_ctx);
+
+    let DebugContext { ui, ui_renderer } = debug_ctx;
+    let ui = ui.expect("debug ui to draw");
+    let ui_renderer = ui_renderer.expect("debug ui renderer to draw with");
+
+    {
+        let frame = render_ctx.frame();
+        let device = render_ctx.device();
+        let mut imgui_rpass = render_ctx
+            .encoder()
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: frame,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+        ui_renderer
+            .render(ui.render(), queue, device, &mut imgui_rpass)
+            .unwrap();
+    }
+
+    let draw_buffer = render_ctx
+        .into_encoder()
+        .expect("gpu to draw with")
+        .finish();
+
+    (update_buffer, draw_buffer)
+}
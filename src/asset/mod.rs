@@ -1,7 +1,7 @@
 //! Loadable stuff
 
 use std::io::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 // Example Asset implementation
@@ -9,6 +9,7 @@ use std::rc::Rc;
 use crate::gfx::{RenderContext, Texture};
 use wgpu::util::DeviceExt;
 
+pub mod prefab;
 #[cfg(feature = "3d")]
 pub mod scene;
 #[cfg(feature = "2d")]
@@ -28,6 +29,8 @@ pub enum AssetError {
     ImageError(image::ImageError),
     SerdeXmlError(serde_xml_rs::Error),
     SerdeJsonError(serde_json::Error),
+    /// prefab file that refers to itself, directly or through other prefab files
+    PrefabCycle(PathBuf),
 }
 
 impl From<std::io::Error> for AssetError {
@@ -76,26 +79,28 @@ where
             depth: 1,
         };
 
-        let texture = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-        });
+        let texture = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: texture_extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            });
 
         // upload image data to texture
         let temp_buf = render_ctx
-            .device
+            .device()
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: None,
                 contents: &img.into_raw(),
                 usage: wgpu::BufferUsage::COPY_SRC,
             });
 
-        render_ctx.encoder.copy_buffer_to_texture(
+        render_ctx.encoder().copy_buffer_to_texture(
             wgpu::BufferCopyView {
                 buffer: &temp_buf,
                 layout: Default::default(),
@@ -126,26 +131,28 @@ impl Asset<Texture> for Bytes {
             depth: 1,
         };
 
-        let texture = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-        });
+        let texture = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: texture_extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            });
 
         // upload image data to texture
         let temp_buf = render_ctx
-            .device
+            .device()
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: None,
                 contents: &img.into_raw(),
                 usage: wgpu::BufferUsage::COPY_SRC,
             });
 
-        render_ctx.encoder.copy_buffer_to_texture(
+        render_ctx.encoder().copy_buffer_to_texture(
             wgpu::BufferCopyView {
                 buffer: &temp_buf,
                 layout: Default::default(),
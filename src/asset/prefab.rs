@@ -0,0 +1,359 @@
+//! Entity templates
+//!
+//! ```json
+//! {
+//!     "components": {
+//!         "Transform": { "rotation": [1.0, 0.0], "translation": [0.0, 0.0] }
+//!     },
+//!     "children": [
+//!         {
+//!             "prefab": "wheel.json",
+//!             "components": { "Transform": { "translation": [2.0, 8.0] } }
+//!         },
+//!         { "components": { "Transform": { "rotation": [1.0, 0.0], "translation": [0.0, 8.0] } } }
+//!     ]
+//! }
+//! ```
+//!
+//! Components are keyed by their `SerializableComponent::NAME`, and instantiated through the
+//! `WorldSerializer` they are registered to, see `EntityBuilder::with_prefab`.
+//!
+//! A prefab can be an instance of another prefab file, like the first child above. Its
+//! components override those of the file, and its children are added to those of the file.
+//! References are resolved when the prefab is loaded, see `Prefab::resolve`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::asset::{Asset, AssetError, AssetState, Bytes};
+use crate::gfx::RenderContext;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Prefab {
+    /// path of the prefab file this one is an instance of, relative to the file that refers to
+    /// it. None once resolved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefab: Option<String>,
+    /// serialized components, by component name
+    #[serde(default)]
+    pub components: BTreeMap<String, Value>,
+    /// child entities, spawned with a `Parent` pointing to the entity of this prefab
+    #[serde(default)]
+    pub children: Vec<Prefab>,
+}
+
+impl Prefab {
+    pub fn from_json(json: &str) -> Result<Prefab, AssetError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// overrides component of this instance. Fields of objects are overridden one by one, so
+    /// `{ "health": 5 }` only changes the health, and keeps the rest of the component.
+    pub fn with_override(self, name: &str, value: Value) -> Prefab {
+        let Prefab { mut components, .. } = self;
+
+        match components.get_mut(name) {
+            Some(component) => merge(component, value),
+            None => {
+                components.insert(name.to_string(), value);
+            }
+        }
+
+        Prefab { components, ..self }
+    }
+
+    /// replaces the references to prefab files of this prefab and its children by what they
+    /// refer to, with paths relative to dir. Done by loading, so only needed for prefabs made
+    /// with `from_json`.
+    pub fn resolve(self, dir: &Path) -> Result<Prefab, AssetError> {
+        self.resolve_in(dir, &mut Vec::new())
+    }
+
+    /// resolves this prefab, referred to from the prefab files of loading
+    fn resolve_in(self, dir: &Path, loading: &mut Vec<PathBuf>) -> Result<Prefab, AssetError> {
+        let children = self
+            .children
+            .into_iter()
+            .map(|child| child.resolve_in(dir, loading))
+            .collect::<Result<Vec<_>, AssetError>>()?;
+
+        let path = match self.prefab {
+            Some(path) => std::fs::canonicalize(dir.join(path))?,
+            None => {
+                return Ok(Prefab {
+                    prefab: None,
+                    components: self.components,
+                    children,
+                })
+            }
+        };
+
+        if loading.contains(&path) {
+            return Err(AssetError::PrefabCycle(path));
+        }
+
+        let json = std::fs::read_to_string(&path)?;
+        let dir = path.parent().unwrap_or(dir).to_path_buf();
+
+        loading.push(path);
+        let base = Prefab::from_json(&json)?.resolve_in(&dir, loading)?;
+        loading.pop();
+
+        let instance = self
+            .components
+            .into_iter()
+            .fold(base, |base, (name, value)| base.with_override(&name, value));
+
+        Ok(Prefab {
+            children: instance.children.into_iter().chain(children).collect(),
+            ..instance
+        })
+    }
+}
+
+fn merge(target: &mut Value, value: Value) {
+    match (target, value) {
+        (Value::Object(target), Value::Object(fields)) => {
+            for (key, value) in fields {
+                match target.get_mut(&key) {
+                    Some(field) => merge(field, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, value) => *target = value,
+    }
+}
+
+impl<T> Asset<Prefab> for T
+where
+    T: AsRef<Path>,
+{
+    fn poll(
+        self: Box<Self>,
+        _render_ctx: &mut RenderContext,
+    ) -> Result<AssetState<Prefab>, AssetError> {
+        let path = std::fs::canonicalize(self.as_ref())?;
+        let json = std::fs::read_to_string(&path)?;
+        let dir = path.parent().unwrap_or(&path).to_path_buf();
+        let prefab = Prefab::from_json(&json)?.resolve_in(&dir, &mut vec![path])?;
+
+        Ok(AssetState::Done(prefab))
+    }
+}
+
+impl Asset<Prefab> for Bytes {
+    fn poll(
+        self: Box<Self>,
+        _render_ctx: &mut RenderContext,
+    ) -> Result<AssetState<Prefab>, AssetError> {
+        // in memory, so references are relative to the working directory
+        let prefab: Prefab = serde_json::from_slice(self.0)?;
+
+        Ok(AssetState::Done(prefab.resolve(Path::new(""))?))
+    }
+}
+
+// the example Transforms are 2d
+#[cfg(all(test, feature = "2d"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::ecs::component::{Parent, Transform};
+    use crate::ecs::serialize::WorldSerializer;
+    use crate::ecs::world::{DefaultWorld, World, WorldStorage};
+    use crate::ecs::{ComponentStorage, ReadAccess};
+    use crate::nphysics::math::Vector;
+
+    const JSON: &str = r#"{
+        "components": {
+            "Transform": { "rotation": [1.0, 0.0], "translation": [1.0, 2.0] }
+        },
+        "children": [
+            { "components": { "Transform": { "rotation": [1.0, 0.0], "translation": [0.0, 8.0] } } }
+        ]
+    }"#;
+
+    #[test]
+    fn overrides_merge_fields() {
+        let prefab = Prefab::from_json(JSON)
+            .unwrap()
+            .with_override("Transform", json!({ "translation": [5.0, 6.0] }))
+            .with_override("Health", json!({ "hp": 3 }));
+
+        assert_eq!(
+            prefab.components["Transform"],
+            json!({ "rotation": [1.0, 0.0], "translation": [5.0, 6.0] })
+        );
+        assert_eq!(prefab.components["Health"], json!({ "hp": 3 }));
+        assert_eq!(prefab.children.len(), 1);
+    }
+
+    #[test]
+    fn loads_from_bytes() {
+        let asset = Box::new(Bytes(JSON.as_bytes()));
+
+        match asset.poll(&mut RenderContext::null((0, 0))) {
+            Ok(AssetState::Done(prefab)) => assert_eq!(prefab.children.len(), 1),
+            _ => panic!("prefab didn't load"),
+        }
+    }
+
+    #[test]
+    fn instantiates_children_with_parent() {
+        let serializer = WorldSerializer::new()
+            .with::<Transform<f32>>()
+            .with::<Parent>();
+        let prefab = Prefab::from_json(JSON).unwrap();
+
+        let builder = DefaultWorld::new()
+            .register::<Transform<f32>>()
+            .register::<Parent>()
+            .add_entity()
+            .with_prefab(&prefab, &serializer)
+            .unwrap();
+        let root = builder.entity();
+        let world = builder.build();
+
+        let child = world.entities()[1];
+        let transforms = WorldStorage::<Transform<f32>>::storage(&world).read();
+        let parents = WorldStorage::<Parent>::storage(&world).read();
+
+        assert_eq!(world.entities().len(), 2);
+        assert_eq!(
+            transforms.fetch(root).unwrap().translation.vector,
+            Vector::new(1., 2.)
+        );
+        assert_eq!(
+            transforms.fetch(child).unwrap().translation.vector,
+            Vector::new(0., 8.)
+        );
+        assert_eq!(parents.fetch(child).unwrap().0, root);
+    }
+
+    #[test]
+    fn unknown_components_are_errors() {
+        let serializer = WorldSerializer::<DefaultWorld>::new().with::<Parent>();
+        let prefab = Prefab::from_json(r#"{ "components": { "Nope": 1 } }"#).unwrap();
+
+        let result = DefaultWorld::new()
+            .register::<Parent>()
+            .add_entity()
+            .with_prefab(&prefab, &serializer);
+
+        assert!(result.is_err());
+    }
+
+    /// writes the prefab files of files to a directory named name in the temp dir, returns it
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(dir.join("parts")).unwrap();
+
+        for (file, json) in files {
+            std::fs::write(dir.join(file), json).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn resolves_prefab_files_on_load() {
+        let dir = write_files(
+            "mela_prefab_files",
+            &[
+                (
+                    "car.json",
+                    r#"{ "children": [
+                        {
+                            "prefab": "parts/wheel.json",
+                            "components": { "Transform": { "translation": [2.0, 0.0] } }
+                        },
+                        {
+                            "prefab": "parts/wheel.json",
+                            "children": [{ "components": { "Name": "cap" } }]
+                        }
+                    ] }"#,
+                ),
+                (
+                    "parts/wheel.json",
+                    r#"{
+                        "components": {
+                            "Name": "wheel",
+                            "Transform": { "rotation": [0.0, 1.0], "translation": [0.0, 0.0] }
+                        },
+                        "children": [{ "prefab": "bolt.json" }]
+                    }"#,
+                ),
+                ("parts/bolt.json", r#"{ "components": { "Name": "bolt" } }"#),
+            ],
+        );
+        let asset: Box<dyn Asset<Prefab>> = Box::new(dir.join("car.json"));
+
+        let car = match asset.poll(&mut RenderContext::null((0, 0))) {
+            Ok(AssetState::Done(prefab)) => prefab,
+            _ => panic!("prefab didn't load"),
+        };
+        let (moved, capped) = (&car.children[0], &car.children[1]);
+
+        assert_eq!(
+            moved.components["Transform"],
+            json!({ "rotation": [0.0, 1.0], "translation": [2.0, 0.0] })
+        );
+        assert_eq!(moved.components["Name"], json!("wheel"));
+        assert!(moved.prefab.is_none());
+        assert_eq!(moved.children.len(), 1);
+        assert_eq!(moved.children[0].components["Name"], json!("bolt"));
+
+        // the children of the instance come after those of the file
+        assert_eq!(
+            capped
+                .children
+                .iter()
+                .map(|child| child.components["Name"].clone())
+                .collect::<Vec<_>>(),
+            vec![json!("bolt"), json!("cap")]
+        );
+    }
+
+    #[test]
+    fn prefab_cycles_are_errors() {
+        let dir = write_files(
+            "mela_prefab_cycle",
+            &[
+                (
+                    "a.json",
+                    r#"{ "children": [{ "prefab": "parts/b.json" }] }"#,
+                ),
+                (
+                    "parts/b.json",
+                    r#"{ "children": [{ "prefab": "../a.json" }] }"#,
+                ),
+            ],
+        );
+        let asset: Box<dyn Asset<Prefab>> = Box::new(dir.join("a.json"));
+
+        match asset.poll(&mut RenderContext::null((0, 0))) {
+            Err(AssetError::PrefabCycle(path)) => assert!(path.ends_with("a.json")),
+            _ => panic!("cycle wasn't found"),
+        }
+    }
+
+    #[test]
+    fn unresolved_prefabs_are_errors() {
+        let serializer = WorldSerializer::<DefaultWorld>::new().with::<Parent>();
+        let prefab = Prefab::from_json(r#"{ "children": [{ "prefab": "wheel.json" }] }"#).unwrap();
+
+        let result = DefaultWorld::new()
+            .register::<Parent>()
+            .add_entity()
+            .with_prefab(&prefab, &serializer);
+
+        assert!(result.is_err());
+    }
+}
@@ -2,12 +2,35 @@
 
 use crate::gfx::RenderContext;
 
+/// Debug ui of the frame
+///
+/// Both are None in a null context, see `DebugContext::null`.
 pub struct DebugContext<'ui, 'ui_renderer> {
-    pub ui: imgui::Ui<'ui>,
-    pub ui_renderer: &'ui_renderer mut imgui_wgpu::Renderer,
+    pub ui: Option<imgui::Ui<'ui>>,
+    pub ui_renderer: Option<&'ui_renderer mut imgui_wgpu::Renderer>,
     //    pub profiler_frame: OpenFrame,
 }
 
+impl<'ui, 'ui_renderer> DebugContext<'ui, 'ui_renderer> {
+    pub fn new(
+        ui: imgui::Ui<'ui>,
+        ui_renderer: &'ui_renderer mut imgui_wgpu::Renderer,
+    ) -> DebugContext<'ui, 'ui_renderer> {
+        DebugContext {
+            ui: Some(ui),
+            ui_renderer: Some(ui_renderer),
+        }
+    }
+
+    /// creates a context without debug ui, for running updates only
+    pub fn null() -> DebugContext<'ui, 'ui_renderer> {
+        DebugContext {
+            ui: None,
+            ui_renderer: None,
+        }
+    }
+}
+
 pub trait DebugDrawable {
     fn draw_debug_ui(&mut self, _render_ctx: &mut RenderContext) {}
 }
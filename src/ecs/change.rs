@@ -0,0 +1,355 @@
+//! Change detection for component storages
+//!
+//! Every change is stamped with a tick of the storage it was made in. Readers keep the tick they
+//! last read up to, like the cursors of an `EventChannel`, so each of them sees every change once
+//! no matter how often they run. The `Dispatcher` keeps the ticks of each system in
+//! `ChangeCursors`.
+
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use crate::ecs::Entity;
+
+/// Number of frames changes are kept around for. Readers that skip more frames than this, like
+/// fixed tick systems at a very high frame rate, miss the older changes.
+pub const MAX_AGE: usize = 16;
+
+#[derive(Debug, Default)]
+struct Changes {
+    /// tick of the last insertion, modification and removal of each entity
+    inserted: HashMap<Entity, u64>,
+    modified: HashMap<Entity, u64>,
+    removed: HashMap<Entity, u64>,
+    /// bumped on every recorded change
+    tick: u64,
+    /// tick at the end of each of the last `MAX_AGE` frames, oldest first
+    frames: VecDeque<u64>,
+    /// bumped on every insertion and removal
+    version: u64,
+}
+
+impl Changes {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// tick at the end of the last frame
+    fn frame_tick(&self) -> u64 {
+        self.frames.back().cloned().unwrap_or(0)
+    }
+}
+
+/// Records which entities had their Component inserted, modified or removed.
+///
+/// Every storage owns one, and its writer records changes into it. Modifications are recorded
+/// when a Component is borrowed mutably through `fetch_mut`, or written through a `Mut`.
+#[derive(Debug, Default)]
+pub struct ChangeTracker {
+    changes: Mutex<Changes>,
+}
+
+fn without_dead_bit(entity: Entity) -> Entity {
+    Entity::with_generation(entity.index(), entity.generation())
+}
+
+fn since(stamps: &HashMap<Entity, u64>, tick: u64) -> impl Iterator<Item = Entity> + '_ {
+    stamps
+        .iter()
+        .filter(move |(_, stamp)| **stamp > tick)
+        .map(|(entity, _)| *entity)
+}
+
+impl ChangeTracker {
+    pub fn new() -> ChangeTracker {
+        ChangeTracker::default()
+    }
+
+    /// records that Component was set for entity, `existed` tells if it had one already
+    pub fn set(&self, entity: Entity, existed: bool) {
+        let mut changes = self.changes.lock().unwrap();
+        let entity = without_dead_bit(entity);
+        let tick = changes.next_tick();
+
+        if existed {
+            changes.modified.insert(entity, tick);
+        } else {
+            changes.inserted.insert(entity, tick);
+            changes.version += 1;
+        }
+    }
+
+    /// records that Component of entity was borrowed mutably
+    pub fn modified(&self, entity: Entity) {
+        let mut changes = self.changes.lock().unwrap();
+        let tick = changes.next_tick();
+
+        changes.modified.insert(without_dead_bit(entity), tick);
+    }
+
+    /// records that Component of entity was removed
+    pub fn removed(&self, entity: Entity) {
+        let mut changes = self.changes.lock().unwrap();
+        let entity = without_dead_bit(entity);
+        let tick = changes.next_tick();
+
+        changes.inserted.remove(&entity);
+        changes.modified.remove(&entity);
+        changes.removed.insert(entity, tick);
+        changes.version += 1;
+    }
+
+    /// entities whose Component was inserted after cursor, and moves cursor past them
+    pub fn read_inserted(&self, cursor: &mut u64) -> HashSet<Entity> {
+        let changes = self.changes.lock().unwrap();
+        let since = std::mem::replace(cursor, changes.tick);
+
+        self::since(&changes.inserted, since).collect()
+    }
+
+    /// entities whose Component was inserted or modified after cursor, and moves cursor past
+    /// them
+    pub fn read_changed(&self, cursor: &mut u64) -> HashSet<Entity> {
+        let changes = self.changes.lock().unwrap();
+        let since = std::mem::replace(cursor, changes.tick);
+
+        self::since(&changes.inserted, since)
+            .chain(self::since(&changes.modified, since))
+            .collect()
+    }
+
+    /// entities whose Component was removed after cursor, and moves cursor past them
+    pub fn read_removed(&self, cursor: &mut u64) -> HashSet<Entity> {
+        let changes = self.changes.lock().unwrap();
+        let since = std::mem::replace(cursor, changes.tick);
+
+        self::since(&changes.removed, since).collect()
+    }
+
+    /// entities whose Component was inserted since the end of the last frame
+    pub fn inserted_entities(&self) -> HashSet<Entity> {
+        self.read_inserted(&mut self.frame_tick())
+    }
+
+    /// entities whose Component was inserted or modified since the end of the last frame
+    pub fn changed_entities(&self) -> HashSet<Entity> {
+        self.read_changed(&mut self.frame_tick())
+    }
+
+    /// entities whose Component was removed since the end of the last frame
+    pub fn removed_entities(&self) -> HashSet<Entity> {
+        self.read_removed(&mut self.frame_tick())
+    }
+
+    /// tick of the latest change
+    pub fn tick(&self) -> u64 {
+        self.changes.lock().unwrap().tick
+    }
+
+    /// tick at the end of the last frame, where new cursors start reading from
+    pub fn frame_tick(&self) -> u64 {
+        self.changes.lock().unwrap().frame_tick()
+    }
+
+    /// structural version of the storage. Changes whenever a Component is inserted or removed,
+    /// but not when one is modified, so it tells when cached joins need to be rebuilt.
+    pub fn version(&self) -> u64 {
+        self.changes.lock().unwrap().version
+    }
+
+    /// ends a frame, and forgets the changes older than `MAX_AGE` frames. Called once per frame
+    /// by `World::clear_events`.
+    pub fn maintain(&self) {
+        let mut changes = self.changes.lock().unwrap();
+        let tick = changes.tick;
+
+        changes.frames.push_back(tick);
+
+        if changes.frames.len() > MAX_AGE {
+            let expired = changes.frames.pop_front().unwrap();
+
+            changes.inserted.retain(|_, stamp| *stamp > expired);
+            changes.modified.retain(|_, stamp| *stamp > expired);
+            changes.removed.retain(|_, stamp| *stamp > expired);
+        }
+    }
+}
+
+/// Ticks up to which a system has read the changes of each Component, see
+/// `SystemData::fetch`.
+///
+/// Cursors belong to change readers by their position in the SystemData, so two readers of the
+/// same changes in one SystemData each see every change.
+#[derive(Debug, Default)]
+pub struct ChangeCursors {
+    cursors: HashMap<(usize, TypeId, &'static str), u64>,
+    /// cursors handed out since `begin_fetch`
+    fetched: usize,
+}
+
+impl ChangeCursors {
+    pub fn new() -> ChangeCursors {
+        ChangeCursors::default()
+    }
+
+    /// starts a fetch of a SystemData, so its change readers get their cursors in order
+    pub fn begin_fetch(&mut self) {
+        self.fetched = 0;
+    }
+
+    /// cursor of the next change reader, reading the kind of changes of Component C. New cursors
+    /// start at the end of the last frame of tracker.
+    pub fn cursor<C: 'static>(&mut self, kind: &'static str, tracker: &ChangeTracker) -> &mut u64 {
+        let position = self.fetched;
+        self.fetched += 1;
+
+        self.cursors
+            .entry((position, TypeId::of::<C>(), kind))
+            .or_insert_with(|| tracker.frame_tick())
+    }
+}
+
+/// Mutable access to a Component, that records a modification the first time it's written
+/// through. Reading through it records nothing.
+pub struct Mut<'a, C> {
+    entity: Entity,
+    component: &'a mut C,
+    changes: &'a ChangeTracker,
+    modified: bool,
+}
+
+impl<'a, C> Mut<'a, C> {
+    pub fn new(entity: Entity, component: &'a mut C, changes: &'a ChangeTracker) -> Mut<'a, C> {
+        Mut {
+            entity,
+            component,
+            changes,
+            modified: false,
+        }
+    }
+
+    /// extends the borrow of the Component to 'b
+    ///
+    /// # Safety
+    ///
+    /// The Component and its ChangeTracker must outlive 'b, and the Component must not be
+    /// borrowed by anything else during 'b.
+    pub(crate) unsafe fn extend<'b>(self) -> Mut<'b, C> {
+        Mut {
+            entity: self.entity,
+            component: &mut *(self.component as *mut C),
+            changes: &*(self.changes as *const ChangeTracker),
+            modified: self.modified,
+        }
+    }
+
+    /// records the modification and gives up the guard for a plain mutable reference
+    pub fn into_mut(self) -> &'a mut C {
+        if !self.modified {
+            self.changes.modified(self.entity);
+        }
+
+        self.component
+    }
+}
+
+impl<'a, C> Deref for Mut<'a, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.component
+    }
+}
+
+impl<'a, C> DerefMut for Mut<'a, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        if !self.modified {
+            self.changes.modified(self.entity);
+            self.modified = true;
+        }
+
+        self.component
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_see_every_change_once() {
+        let tracker = ChangeTracker::new();
+        let (a, b) = (Entity::new(0), Entity::new(1));
+        let mut cursor = 0;
+
+        tracker.set(a, false);
+        tracker.set(b, false);
+        tracker.maintain();
+        tracker.set(a, true);
+
+        let changed = tracker.read_changed(&mut cursor);
+        assert!(changed.contains(&a) && changed.contains(&b));
+        assert!(tracker.read_changed(&mut cursor).is_empty());
+
+        // the last frame only changed a
+        assert_eq!(
+            tracker.changed_entities().into_iter().collect::<Vec<_>>(),
+            vec![a]
+        );
+
+        tracker.modified(b);
+        assert_eq!(
+            tracker
+                .read_changed(&mut cursor)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![b]
+        );
+    }
+
+    #[test]
+    fn changes_expire_after_max_age() {
+        let tracker = ChangeTracker::new();
+        let entity = Entity::new(0);
+        let mut cursor = 0;
+
+        tracker.removed(entity);
+
+        for _ in 0..MAX_AGE {
+            tracker.maintain();
+        }
+
+        assert_eq!(tracker.read_removed(&mut 0).len(), 1);
+
+        tracker.maintain();
+
+        assert!(tracker.read_removed(&mut cursor).is_empty());
+    }
+
+    #[test]
+    fn mut_records_writes_only() {
+        let tracker = ChangeTracker::new();
+        let entity = Entity::new(0);
+        let mut component = 1;
+        let mut cursor = tracker.tick();
+
+        {
+            let component = Mut::new(entity, &mut component, &tracker);
+            assert_eq!(*component, 1);
+        }
+
+        assert!(tracker.read_changed(&mut cursor).is_empty());
+
+        {
+            let mut component = Mut::new(entity, &mut component, &tracker);
+            *component += 1;
+            *component += 1;
+        }
+
+        assert_eq!(component, 3);
+        assert_eq!(tracker.read_changed(&mut cursor).len(), 1);
+        assert_eq!(tracker.tick(), 1, "recorded once");
+    }
+}
@@ -0,0 +1,240 @@
+//! Typed event channels for messaging between systems
+//!
+//! ```ignore
+//! // publishing system, SystemData has a WriteRes<'a, EventChannel<DamageEvent>>
+//! damage_events.single_write(DamageEvent { target, amount: 10 });
+//!
+//! // reading system, SystemData has a ReadRes<'a, EventChannel<DamageEvent>>
+//! let reader = self
+//!     .reader
+//!     .get_or_insert_with(|| damage_events.register_reader());
+//!
+//! for event in damage_events.read(reader) {
+//!     // every reader sees every event exactly once
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Default number of frames an event is kept around if some reader never reads it
+pub const DEFAULT_MAX_AGE: u64 = 2;
+
+/// Handle to a reader cursor of an `EventChannel`. Only valid for the channel that created it.
+#[derive(Debug)]
+pub struct ReaderId {
+    id: usize,
+}
+
+/// Channel of events of type T, stored as a resource
+///
+/// Events are dropped by `maintain` once every registered reader has read them, or once they
+/// are `max_age` frames old, whichever comes first. Readers that fall behind miss the dropped
+/// events.
+#[derive(Debug)]
+pub struct EventChannel<T> {
+    /// events with the frame they were written on
+    events: VecDeque<(u64, T)>,
+    /// absolute index of the first event in `events`
+    offset: usize,
+    /// absolute index of the next unread event of each reader, None for dropped readers
+    cursors: Mutex<Vec<Option<usize>>>,
+    frame: u64,
+    max_age: u64,
+}
+
+impl<T> Default for EventChannel<T> {
+    fn default() -> Self {
+        EventChannel::with_max_age(DEFAULT_MAX_AGE)
+    }
+}
+
+impl<T> EventChannel<T> {
+    pub fn new() -> EventChannel<T> {
+        EventChannel::default()
+    }
+
+    /// creates a channel that keeps unread events for at most `max_age` frames
+    pub fn with_max_age(max_age: u64) -> EventChannel<T> {
+        EventChannel {
+            events: VecDeque::new(),
+            offset: 0,
+            cursors: Mutex::new(Vec::new()),
+            frame: 0,
+            max_age,
+        }
+    }
+
+    /// registers a new reader. It will only see events written after this call.
+    pub fn register_reader(&self) -> ReaderId {
+        let mut cursors = self.cursors.lock().unwrap();
+        let end = Some(self.end());
+
+        let id = match cursors.iter().position(Option::is_none) {
+            Some(id) => {
+                cursors[id] = end;
+                id
+            }
+            None => {
+                cursors.push(end);
+                cursors.len() - 1
+            }
+        };
+
+        ReaderId { id }
+    }
+
+    /// drops a reader, so unread events no longer wait for it
+    pub fn drop_reader(&self, reader: ReaderId) {
+        self.cursors.lock().unwrap()[reader.id] = None;
+    }
+
+    pub fn single_write(&mut self, event: T) {
+        self.events.push_back((self.frame, event));
+    }
+
+    pub fn iter_write<I: IntoIterator<Item = T>>(&mut self, events: I) {
+        let frame = self.frame;
+        self.events
+            .extend(events.into_iter().map(|event| (frame, event)));
+    }
+
+    /// events the reader hasn't read yet. Marks them read.
+    pub fn read(&self, reader: &mut ReaderId) -> impl Iterator<Item = &T> {
+        let end = self.end();
+        let cursor = self.cursors.lock().unwrap()[reader.id]
+            .replace(end)
+            .expect("reader was dropped");
+
+        // the reader might have fallen behind events that were dropped already
+        let start = cursor.max(self.offset) - self.offset;
+
+        self.events.range(start..).map(|(_, event)| event)
+    }
+
+    /// number of events still in the channel
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// drops consumed and expired events, and advances the frame counter. Called once per frame
+    /// by `Resources::maintain` for channels inserted with `Resources::insert_channel`.
+    pub fn maintain(&mut self) {
+        self.frame += 1;
+
+        let consumed = self
+            .cursors
+            .get_mut()
+            .unwrap()
+            .iter()
+            .flatten()
+            .min()
+            .cloned()
+            .unwrap_or_else(|| self.end());
+
+        while let Some((written, _)) = self.events.front() {
+            if self.offset >= consumed && self.frame - written < self.max_age {
+                break;
+            }
+
+            self.events.pop_front();
+            self.offset += 1;
+        }
+    }
+
+    fn end(&self) -> usize {
+        self.offset + self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_reader_sees_every_event_once() {
+        let mut channel = EventChannel::new();
+        let mut a = channel.register_reader();
+        let mut b = channel.register_reader();
+
+        channel.iter_write(vec![1, 2]);
+
+        assert_eq!(channel.read(&mut a).collect::<Vec<_>>(), vec![&1, &2]);
+        assert_eq!(channel.read(&mut a).count(), 0);
+
+        channel.single_write(3);
+
+        assert_eq!(channel.read(&mut a).collect::<Vec<_>>(), vec![&3]);
+        assert_eq!(channel.read(&mut b).collect::<Vec<_>>(), vec![&1, &2, &3]);
+    }
+
+    #[test]
+    fn readers_only_see_later_events() {
+        let mut channel = EventChannel::new();
+        channel.single_write(1);
+
+        let mut reader = channel.register_reader();
+        channel.single_write(2);
+
+        assert_eq!(channel.read(&mut reader).collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn maintain_drops_events_read_by_everyone() {
+        let mut channel = EventChannel::new();
+        let mut a = channel.register_reader();
+        let mut b = channel.register_reader();
+
+        channel.iter_write(vec![1, 2]);
+        assert_eq!(channel.read(&mut a).count(), 2);
+
+        channel.maintain();
+        assert_eq!(channel.len(), 2);
+
+        assert_eq!(channel.read(&mut b).count(), 2);
+
+        channel.maintain();
+        assert!(channel.is_empty());
+    }
+
+    #[test]
+    fn unread_events_expire() {
+        let mut channel = EventChannel::with_max_age(2);
+        let mut reader = channel.register_reader();
+
+        channel.single_write(1);
+        channel.maintain();
+        assert_eq!(channel.len(), 1);
+
+        channel.maintain();
+        assert!(channel.is_empty());
+
+        // the reader missed it, and is caught up to the end of the channel
+        assert_eq!(channel.read(&mut reader).count(), 0);
+        channel.single_write(2);
+        assert_eq!(channel.read(&mut reader).collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn dropped_readers_are_not_waited_for() {
+        let mut channel = EventChannel::with_max_age(10);
+        let mut a = channel.register_reader();
+        let b = channel.register_reader();
+
+        channel.single_write(1);
+        assert_eq!(channel.read(&mut a).count(), 1);
+
+        channel.drop_reader(b);
+        channel.maintain();
+        assert!(channel.is_empty());
+
+        // the dropped slot is reused
+        let mut c = channel.register_reader();
+        channel.single_write(2);
+        assert_eq!(channel.read(&mut c).count(), 1);
+    }
+}
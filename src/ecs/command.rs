@@ -0,0 +1,298 @@
+//! Deferred structural changes
+//!
+//! Systems only get shared access to the World, so they can't add or remove entities while
+//! running. Instead they record commands through `Commands`, which are applied after dispatch
+//! by `World::apply_commands`.
+//!
+//! ```ignore
+//! fn update<'f>(&mut self, (commands, enemies): Self::SystemData<'f>, ..) {
+//!     let projectile = commands.spawn(|builder| builder.with_component(Projectile::new()));
+//!     commands.insert(projectile, Velocity::default());
+//!
+//!     for (entity, enemy) in enemies.iter() {
+//!         if enemy.health <= 0 {
+//!             commands.despawn(entity);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::any::TypeId;
+use std::sync::Mutex;
+
+use crate::ecs::entity::EntityBuilder;
+use crate::ecs::resource::ReadRes;
+use crate::ecs::system::SystemData;
+use crate::ecs::world::{World, WorldStorage};
+use crate::ecs::{Component, ComponentStorage, Entity, WriteAccess};
+
+type Command<W> = Box<dyn FnOnce(W) -> W + Send>;
+
+/// Queue of recorded commands, stored as a resource. `DefaultWorld` has one from the start,
+/// other Worlds need to insert a `CommandBuffer<Self>` to use `Commands`.
+pub struct CommandBuffer<W> {
+    commands: Mutex<Vec<Command<W>>>,
+    /// entities reserved by the spawn commands in commands
+    reserved: Mutex<Vec<Entity>>,
+}
+
+impl<W> Default for CommandBuffer<W> {
+    fn default() -> Self {
+        CommandBuffer {
+            commands: Mutex::new(Vec::new()),
+            reserved: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<W> CommandBuffer<W> {
+    pub fn new() -> CommandBuffer<W> {
+        CommandBuffer::default()
+    }
+
+    fn push(&self, command: Command<W>) {
+        self.commands.lock().unwrap().push(command);
+    }
+
+    /// takes all recorded commands, in the order they were recorded
+    pub(crate) fn take(&self) -> Vec<Command<W>> {
+        self.reserved.lock().unwrap().clear();
+
+        std::mem::take(&mut *self.commands.lock().unwrap())
+    }
+
+    /// drops all recorded commands, returns the entities reserved by their spawns
+    pub(crate) fn clear(&self) -> Vec<Entity> {
+        self.commands.lock().unwrap().clear();
+
+        std::mem::take(&mut *self.reserved.lock().unwrap())
+    }
+}
+
+/// Records commands to be applied to the World after dispatch.
+///
+/// Only needs read access to the command buffer, so it doesn't stop systems from running in
+/// parallel. Commands recorded by systems running in parallel are applied in an unspecified
+/// order.
+pub struct Commands<'a, W: 'static> {
+    world: &'a W,
+    buffer: ReadRes<'a, CommandBuffer<W>>,
+}
+
+impl<'a, W: 'static + World> Commands<'a, W> {
+    /// adds a new entity, built by build. The entity is reserved right away, so later commands
+    /// can refer to it, but it's only alive once the commands are applied. If they're cleared
+    /// instead, see `World::clear_commands`, the reservation is released.
+    pub fn spawn<F>(&self, build: F) -> Entity
+    where
+        F: 'static + FnOnce(EntityBuilder<W>) -> EntityBuilder<W> + Send,
+    {
+        let entity = self.world.reserve_entity();
+
+        self.buffer.push(Box::new(move |world: W| {
+            build(world.add_reserved_entity(entity)).build()
+        }));
+        self.buffer.reserved.lock().unwrap().push(entity);
+
+        entity
+    }
+
+    /// removes entity and all its components
+    pub fn despawn(&self, entity: Entity) {
+        self.buffer
+            .push(Box::new(move |world: W| world.remove_entity(entity)));
+    }
+
+    /// sets component of entity, if the entity is still alive when commands are applied
+    pub fn insert<C>(&self, entity: Entity, component: C)
+    where
+        C: 'static + Component,
+        W: WorldStorage<C>,
+    {
+        self.buffer.push(Box::new(move |world: W| {
+            if world.is_alive(entity) {
+                world.storage().write().set(entity, component);
+            }
+
+            world
+        }));
+    }
+
+    /// unsets Component C of entity
+    pub fn remove<C>(&self, entity: Entity)
+    where
+        C: 'static + Component,
+        W: WorldStorage<C>,
+    {
+        self.buffer.push(Box::new(move |world: W| {
+            world.storage().write().unset(entity);
+
+            world
+        }));
+    }
+
+    /// runs command with the whole World, for changes the other commands don't cover
+    pub fn add<F>(&self, command: F)
+    where
+        F: 'static + FnOnce(W) -> W + Send,
+    {
+        self.buffer.push(Box::new(command));
+    }
+}
+
+impl<'access, W: 'static + World> SystemData<'access, W> for Commands<'access, W> {
+    fn get(world: &'access W) -> Self {
+        let buffer = world.resources().read().unwrap_or_else(|| {
+            panic!(
+                "resource {} not found in world",
+                std::any::type_name::<CommandBuffer<W>>()
+            )
+        });
+
+        Commands { world, buffer }
+    }
+
+    fn reads() -> Vec<TypeId> {
+        vec![TypeId::of::<CommandBuffer<W>>()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::system::Read;
+    use crate::ecs::world::DefaultWorld;
+    use crate::ecs::ReadAccess;
+
+    #[derive(Debug, PartialEq)]
+    struct Hp(i32);
+
+    impl Component for Hp {}
+
+    fn world() -> DefaultWorld {
+        DefaultWorld::new()
+            .register::<Hp>()
+            .add_entity()
+            .with_component(Hp(1))
+            .build()
+    }
+
+    #[test]
+    fn commands_wait_for_apply() {
+        let world = world();
+        let entity = world.entities()[0];
+
+        {
+            let commands: Commands<DefaultWorld> = SystemData::get(&world);
+            commands.spawn(|builder| builder.with_component(Hp(2)));
+            commands.remove::<Hp>(entity);
+        }
+
+        assert_eq!(world.entities().len(), 1);
+
+        let world = world.apply_commands();
+        let hp: Read<Hp> = SystemData::get(&world);
+
+        assert_eq!(world.entities().len(), 2);
+        assert_eq!(hp.fetch(entity), None);
+        assert_eq!(
+            hp.iter().map(|(_, hp)| hp).collect::<Vec<_>>(),
+            vec![&Hp(2)]
+        );
+    }
+
+    #[test]
+    fn commands_apply_in_order() {
+        let world = world();
+        let entity = world.entities()[0];
+
+        {
+            let commands: Commands<DefaultWorld> = SystemData::get(&world);
+            commands.insert(entity, Hp(5));
+            commands.despawn(entity);
+            // entity is gone by the time this one is applied
+            commands.insert(entity, Hp(6));
+        }
+
+        let world = world.apply_commands();
+        let hp: Read<Hp> = SystemData::get(&world);
+
+        assert!(!world.is_alive(entity));
+        assert_eq!(hp.iter().count(), 0);
+    }
+
+    #[test]
+    fn apply_empties_the_buffer() {
+        let world = world();
+
+        {
+            let commands: Commands<DefaultWorld> = SystemData::get(&world);
+            commands.add(|world| world.add_entity().build());
+        }
+
+        let world = world.apply_commands().apply_commands();
+
+        assert_eq!(world.entities().len(), 2);
+    }
+
+    #[test]
+    fn spawned_entities_can_be_referred_to() {
+        let world = world();
+
+        let spawned = {
+            let commands: Commands<DefaultWorld> = SystemData::get(&world);
+            // added before the spawn is applied, so it must not get the reserved entity
+            commands.add(|world| world.add_entity().with_component(Hp(4)).build());
+            let spawned = commands.spawn(|builder| builder);
+            commands.insert(spawned, Hp(3));
+            spawned
+        };
+
+        assert!(!world.is_alive(spawned));
+
+        let world = world.apply_commands();
+        let hp: Read<Hp> = SystemData::get(&world);
+
+        assert!(world.is_alive(spawned));
+        assert_eq!(world.entities().len(), 3);
+        assert_eq!(hp.fetch(spawned), Some(&Hp(3)));
+        assert_eq!(hp.iter().count(), 3);
+    }
+
+    #[test]
+    fn default_world_has_a_command_buffer() {
+        let world = DefaultWorld::default().register::<Hp>();
+
+        {
+            let commands: Commands<DefaultWorld> = SystemData::get(&world);
+            commands.spawn(|builder| builder.with_component(Hp(1)));
+        }
+
+        assert_eq!(world.apply_commands().entities().len(), 1);
+    }
+
+    #[test]
+    fn cleared_spawns_release_their_entities() {
+        let world = world();
+
+        let spawned = {
+            let commands: Commands<DefaultWorld> = SystemData::get(&world);
+            let spawned = commands.spawn(|builder| builder.with_component(Hp(2)));
+            commands.insert(spawned, Hp(3));
+            spawned
+        };
+
+        let world = world.clear_commands().apply_commands();
+
+        assert!(!world.is_alive(spawned));
+        assert_eq!(world.entities().len(), 1);
+        assert_eq!(Read::<Hp>::get(&world).iter().count(), 1);
+
+        // the index is free again, but the cleared handle stays stale
+        let builder = world.add_entity();
+        let entity = builder.entity();
+
+        assert_eq!(entity.index(), spawned.index());
+        assert!(!builder.build().is_alive(spawned));
+    }
+}
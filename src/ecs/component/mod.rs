@@ -4,18 +4,23 @@ use std::ops::Deref;
 use std::sync::Arc;
 
 use crate::nphysics::{
-    math::{Isometry, Rotation},
-    object::{BodyStatus, ColliderDesc, DefaultBodyHandle},
+    algebra::ForceType,
+    math::{self, Force, Isometry, Rotation, Vector},
+    ncollide::{pipeline::CollisionGroups, shape::ShapeHandle},
+    object::{BodyPartHandle, BodyStatus, ColliderDesc, DefaultBodyHandle},
 };
-use nalgebra::{Matrix4, RealField};
+use nalgebra::{Matrix4, RealField, Unit};
+use serde::de::DeserializeOwned;
 use serde::export::Formatter;
+use serde::{Deserialize, Serialize};
 
-use crate::ecs::Component;
+use crate::ecs::serialize::{EntityMap, SerializableComponent};
+use crate::ecs::{Component, Entity};
 use crate::gfx::light::DirectionalLight;
 #[cfg(feature = "3d")]
 use crate::gfx::Mesh;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transform<T: RealField>(pub Isometry<T>);
 
 impl<T: RealField> Deref for Transform<T> {
@@ -26,9 +31,104 @@ impl<T: RealField> Deref for Transform<T> {
     }
 }
 
+impl<T: RealField> Transform<T> {
+    /// transformation between previous and this one, alpha 0 being previous and 1 this one
+    pub fn interpolate(&self, previous: &Isometry<T>, alpha: T) -> Isometry<T> {
+        let translation = previous
+            .translation
+            .vector
+            .lerp(&self.0.translation.vector, alpha);
+        let rotation = previous.rotation.rotation_to(&self.0.rotation).powf(alpha);
+
+        Isometry::from_parts(translation.into(), rotation * previous.rotation)
+    }
+}
+
 impl<T: RealField> Component for Transform<T> {}
 
+impl<T: RealField + Serialize + DeserializeOwned> SerializableComponent for Transform<T> {
+    const NAME: &'static str = "Transform";
+}
+
+/// `Transform` as it was before the latest fixed tick, kept by `TransformHistorySystem`.
+/// `TransformSystem` interpolates between the two, so entities that move in fixed ticks are
+/// drawn smoothly at any frame rate.
+#[derive(Clone, Debug)]
+pub struct PreviousTransform<T: RealField>(pub Isometry<T>);
+
+impl<T: RealField> Component for PreviousTransform<T> {}
+
+/// World space transformation, computed each frame by `TransformSystem` from `Transform` and
+/// `Parent`
+#[derive(Clone, Debug)]
+pub struct GlobalTransform<T: RealField>(pub Isometry<T>);
+
+impl<T: RealField> Deref for GlobalTransform<T> {
+    type Target = Isometry<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: RealField> Component for GlobalTransform<T> {}
+
+/// Parent of an entity in the transform hierarchy. The `Transform` of an entity with a Parent is
+/// relative to the parent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent(pub Entity);
+
+impl Component for Parent {}
+
+impl SerializableComponent for Parent {
+    const NAME: &'static str = "Parent";
+
+    fn map_entities(&mut self, entities: &EntityMap) -> bool {
+        // a parent that wasn't saved is gone, and the entity becomes a root
+        match entities.get(self.0) {
+            Some(parent) => {
+                self.0 = parent;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Children of an entity in the transform hierarchy. Maintained by `TransformSystem` from the
+/// `Parent` components, so there is no need to set it yourself.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Children(pub Vec<Entity>);
+
+impl Component for Children {}
+
+impl SerializableComponent for Children {
+    const NAME: &'static str = "Children";
+
+    fn map_entities(&mut self, entities: &EntityMap) -> bool {
+        // children that weren't saved are gone
+        self.0 = self
+            .0
+            .iter()
+            .filter_map(|child| entities.get(*child))
+            .collect();
+
+        !self.0.is_empty()
+    }
+}
+
+/// A rigid body, built by `PhysicsSystem` at the entity's `Transform`. Bodies are stepped in
+/// world space, so the Transform of a body with a `Parent` is converted from and to its parent's
+/// space, and the body follows its parent when the parent moves. `Velocity` is in world space.
+///
+/// Setting the `Transform` moves the body there on the next step, eq. to teleport or respawn it,
+/// and kinematic bodies are moved by their `Velocity`. Changes to status, mass and damping are
+/// applied on the next step as well. Colliders are only read when the body is built.
 pub struct PhysicsBody<T: RealField> {
+    /// shapes of the body. Each is offset from the body by its `ColliderDesc::position`, so one
+    /// body can be made of several shapes, eq. an L-shaped platform out of two cuboids.
+    /// Colliders with `ColliderDesc::sensor` set don't collide, but report what overlaps them as
+    /// `CollisionEvent::SensorEntered` and `SensorExited`, eq. for the hole of a minigolf course.
     pub colliders: Vec<ColliderDesc<T>>,
     pub body_status: BodyStatus,
     pub mass: T,
@@ -45,6 +145,161 @@ impl<T: RealField> std::fmt::Debug for PhysicsBody<T> {
 
 impl<T: RealField> Component for PhysicsBody<T> {}
 
+/// Velocity of a `PhysicsBody`, written by `PhysicsSystem` after every step. Setting it changes
+/// the velocity of the body on the next step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Velocity<T: RealField>(pub math::Velocity<T>);
+
+impl<T: RealField> Deref for Velocity<T> {
+    type Target = math::Velocity<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: RealField> Component for Velocity<T> {}
+
+/// Forces and impulses to apply to a `PhysicsBody` on the next step. Cleared by `PhysicsSystem`
+/// once applied, so forces only last one step.
+///
+/// Everything added since the last step is applied on the next one, so a force added every frame
+/// is applied as many times as frames went by since the last tick, and none at all on the other
+/// ticks of a frame with several. Add continuous forces, like thrust or wind, from fixed ticks,
+/// once per tick. Impulses, like a jump, can be added from any update.
+#[derive(Clone, Debug, Default)]
+pub struct Forces<T: RealField>(pub Vec<(Force<T>, ForceType)>);
+
+impl<T: RealField> Forces<T> {
+    pub fn add_force(&mut self, force: Force<T>) {
+        self.0.push((force, ForceType::Force));
+    }
+
+    /// adds an impulse, which changes the velocity of the body at once
+    pub fn add_impulse(&mut self, impulse: Force<T>) {
+        self.0.push((impulse, ForceType::Impulse));
+    }
+}
+
+impl<T: RealField> Component for Forces<T> {}
+
+/// Joint between a `MultibodyLink` and its parent, or the world for the root link
+#[derive(Clone, Debug)]
+pub enum LinkJoint<T: RealField> {
+    /// moves and rotates freely, for roots that aren't attached to anything
+    Free,
+    /// doesn't move at all
+    Fixed,
+    /// rotates around axis, like a wheel or a hinge, starting at angle
+    #[cfg(feature = "3d")]
+    Revolute { axis: Unit<Vector<T>>, angle: T },
+    /// rotates, like a wheel or a hinge, starting at angle
+    #[cfg(feature = "2d")]
+    Revolute { angle: T },
+    /// slides along axis, like a piston, starting at offset
+    Prismatic { axis: Unit<Vector<T>>, offset: T },
+}
+
+/// A link of a multibody, an articulated body like a car with wheels or a ragdoll, built by
+/// `PhysicsSystem` instead of a `PhysicsBody`.
+///
+/// Every link is an entity of its own, connected to its parent link by a joint. The root link,
+/// the one without a parent, is connected to the world, at its entity's `Transform`. Free and
+/// fixed roots take the whole transform, other joints only the translation. After that, the
+/// `Transform` of every link is written by `PhysicsSystem`, relative to its `Parent` if it has
+/// one, like for a `PhysicsBody`.
+///
+/// Adding or removing a link rebuilds the whole multibody, in its initial position.
+pub struct MultibodyLink<T: RealField> {
+    /// link this one is attached to, None for the root
+    pub parent: Option<Entity>,
+    pub joint: LinkJoint<T>,
+    /// position of the joint, relative to the parent link
+    pub parent_shift: Vector<T>,
+    /// position of this link, relative to the joint
+    pub body_shift: Vector<T>,
+    pub mass: T,
+    /// shapes of this link, each offset from it by its `ColliderDesc::position`
+    pub colliders: Vec<ColliderDesc<T>>,
+    /// the multibody and the index of this link in it, set by `PhysicsSystem`
+    pub handle: Option<BodyPartHandle<DefaultBodyHandle>>,
+}
+
+impl<T: RealField> std::fmt::Debug for MultibodyLink<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultibodyLink")
+            .field("parent", &self.parent)
+            .field("joint", &self.joint)
+            .field("mass", &self.mass)
+            .field("colliders", &self.colliders.len())
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl<T: RealField> Component for MultibodyLink<T> {}
+
+/// A character moved by `CharacterControllerSystem` instead of physics. Walks at
+/// `desired_velocity`, sliding along walls and climbing steps and slopes, and falls by the gravity
+/// of the `PhysicsWorld`.
+///
+/// ```ignore
+/// CharacterController {
+///     step_offset: 0.3,
+///     jump_speed: 5.,
+///     ..CharacterController::new(ShapeHandle::new(Capsule::new(0.5, 0.3)))
+/// }
+/// ```
+pub struct CharacterController<T: RealField> {
+    /// shape of the character, centered at its `Transform`
+    pub shape: ShapeHandle<T>,
+    /// velocity the character walks at, set by gameplay code. The part along gravity is ignored.
+    pub desired_velocity: Vector<T>,
+    /// set to jump on the next update, if grounded. Cleared by the system.
+    pub jump: bool,
+    pub jump_speed: T,
+    /// height of the highest step the character walks up
+    pub step_offset: T,
+    /// steepest slope the character can stand on, in radians
+    pub max_slope: T,
+    /// what the character collides with
+    pub collision_groups: CollisionGroups,
+    /// whether the character is standing on something, set by the system
+    pub grounded: bool,
+    /// speed against gravity, from jumping or falling. Set by the system.
+    pub vertical_speed: T,
+}
+
+impl<T: RealField> CharacterController<T> {
+    /// a character that can't step, jump or stand on slopes steeper than 45 degrees
+    pub fn new(shape: ShapeHandle<T>) -> CharacterController<T> {
+        CharacterController {
+            shape,
+            desired_velocity: Vector::zeros(),
+            jump: false,
+            jump_speed: T::zero(),
+            step_offset: T::zero(),
+            max_slope: T::frac_pi_4(),
+            collision_groups: CollisionGroups::new(),
+            grounded: false,
+            vertical_speed: T::zero(),
+        }
+    }
+}
+
+impl<T: RealField> std::fmt::Debug for CharacterController<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CharacterController")
+            .field("desired_velocity", &self.desired_velocity)
+            .field("jump", &self.jump)
+            .field("grounded", &self.grounded)
+            .field("vertical_speed", &self.vertical_speed)
+            .finish()
+    }
+}
+
+impl<T: RealField> Component for CharacterController<T> {}
+
 #[cfg(feature = "3d")]
 pub struct MeshComponent<M: Mesh + Send + Sync> {
     pub primitives: Vec<Arc<M>>,
@@ -0,0 +1,729 @@
+//! Runs systems, in parallel where their Component access allows it
+
+use std::any::TypeId;
+use std::time::Duration;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::debug::DebugContext;
+use crate::ecs::change::ChangeCursors;
+use crate::ecs::schedule::RunCriteria;
+use crate::ecs::system::{ParallelSystem, ParallelSystemCaller, SystemCaller};
+use crate::ecs::world::World;
+use crate::ecs::System;
+use crate::game::IoState;
+use crate::gfx::RenderContext;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DispatchMode {
+    /// Runs parallel systems that don't conflict with each other on a thread pool
+    Parallel,
+    /// Runs every system on the calling thread, in the order they were added.
+    /// Deterministic, so it's useful for debugging and tests.
+    Sequential,
+}
+
+enum DispatcherSystem<W: World> {
+    /// needs render or debug context, so it can only run on the calling thread
+    ThreadLocal(Box<dyn SystemCaller<W>>),
+    Parallel(Box<dyn ParallelSystemCaller<W>>),
+}
+
+struct DispatcherEntry<W: World> {
+    system: DispatcherSystem<W>,
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+    /// labels other systems can be ordered against, the first one is the system name
+    labels: Vec<&'static str>,
+    /// labels of systems this system must run before
+    before: Vec<&'static str>,
+    /// labels of systems this system must run after
+    after: Vec<&'static str>,
+    run_criteria: Option<RunCriteria<W>>,
+    /// how far the system has read the changes of each Component
+    cursors: ChangeCursors,
+    /// result of run_criteria for the current dispatch
+    enabled: bool,
+    /// position in the order systems were added
+    added: usize,
+    /// systems are run in batches, systems in the same batch don't conflict with each other
+    batch: usize,
+}
+
+impl<W: World> DispatcherEntry<W> {
+    fn conflicts_with(&self, other: &DispatcherEntry<W>) -> bool {
+        self.writes
+            .iter()
+            .any(|c| other.reads.contains(c) || other.writes.contains(c))
+            || other.writes.iter().any(|c| self.reads.contains(c))
+    }
+
+    /// true if this system was explicitly ordered to run after other
+    fn runs_after(&self, other: &DispatcherEntry<W>) -> bool {
+        self.after.iter().any(|label| other.labels.contains(label))
+            || other.before.iter().any(|label| self.labels.contains(label))
+    }
+
+    fn name(&self) -> &'static str {
+        self.labels[0]
+    }
+}
+
+/// Runs systems in the order they were added, except that parallel systems may be run at the
+/// same time as systems they don't conflict with. Two systems conflict if one of them writes a
+/// Component the other one reads or writes.
+///
+/// The order can be changed with `before` and `after`, which refer to the name or `label` of
+/// other systems:
+///
+/// ```ignore
+/// let dispatcher = Dispatcher::new()
+///     .with(CameraSystem::new())
+///     .after("PhysicsSystem")
+///     .with(PhysicsSystem::<f32>::new());
+/// ```
+///
+/// Thread local systems always run on the calling thread, in order.
+pub struct Dispatcher<W: World> {
+    systems: Vec<DispatcherEntry<W>>,
+    mode: DispatchMode,
+    pool: Option<ThreadPool>,
+}
+
+impl<W: World> Default for Dispatcher<W> {
+    fn default() -> Self {
+        Dispatcher {
+            systems: Vec::new(),
+            mode: DispatchMode::Parallel,
+            pool: None,
+        }
+    }
+}
+
+impl<W: World> Dispatcher<W> {
+    pub fn new() -> Dispatcher<W> {
+        Dispatcher::default()
+    }
+
+    /// sets the dispatch mode, see `DispatchMode`
+    pub fn with_mode(self, mode: DispatchMode) -> Dispatcher<W> {
+        Dispatcher { mode, ..self }
+    }
+
+    /// adds a thread local system
+    pub fn with<S: 'static + System<W>>(self, system: S) -> Dispatcher<W> {
+        self.with_boxed(Box::new(system))
+    }
+
+    /// adds an already boxed thread local system
+    pub fn with_boxed(self, system: Box<dyn SystemCaller<W>>) -> Dispatcher<W> {
+        let reads = system.reads();
+        let writes = system.writes();
+
+        self.push(DispatcherSystem::ThreadLocal(system), reads, writes)
+    }
+
+    /// adds a system that can be run on the thread pool
+    pub fn with_parallel<S: 'static + ParallelSystem<W>>(self, system: S) -> Dispatcher<W> {
+        let reads = system.reads();
+        let writes = system.writes();
+
+        self.push(DispatcherSystem::Parallel(Box::new(system)), reads, writes)
+    }
+
+    fn push(
+        self,
+        system: DispatcherSystem<W>,
+        reads: Vec<TypeId>,
+        writes: Vec<TypeId>,
+    ) -> Dispatcher<W> {
+        let Dispatcher { mut systems, .. } = self;
+
+        let name = match &system {
+            DispatcherSystem::ThreadLocal(system) => system.name(),
+            DispatcherSystem::Parallel(system) => system.name(),
+        };
+
+        systems.push(DispatcherEntry {
+            system,
+            reads,
+            writes,
+            labels: vec![name],
+            before: Vec::new(),
+            after: Vec::new(),
+            run_criteria: None,
+            cursors: ChangeCursors::new(),
+            enabled: true,
+            added: systems.len(),
+            batch: 0,
+        });
+
+        Dispatcher { systems, ..self }.sorted()
+    }
+
+    /// gives the last added system another label, besides its name
+    pub fn label(self, label: &'static str) -> Dispatcher<W> {
+        self.with_last(|entry| entry.labels.push(label))
+    }
+
+    /// runs the last added system before the systems with label. Labels that no system has are
+    /// ignored.
+    pub fn before(self, label: &'static str) -> Dispatcher<W> {
+        self.with_last(|entry| entry.before.push(label))
+    }
+
+    /// runs the last added system after the systems with label. Labels that no system has are
+    /// ignored.
+    pub fn after(self, label: &'static str) -> Dispatcher<W> {
+        self.with_last(|entry| entry.after.push(label))
+    }
+
+    /// only runs the last added system when criteria returns true, see `schedule` for common
+    /// criteria. Criteria are checked on the calling thread before any system runs.
+    pub fn run_if<F>(self, criteria: F) -> Dispatcher<W>
+    where
+        F: 'static + FnMut(&W) -> bool + Send,
+    {
+        self.with_last(|entry| entry.run_criteria = Some(Box::new(criteria)))
+    }
+
+    fn with_last<F: FnOnce(&mut DispatcherEntry<W>)>(self, f: F) -> Dispatcher<W> {
+        let Dispatcher { mut systems, .. } = self;
+
+        let last = systems
+            .iter_mut()
+            .max_by_key(|entry| entry.added)
+            .expect("no system added yet");
+        f(last);
+
+        Dispatcher { systems, ..self }.sorted()
+    }
+
+    /// orders systems by when they were added and their before/after constraints, and puts them
+    /// in batches
+    fn sorted(self) -> Dispatcher<W> {
+        let Dispatcher { mut systems, .. } = self;
+
+        systems.sort_by_key(|entry| entry.added);
+
+        // stable topological sort: always take the earliest added system that has nothing left
+        // to wait for
+        let mut remaining: Vec<Option<DispatcherEntry<W>>> =
+            systems.into_iter().map(Some).collect();
+        let mut sorted: Vec<DispatcherEntry<W>> = Vec::with_capacity(remaining.len());
+
+        while sorted.len() < remaining.len() {
+            let next = remaining
+                .iter()
+                .position(|entry| match entry {
+                    Some(entry) => remaining
+                        .iter()
+                        .flatten()
+                        .all(|other| other.added == entry.added || !entry.runs_after(other)),
+                    None => false,
+                })
+                .unwrap_or_else(|| {
+                    let names: Vec<_> = remaining.iter().flatten().map(|e| e.name()).collect();
+                    panic!("systems {:?} are ordered in a cycle", names)
+                });
+
+            sorted.push(remaining[next].take().unwrap());
+        }
+
+        // run after every earlier system we conflict with or were ordered after, and thread
+        // local systems after every earlier thread local system, so their order is kept
+        for i in 0..sorted.len() {
+            let (earlier, rest) = sorted.split_at_mut(i);
+            let entry = &mut rest[0];
+
+            let thread_local = match entry.system {
+                DispatcherSystem::ThreadLocal(_) => true,
+                DispatcherSystem::Parallel(_) => false,
+            };
+
+            entry.batch = earlier
+                .iter()
+                .filter_map(|other| match other.system {
+                    DispatcherSystem::ThreadLocal(_) if thread_local => Some(other.batch),
+                    _ if entry.conflicts_with(other) || entry.runs_after(other) => {
+                        Some(other.batch + 1)
+                    }
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
+        }
+
+        Dispatcher {
+            systems: sorted,
+            ..self
+        }
+    }
+
+    /// names of the systems, in the order they run
+    pub fn system_names(&self) -> Vec<&'static str> {
+        self.systems.iter().map(|entry| entry.name()).collect()
+    }
+
+    /// runs all systems once.
+    ///
+    /// If the World has `Time` or `IoState` resources, they are updated before any system runs.
+    pub fn dispatch(
+        &mut self,
+        world: &W,
+        delta: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) where
+        W: Sync,
+    {
+        world
+            .resources()
+            .begin_frame(delta, render_ctx.alpha, io_state);
+
+        self.run(world, delta, io_state, render_ctx, debug_ctx);
+    }
+
+    /// runs all systems once, without touching resources
+    pub(crate) fn run(
+        &mut self,
+        world: &W,
+        delta: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) where
+        W: Sync,
+    {
+        for entry in &mut self.systems {
+            entry.enabled = match &mut entry.run_criteria {
+                Some(criteria) => criteria(world),
+                None => true,
+            };
+        }
+
+        match self.mode {
+            DispatchMode::Sequential => {
+                for entry in self.systems.iter_mut().filter(|entry| entry.enabled) {
+                    let cursors = &mut entry.cursors;
+
+                    match &mut entry.system {
+                        DispatcherSystem::ThreadLocal(system) => {
+                            system.dispatch(world, cursors, delta, io_state, render_ctx, debug_ctx)
+                        }
+                        DispatcherSystem::Parallel(system) => {
+                            system.dispatch(world, cursors, delta, io_state)
+                        }
+                    }
+                }
+            }
+            DispatchMode::Parallel => {
+                let Dispatcher { systems, pool, .. } = self;
+                let pool = pool.get_or_insert_with(|| {
+                    ThreadPoolBuilder::new()
+                        .build()
+                        .expect("failed to create system thread pool")
+                });
+                let batch_count = systems.iter().map(|entry| entry.batch + 1).max();
+
+                for batch in 0..batch_count.unwrap_or(0) {
+                    pool.in_place_scope(|scope| {
+                        let mut thread_local = Vec::new();
+
+                        let entries = systems
+                            .iter_mut()
+                            .filter(|entry| entry.enabled && entry.batch == batch);
+
+                        for entry in entries {
+                            let cursors = &mut entry.cursors;
+
+                            match &mut entry.system {
+                                DispatcherSystem::Parallel(system) => scope.spawn(move |_| {
+                                    system.dispatch(world, cursors, delta, io_state)
+                                }),
+                                DispatcherSystem::ThreadLocal(system) => {
+                                    thread_local.push((system, cursors))
+                                }
+                            }
+                        }
+
+                        for (system, cursors) in thread_local {
+                            system.dispatch(world, cursors, delta, io_state, render_ctx, debug_ctx);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    /// draws all thread local systems
+    pub fn render(&self, render_ctx: &mut RenderContext) {
+        for entry in &self.systems {
+            if let DispatcherSystem::ThreadLocal(system) = &entry.system {
+                system.render(render_ctx);
+            }
+        }
+    }
+
+    /// draws all thread local systems to views, see `System::draw_to`
+    pub fn render_to(&self, views: &[&wgpu::TextureView], render_ctx: &mut RenderContext) {
+        for entry in &self.systems {
+            if let DispatcherSystem::ThreadLocal(system) = &entry.system {
+                system.render_to(views, render_ctx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::ecs::system::{Changed, Read, SystemData, Write};
+    use crate::ecs::world::DefaultWorld;
+    use crate::ecs::Component;
+
+    #[derive(Debug)]
+    struct A(i32);
+
+    impl Component for A {}
+
+    #[derive(Debug)]
+    struct B(i32);
+
+    impl Component for B {}
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    /// logs its name when run
+    struct Named(&'static str, Log);
+
+    impl ParallelSystem<DefaultWorld> for Named {
+        type SystemData<'a> = ();
+
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn update<'f>(&mut self, _: (), _delta: Duration, _io_state: &IoState) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    /// logs its name when run, on the calling thread
+    struct Local(&'static str, Log);
+
+    impl System<DefaultWorld> for Local {
+        type SystemData<'a> = ();
+
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn update<'f>(
+            &mut self,
+            _: (),
+            _delta: Duration,
+            _io_state: &IoState,
+            _render_ctx: &mut RenderContext,
+            _debug_ctx: &mut DebugContext,
+        ) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    struct IncA;
+
+    impl ParallelSystem<DefaultWorld> for IncA {
+        type SystemData<'a> = Write<'a, A>;
+
+        fn name(&self) -> &'static str {
+            "IncA"
+        }
+
+        fn update<'f>(&mut self, mut a: Write<'f, A>, _delta: Duration, _io_state: &IoState) {
+            for (_, mut a) in a.iter_mut() {
+                a.0 += 1;
+            }
+        }
+    }
+
+    struct DoubleB;
+
+    impl ParallelSystem<DefaultWorld> for DoubleB {
+        type SystemData<'a> = Write<'a, B>;
+
+        fn name(&self) -> &'static str {
+            "DoubleB"
+        }
+
+        fn update<'f>(&mut self, mut b: Write<'f, B>, _delta: Duration, _io_state: &IoState) {
+            for (_, mut b) in b.iter_mut() {
+                b.0 *= 2;
+            }
+        }
+    }
+
+    struct CopyAToB;
+
+    impl ParallelSystem<DefaultWorld> for CopyAToB {
+        type SystemData<'a> = (Read<'a, A>, Write<'a, B>);
+
+        fn name(&self) -> &'static str {
+            "CopyAToB"
+        }
+
+        fn update<'f>(
+            &mut self,
+            (a, mut b): Self::SystemData<'f>,
+            _delta: Duration,
+            _io_state: &IoState,
+        ) {
+            for (entity, a) in a.iter() {
+                b.set(entity, B(a.0));
+            }
+        }
+    }
+
+    /// logs how many A changed since it last ran
+    struct CountChangedA(Arc<Mutex<Vec<usize>>>);
+
+    impl ParallelSystem<DefaultWorld> for CountChangedA {
+        type SystemData<'a> = Changed<'a, A>;
+
+        fn name(&self) -> &'static str {
+            "CountChangedA"
+        }
+
+        fn update<'f>(&mut self, changed: Changed<'f, A>, _delta: Duration, _io_state: &IoState) {
+            self.0.lock().unwrap().push(changed.len());
+        }
+    }
+
+    /// logs how many A changed since it last ran, through two readers
+    struct CountChangedATwice(Arc<Mutex<Vec<(usize, usize)>>>);
+
+    impl ParallelSystem<DefaultWorld> for CountChangedATwice {
+        type SystemData<'a> = (Changed<'a, A>, Changed<'a, A>);
+
+        fn name(&self) -> &'static str {
+            "CountChangedATwice"
+        }
+
+        fn update<'f>(
+            &mut self,
+            (first, second): (Changed<'f, A>, Changed<'f, A>),
+            _delta: Duration,
+            _io_state: &IoState,
+        ) {
+            self.0.lock().unwrap().push((first.len(), second.len()));
+        }
+    }
+
+    fn dispatch(dispatcher: &mut Dispatcher<DefaultWorld>, world: &DefaultWorld) {
+        dispatcher.dispatch(
+            world,
+            Duration::from_secs(0),
+            &IoState::default(),
+            &mut RenderContext::null((0, 0)),
+            &mut DebugContext::null(),
+        );
+    }
+
+    fn batches(dispatcher: &Dispatcher<DefaultWorld>) -> Vec<(&'static str, usize)> {
+        dispatcher
+            .systems
+            .iter()
+            .map(|entry| (entry.name(), entry.batch))
+            .collect()
+    }
+
+    #[test]
+    fn conflicting_systems_get_later_batches() {
+        let dispatcher = Dispatcher::<DefaultWorld>::new()
+            .with_parallel(IncA)
+            .with_parallel(DoubleB)
+            .with_parallel(CopyAToB);
+
+        assert_eq!(
+            batches(&dispatcher),
+            vec![("IncA", 0), ("DoubleB", 0), ("CopyAToB", 1)]
+        );
+    }
+
+    #[test]
+    fn thread_local_systems_keep_their_order() {
+        let log = Log::default();
+        let dispatcher = Dispatcher::<DefaultWorld>::new()
+            .with(Local("a", log.clone()))
+            .with_parallel(Named("b", log.clone()))
+            .with(Local("c", log.clone()));
+
+        assert_eq!(batches(&dispatcher), vec![("a", 0), ("b", 0), ("c", 0)]);
+
+        let mut dispatcher = dispatcher.with_mode(DispatchMode::Parallel);
+        dispatch(&mut dispatcher, &DefaultWorld::new());
+
+        let log = log.lock().unwrap();
+        let local: Vec<_> = log.iter().filter(|name| **name != "b").collect();
+        assert_eq!(local, vec![&"a", &"c"]);
+    }
+
+    #[test]
+    fn before_and_after_reorder_systems() {
+        let log = Log::default();
+        let dispatcher = Dispatcher::<DefaultWorld>::new()
+            .with_parallel(Named("c", log.clone()))
+            .after("b")
+            .with_parallel(Named("a", log.clone()))
+            .with_parallel(Named("b", log.clone()))
+            .with_parallel(Named("x", log.clone()))
+            .label("first")
+            .before("a");
+
+        // earliest added system that isn't waiting for anything goes first
+        assert_eq!(dispatcher.system_names(), vec!["b", "c", "x", "a"]);
+        assert_eq!(
+            batches(&dispatcher),
+            vec![("b", 0), ("c", 1), ("x", 0), ("a", 1)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ordered in a cycle")]
+    fn ordering_cycle_panics() {
+        let log = Log::default();
+        let _ = Dispatcher::<DefaultWorld>::new()
+            .with_parallel(Named("a", log.clone()))
+            .after("b")
+            .with_parallel(Named("b", log.clone()))
+            .after("a");
+    }
+
+    #[test]
+    fn sequential_runs_in_order() {
+        let log = Log::default();
+        let mut dispatcher = Dispatcher::<DefaultWorld>::new()
+            .with_mode(DispatchMode::Sequential)
+            .with_parallel(Named("a", log.clone()))
+            .with(Local("b", log.clone()))
+            .with_parallel(Named("c", log.clone()))
+            .before("a")
+            .with_parallel(Named("d", log.clone()))
+            .run_if(|_| false);
+
+        dispatch(&mut dispatcher, &DefaultWorld::new());
+
+        assert_eq!(*log.lock().unwrap(), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn parallel_gives_the_same_result_as_sequential() {
+        let run = |mode| {
+            let world = DefaultWorld::new()
+                .register::<A>()
+                .register::<B>()
+                .add_entity()
+                .with_component(A(1))
+                .with_component(B(5))
+                .build();
+            let mut dispatcher = Dispatcher::new()
+                .with_mode(mode)
+                .with_parallel(IncA)
+                .with_parallel(DoubleB)
+                .with_parallel(CopyAToB)
+                .with_parallel(DoubleB);
+
+            dispatch(&mut dispatcher, &world);
+
+            let b: Read<B> = SystemData::get(&world);
+            let value = b.iter().next().unwrap().1 .0;
+            value
+        };
+
+        assert_eq!(run(DispatchMode::Sequential), 4);
+        for _ in 0..20 {
+            assert_eq!(run(DispatchMode::Parallel), 4);
+        }
+    }
+
+    #[test]
+    fn changes_stay_visible_until_read() {
+        let counts = Arc::new(Mutex::new(Vec::new()));
+        let enabled = Arc::new(Mutex::new(true));
+        let run = enabled.clone();
+        let mut dispatcher = Dispatcher::<DefaultWorld>::new()
+            .with_parallel(CountChangedA(counts.clone()))
+            .run_if(move |_| *run.lock().unwrap());
+        let world = DefaultWorld::new()
+            .register::<A>()
+            .add_entity()
+            .with_component(A(1))
+            .build();
+
+        dispatch(&mut dispatcher, &world);
+        let world = world.clear_events();
+
+        // written in a frame the system doesn't run, like a fixed tick that's skipped
+        *enabled.lock().unwrap() = false;
+        {
+            let mut a: Write<A> = SystemData::get(&world);
+            for (_, mut a) in a.iter_mut() {
+                a.0 += 1;
+            }
+        }
+        dispatch(&mut dispatcher, &world);
+        let world = world.clear_events();
+
+        *enabled.lock().unwrap() = true;
+        dispatch(&mut dispatcher, &world);
+        let world = world.clear_events();
+        dispatch(&mut dispatcher, &world);
+
+        assert_eq!(*counts.lock().unwrap(), vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn reading_through_writers_changes_nothing() {
+        let counts = Arc::new(Mutex::new(Vec::new()));
+        let mut dispatcher =
+            Dispatcher::<DefaultWorld>::new().with_parallel(CountChangedA(counts.clone()));
+        let world = DefaultWorld::new()
+            .register::<A>()
+            .add_entity()
+            .with_component(A(1))
+            .build();
+
+        dispatch(&mut dispatcher, &world);
+        let world = world.clear_events();
+
+        {
+            let mut a: Write<A> = SystemData::get(&world);
+            let sum: i32 = a.iter_mut().map(|(_, a)| a.0).sum();
+            assert_eq!(sum, 1);
+        }
+        dispatch(&mut dispatcher, &world);
+
+        assert_eq!(*counts.lock().unwrap(), vec![1, 0]);
+    }
+
+    #[test]
+    fn change_readers_have_cursors_of_their_own() {
+        let counts = Arc::new(Mutex::new(Vec::new()));
+        let mut dispatcher =
+            Dispatcher::<DefaultWorld>::new().with_parallel(CountChangedATwice(counts.clone()));
+        let world = DefaultWorld::new()
+            .register::<A>()
+            .add_entity()
+            .with_component(A(1))
+            .build();
+
+        dispatch(&mut dispatcher, &world);
+        let world = world.clear_events();
+        dispatch(&mut dispatcher, &world);
+
+        assert_eq!(*counts.lock().unwrap(), vec![(1, 1), (0, 0)]);
+    }
+}
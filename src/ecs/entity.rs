@@ -1,15 +1,27 @@
 //! Entity related stuff
 
+use std::sync::Mutex;
+
 use serde::export::Formatter;
+use serde::{Deserialize, Serialize};
 
+use crate::asset::prefab::Prefab;
+use crate::ecs::component::Parent;
+use crate::ecs::serialize::{SerializeError, WorldSerializer};
 use crate::ecs::world::{World, WorldStorage};
 use crate::ecs::{Component, ComponentStorage, WriteAccess};
 
+const INDEX_MASK: u64 = 0x00_0f_ff_ff_ff_ff_ff_ff;
+const GENERATION_SHIFT: u64 = 52;
+const GENERATION_MASK: u64 = 0x7_ff;
+const DEAD_BIT: u64 = 1 << 63;
+
 /// Entities are just very complicated 64 bit numbers
-/// First 52 bits are the unique identity of this entity.
+/// First 52 bits are the index of this entity.
+/// Next 11 bits are the generation of that index, which gets bumped every time the index is
+/// recycled by the `EntityAllocator`.
 /// Last bit (at position 63) tells if this entity is alive or dead.
-/// Rest are reserved.
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Entity(pub u64);
 
 impl Entity {
@@ -17,12 +29,35 @@ impl Entity {
         Entity(id)
     }
 
+    /// constructs an Entity from index and generation
+    pub fn with_generation(index: usize, generation: u16) -> Entity {
+        Entity(
+            (index as u64 & INDEX_MASK)
+                | ((generation as u64 & GENERATION_MASK) << GENERATION_SHIFT),
+        )
+    }
+
+    /// index of this entity, used to index component storages
+    pub fn index(&self) -> usize {
+        usize::from(self)
+    }
+
+    /// generation of this entity's index
+    pub fn generation(&self) -> u16 {
+        ((self.0 >> GENERATION_SHIFT) & GENERATION_MASK) as u16
+    }
+
     pub fn is_dead(&self) -> bool {
         self.0 >> 63 == 1
     }
 
     pub fn kill(&mut self) -> Entity {
-        Entity(self.0 | 1 << 63)
+        Entity(self.0 | DEAD_BIT)
+    }
+
+    /// returns true if both entities point to the same index and generation, ignoring the dead bit
+    pub fn is_same(&self, other: Entity) -> bool {
+        self.0 & !DEAD_BIT == other.0 & !DEAD_BIT
     }
 }
 
@@ -30,22 +65,23 @@ impl std::fmt::Debug for Entity {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "[{}{}]",
+            "[{}{}v{}]",
             if self.is_dead() { '*' } else { ' ' },
-            usize::from(self)
+            usize::from(self),
+            self.generation()
         )
     }
 }
 
 impl From<&Entity> for usize {
     fn from(e: &Entity) -> Self {
-        (e.0 & 0x00_0f_ff_ff_ff_ff_ff_ff) as usize
+        (e.0 & INDEX_MASK) as usize
     }
 }
 
 impl From<Entity> for usize {
     fn from(e: Entity) -> Self {
-        (e.0 & 0x00_0f_ff_ff_ff_ff_ff_ff) as usize
+        (e.0 & INDEX_MASK) as usize
     }
 }
 
@@ -55,6 +91,117 @@ impl From<usize> for Entity {
     }
 }
 
+/// Hands out entities, recycling the indices of freed entities.
+///
+/// Every time an index is freed its generation is bumped, so handles to the old entity can be
+/// told apart from the new entity that reuses the index. Generations are 11 bits, so an index
+/// whose generation would wrap back to 0 is retired instead of reused, as its old handles would
+/// otherwise become valid again.
+///
+/// Entities can also be reserved through a shared reference, eq. by `Commands`. A reserved entity
+/// becomes alive when the allocator is next borrowed mutably, see `flush`.
+#[derive(Debug, Default)]
+pub struct EntityAllocator {
+    generations: Vec<u16>,
+    alive: Vec<bool>,
+    free_list: Vec<usize>,
+    reserved: Mutex<Reserved>,
+}
+
+/// Entities handed out by `EntityAllocator::reserve` and not allocated yet. The freed indices are
+/// taken from the end of the free list, the new ones follow the allocated indices.
+#[derive(Debug, Default)]
+struct Reserved {
+    freed: usize,
+    new: usize,
+}
+
+impl EntityAllocator {
+    pub fn new() -> EntityAllocator {
+        EntityAllocator::default()
+    }
+
+    /// reserves an entity that `allocate` won't hand out. It's allocated by the next call that
+    /// borrows the allocator mutably.
+    pub fn reserve(&self) -> Entity {
+        let mut reserved = self.reserved.lock().unwrap();
+
+        if reserved.freed < self.free_list.len() {
+            let index = self.free_list[self.free_list.len() - 1 - reserved.freed];
+            reserved.freed += 1;
+
+            Entity::with_generation(index, self.generations[index])
+        } else {
+            let index = self.generations.len() + reserved.new;
+            reserved.new += 1;
+
+            Entity::with_generation(index, 0)
+        }
+    }
+
+    /// allocates all reserved entities
+    pub fn flush(&mut self) {
+        let reserved = std::mem::take(self.reserved.get_mut().unwrap());
+
+        for _ in 0..reserved.freed {
+            let index = self.free_list.pop().unwrap();
+            self.alive[index] = true;
+        }
+
+        for _ in 0..reserved.new {
+            self.generations.push(0);
+            self.alive.push(true);
+        }
+    }
+
+    /// allocates a new entity, reusing a freed index if there is one
+    pub fn allocate(&mut self) -> Entity {
+        self.flush();
+
+        match self.free_list.pop() {
+            Some(index) => {
+                self.alive[index] = true;
+                Entity::with_generation(index, self.generations[index])
+            }
+            None => {
+                let index = self.generations.len();
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity::with_generation(index, 0)
+            }
+        }
+    }
+
+    /// frees the index of entity for reuse. Returns false if the entity was already freed.
+    pub fn free(&mut self, entity: Entity) -> bool {
+        self.flush();
+
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let index = entity.index();
+        self.alive[index] = false;
+        self.generations[index] = (self.generations[index] + 1) & GENERATION_MASK as u16;
+
+        // retired once all generations are used up
+        if self.generations[index] != 0 {
+            self.free_list.push(index);
+        }
+
+        true
+    }
+
+    /// returns true if entity has been allocated and not freed since
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index();
+
+        index < self.generations.len()
+            && self.alive[index]
+            && self.generations[index] == entity.generation()
+    }
+}
+
 /// Adds new entity, and possible components, to a World.
 pub struct EntityBuilder<W: World> {
     new_entity: Entity,
@@ -68,6 +215,11 @@ impl<W: World> EntityBuilder<W> {
         EntityBuilder { new_entity, world }
     }
 
+    /// the entity being built
+    pub fn entity(&self) -> Entity {
+        self.new_entity
+    }
+
     /// consumes this entity builder, returning the new world
     pub fn build(self) -> W {
         self.world
@@ -92,6 +244,19 @@ impl<W: World> EntityBuilder<W> {
         }
     }
 
+    /// adds the components and child entities of prefab. Components added after this override
+    /// the ones from the prefab.
+    pub fn with_prefab(
+        self,
+        prefab: &Prefab,
+        serializer: &WorldSerializer<W>,
+    ) -> Result<EntityBuilder<W>, SerializeError>
+    where
+        W: WorldStorage<Parent>,
+    {
+        serializer.instantiate(prefab, self)
+    }
+
     /// builds the entity, immediately calling add_entity on the underlying World.
     ///
     /// Allows chaining of `add_entity()` calls.
@@ -99,3 +264,64 @@ impl<W: World> EntityBuilder<W> {
         self.build().add_entity()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_handles_are_stale() {
+        let mut allocator = EntityAllocator::new();
+        let first = allocator.allocate();
+
+        assert!(allocator.free(first));
+        assert!(!allocator.free(first));
+
+        let second = allocator.allocate();
+
+        assert_eq!(first.index(), second.index());
+        assert_ne!(first.generation(), second.generation());
+        assert!(!allocator.is_alive(first));
+        assert!(allocator.is_alive(second));
+    }
+
+    #[test]
+    fn reserved_entities_are_not_handed_out_again() {
+        let mut allocator = EntityAllocator::new();
+        let freed = allocator.allocate();
+        allocator.allocate();
+        allocator.free(freed);
+
+        let (reused, new) = (allocator.reserve(), allocator.reserve());
+
+        assert_eq!(reused.index(), freed.index());
+        assert_ne!(reused, freed);
+        assert!(!allocator.is_alive(reused));
+
+        let allocated = allocator.allocate();
+
+        assert!(allocator.is_alive(reused) && allocator.is_alive(new));
+        assert!(![reused, new].contains(&allocated));
+        assert_eq!((new.index(), allocated.index()), (2, 3));
+    }
+
+    #[test]
+    fn wrapping_generation_retires_index() {
+        let mut allocator = EntityAllocator::new();
+        let first = allocator.allocate();
+        let mut entity = first;
+
+        for _ in 0..GENERATION_MASK {
+            allocator.free(entity);
+            entity = allocator.allocate();
+            assert_eq!(entity.index(), first.index());
+        }
+
+        allocator.free(entity);
+
+        let fresh = allocator.allocate();
+
+        assert_ne!(fresh.index(), first.index());
+        assert!(!allocator.is_alive(first));
+    }
+}
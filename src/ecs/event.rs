@@ -2,6 +2,7 @@
 
 use crate::ecs::Entity;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Event {
     EntityAdded(Entity),
     EntityRemoved(Entity),
@@ -0,0 +1,745 @@
+//! Joins over multiple component storages
+//!
+//! ```ignore
+//! for (entity, (mesh, transform, camera)) in
+//!     (&mesh_reader, &mut transform_writer, Maybe(&camera_reader)).join()
+//! {
+//!     // only entities with both a mesh and a transform end up here
+//! }
+//! ```
+//!
+//! Systems that run the same join every frame over a large world can keep a `JoinCache`, and use
+//! `join_cached` instead. The matching entities are then only searched for again when one of the
+//! storages had components inserted or removed, and are visited in the order of the slots of the
+//! driving storage, so packed storages are read front to back.
+
+use crate::ecs::system::{Added, Changed, Read, ReadStorage, Removed, Write, WriteStorage};
+use crate::ecs::world::WorldStorage;
+use crate::ecs::{Component, Entity, Mut};
+
+/// Something that can be part of a join. Implemented for `&Read`, `&Write`, `&mut Write`,
+/// `&ReadStorage`, `&WriteStorage`, `&mut WriteStorage`, `&Added`, `&Changed`, `&Removed`,
+/// `Maybe`, `Without` and tuples of those.
+///
+/// A join goes over the slots of its smallest required member, and fetches the items of the
+/// other members by entity.
+pub trait Join: Sized {
+    type Item;
+
+    /// number of members, counting the members of nested tuples
+    const MEMBERS: usize = 1;
+
+    /// the member with the fewest slots, which drives the join. None if no member can restrict
+    /// the join (optional and negated members).
+    fn driver(&self) -> Option<Driver>;
+
+    /// entity in slot of member, without fetching its item
+    fn slot_entity(&self, member: usize, slot: usize) -> Option<Entity>;
+
+    /// fetches the item of the entity in slot of member, or None if the slot is empty or the
+    /// entity doesn't match this join
+    ///
+    /// # Safety
+    ///
+    /// See `get`. Member has to be the one returned by `driver`.
+    unsafe fn get_slot(&mut self, member: usize, slot: usize) -> Option<(Entity, Self::Item)>;
+
+    /// fetches the item of entity, or None if the entity doesn't match this member
+    ///
+    /// # Safety
+    ///
+    /// Items of mutable members are references that outlive the `&mut self` borrow they were
+    /// fetched through. The caller must make sure no entity is fetched twice, through `get` or
+    /// `get_slot`, while an item fetched for it before is still alive, as the items would
+    /// alias. The iterators returned by `join` and `join_cached` only visit every entity once.
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item>;
+
+    /// returns true if entity matches this member, without fetching its item
+    fn contains(&self, entity: Entity) -> bool;
+
+    /// structural versions of the storages behind this member, see `ChangeTracker::version`.
+    /// None if the entities matching this member can change without its storages changing, in
+    /// which case the join can't be cached.
+    fn versions(&self) -> Option<Vec<u64>>;
+
+    /// iterates over all entities matching every member of this join. A join without any
+    /// required member, eq. only `Maybe` and `Without` members, matches nothing.
+    fn join(self) -> JoinIter<Self> {
+        let driver = self.driver();
+
+        JoinIter {
+            join: self,
+            driver,
+            slot: 0,
+        }
+    }
+
+    /// like `join`, but reuses the matching slots of the driving member found by the previous
+    /// call with the same cache, unless a storage of this join had components inserted or
+    /// removed since. Only visits matching entities.
+    ///
+    /// A cache must only be used with one join.
+    fn join_cached(self, cache: &mut JoinCache) -> CachedJoinIter<'_, Self> {
+        let versions = self.versions();
+
+        if versions.is_none() || versions != cache.versions {
+            cache.slots.clear();
+            cache.driver = self.driver();
+
+            if let Some(driver) = cache.driver {
+                let join = &self;
+
+                cache.slots.extend((0..driver.slots).filter(|slot| {
+                    join.slot_entity(driver.member, *slot)
+                        .map_or(false, |entity| join.contains(entity))
+                }));
+            }
+            cache.versions = versions;
+        }
+
+        CachedJoinIter {
+            join: self,
+            member: cache.driver.map_or(0, |driver| driver.member),
+            slots: cache.slots.iter(),
+        }
+    }
+}
+
+/// Member driving a join, see `Join::driver`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Driver {
+    /// position of the member in the join, counting the members of nested tuples
+    pub member: usize,
+    /// number of slots in the storage of the member
+    pub slots: usize,
+}
+
+impl Driver {
+    fn new(slots: usize) -> Option<Driver> {
+        Some(Driver { member: 0, slots })
+    }
+}
+
+/// Entities matching a join, kept between frames as the slots they have in the storage of the
+/// driving member. See `Join::join_cached`.
+#[derive(Debug, Default)]
+pub struct JoinCache {
+    versions: Option<Vec<u64>>,
+    driver: Option<Driver>,
+    /// in ascending order
+    slots: Vec<usize>,
+}
+
+impl JoinCache {
+    pub fn new() -> JoinCache {
+        JoinCache::default()
+    }
+
+    /// forces the matching entities to be searched for again on next use
+    pub fn invalidate(&mut self) {
+        self.versions = None;
+    }
+}
+
+/// Iterator over the cached entities of a join, see `Join::join_cached`
+pub struct CachedJoinIter<'c, J: Join> {
+    join: J,
+    /// the driving member
+    member: usize,
+    slots: std::slice::Iter<'c, usize>,
+}
+
+impl<'c, J: Join> Iterator for CachedJoinIter<'c, J> {
+    type Item = (Entity, J::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for slot in &mut self.slots {
+            // cached slots are unique, and all of them matched when the cache was built
+            if let Some(item) = unsafe { self.join.get_slot(self.member, *slot) } {
+                return Some(item);
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.slots.len()))
+    }
+}
+
+/// Iterator over the entities matching a join, see `Join::join`
+pub struct JoinIter<J: Join> {
+    join: J,
+    driver: Option<Driver>,
+    slot: usize,
+}
+
+impl<J: Join> Iterator for JoinIter<J> {
+    type Item = (Entity, J::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let driver = self.driver?;
+
+        while self.slot < driver.slots {
+            let slot = self.slot;
+            self.slot += 1;
+
+            // every slot holds another entity, so none is fetched twice
+            if let Some(item) = unsafe { self.join.get_slot(driver.member, slot) } {
+                return Some(item);
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.driver.map_or(0, |driver| driver.slots - self.slot);
+
+        (0, Some(remaining))
+    }
+}
+
+/// Optional join member, yields `Some(component)` if the entity has the component and `None`
+/// otherwise
+pub struct Maybe<J>(pub J);
+
+/// Negated join member, only matches entities that don't have the component
+pub struct Without<J>(pub J);
+
+impl<'j, 'a: 'j, C: Component> Join for &'j Read<'a, C> {
+    type Item = &'j C;
+
+    fn driver(&self) -> Option<Driver> {
+        Driver::new(self.slots())
+    }
+
+    fn slot_entity(&self, _member: usize, slot: usize) -> Option<Entity> {
+        self.slot(slot).map(|(entity, _)| entity)
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, slot: usize) -> Option<(Entity, Self::Item)> {
+        let reader: &'j Read<'a, C> = *self;
+        reader.slot(slot)
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        let reader: &'j Read<'a, C> = *self;
+        reader.fetch(entity)
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.fetch(entity).is_some()
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        Some(vec![self.changes().version()])
+    }
+}
+
+impl<'j, 'a: 'j, C: Component> Join for &'j Write<'a, C> {
+    type Item = &'j C;
+
+    fn driver(&self) -> Option<Driver> {
+        Driver::new(self.slots())
+    }
+
+    fn slot_entity(&self, _member: usize, slot: usize) -> Option<Entity> {
+        self.slot(slot).map(|(entity, _)| entity)
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, slot: usize) -> Option<(Entity, Self::Item)> {
+        let writer: &'j Write<'a, C> = *self;
+        writer.slot(slot)
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        let writer: &'j Write<'a, C> = *self;
+        writer.fetch(entity)
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.fetch(entity).is_some()
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        Some(vec![self.changes().version()])
+    }
+}
+
+impl<'j, 'a: 'j, C: Component> Join for &'j mut Write<'a, C> {
+    type Item = Mut<'j, C>;
+
+    fn driver(&self) -> Option<Driver> {
+        Driver::new(self.slots())
+    }
+
+    fn slot_entity(&self, _member: usize, slot: usize) -> Option<Entity> {
+        self.slot(slot).map(|(entity, _)| entity)
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, slot: usize) -> Option<(Entity, Self::Item)> {
+        // see get
+        self.slot_mut(slot)
+            .map(|(entity, component)| (entity, component.extend()))
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        // we hold the only borrow of the writer for 'j, and the caller guarantees every entity
+        // is fetched only once, so the references never alias
+        self.get_mut(entity).map(|component| component.extend())
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.fetch(entity).is_some()
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        Some(vec![self.changes().version()])
+    }
+}
+
+impl<'j, 'a: 'j, W, C> Join for &'j ReadStorage<'a, W, C>
+where
+    W: 'a + WorldStorage<C>,
+    C: 'a + Component,
+{
+    type Item = &'j C;
+
+    fn driver(&self) -> Option<Driver> {
+        Driver::new(self.slots())
+    }
+
+    fn slot_entity(&self, _member: usize, slot: usize) -> Option<Entity> {
+        self.slot(slot).map(|(entity, _)| entity)
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, slot: usize) -> Option<(Entity, Self::Item)> {
+        let reader: &'j ReadStorage<'a, W, C> = *self;
+        reader.slot(slot)
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        let reader: &'j ReadStorage<'a, W, C> = *self;
+        reader.fetch(entity)
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.fetch(entity).is_some()
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        Some(vec![self.changes().version()])
+    }
+}
+
+impl<'j, 'a: 'j, W, C> Join for &'j WriteStorage<'a, W, C>
+where
+    W: 'a + WorldStorage<C>,
+    C: 'a + Component,
+{
+    type Item = &'j C;
+
+    fn driver(&self) -> Option<Driver> {
+        Driver::new(self.slots())
+    }
+
+    fn slot_entity(&self, _member: usize, slot: usize) -> Option<Entity> {
+        self.slot(slot).map(|(entity, _)| entity)
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, slot: usize) -> Option<(Entity, Self::Item)> {
+        let writer: &'j WriteStorage<'a, W, C> = *self;
+        writer.slot(slot)
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        let writer: &'j WriteStorage<'a, W, C> = *self;
+        writer.fetch(entity)
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.fetch(entity).is_some()
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        Some(vec![self.changes().version()])
+    }
+}
+
+impl<'j, 'a: 'j, W, C> Join for &'j mut WriteStorage<'a, W, C>
+where
+    W: 'a + WorldStorage<C>,
+    C: 'a + Component,
+{
+    type Item = Mut<'j, C>;
+
+    fn driver(&self) -> Option<Driver> {
+        Driver::new(self.slots())
+    }
+
+    fn slot_entity(&self, _member: usize, slot: usize) -> Option<Entity> {
+        self.slot(slot).map(|(entity, _)| entity)
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, slot: usize) -> Option<(Entity, Self::Item)> {
+        // see get
+        self.slot_mut(slot)
+            .map(|(entity, component)| (entity, component.extend()))
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        // same as for &mut Write
+        self.get_mut(entity).map(|component| component.extend())
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.fetch(entity).is_some()
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        Some(vec![self.changes().version()])
+    }
+}
+
+macro_rules! impl_join_changes {
+    ($($name:ident),*) => {$(
+        impl<'j, 'a: 'j, C> Join for &'j $name<'a, C> {
+            type Item = ();
+
+            fn driver(&self) -> Option<Driver> {
+                Driver::new(self.len())
+            }
+
+            fn slot_entity(&self, _member: usize, slot: usize) -> Option<Entity> {
+                self.entity_at(slot)
+            }
+
+            unsafe fn get_slot(
+                &mut self,
+                _member: usize,
+                slot: usize,
+            ) -> Option<(Entity, Self::Item)> {
+                self.entity_at(slot).map(|entity| (entity, ()))
+            }
+
+            unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+                if Join::contains(self, entity) {
+                    Some(())
+                } else {
+                    None
+                }
+            }
+
+            fn contains(&self, entity: Entity) -> bool {
+                $name::contains(*self, entity)
+            }
+
+            fn versions(&self) -> Option<Vec<u64>> {
+                // every fetch reads other changes, without the storage changing
+                None
+            }
+        }
+    )*};
+}
+
+impl_join_changes!(Added, Changed, Removed);
+
+impl<J: Join> Join for Maybe<J> {
+    type Item = Option<J::Item>;
+
+    fn driver(&self) -> Option<Driver> {
+        None
+    }
+
+    fn slot_entity(&self, _member: usize, _slot: usize) -> Option<Entity> {
+        None
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, _slot: usize) -> Option<(Entity, Self::Item)> {
+        None
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        Some(self.0.get(entity))
+    }
+
+    fn contains(&self, _entity: Entity) -> bool {
+        true
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        // matches every entity, so its storages don't matter
+        self.0.versions().map(|_| Vec::new())
+    }
+}
+
+impl<J: Join> Join for Without<J> {
+    type Item = ();
+
+    fn driver(&self) -> Option<Driver> {
+        None
+    }
+
+    fn slot_entity(&self, _member: usize, _slot: usize) -> Option<Entity> {
+        None
+    }
+
+    unsafe fn get_slot(&mut self, _member: usize, _slot: usize) -> Option<(Entity, Self::Item)> {
+        None
+    }
+
+    unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+        if self.0.contains(entity) {
+            None
+        } else {
+            Some(())
+        }
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        !self.0.contains(entity)
+    }
+
+    fn versions(&self) -> Option<Vec<u64>> {
+        self.0.versions()
+    }
+}
+
+macro_rules! impl_join_tuple {
+    ($($member:ident $value:ident),+) => {
+        impl<$($member: Join),+> Join for ($($member,)+) {
+            type Item = ($($member::Item,)+);
+
+            const MEMBERS: usize = 0 $(+ $member::MEMBERS)+;
+
+            #[allow(unused_assignments)]
+            fn driver(&self) -> Option<Driver> {
+                let ($($value,)+) = self;
+                let mut smallest: Option<Driver> = None;
+                let mut offset = 0;
+
+                $(
+                    if let Some(driver) = $value.driver() {
+                        if smallest.map_or(true, |smallest| driver.slots < smallest.slots) {
+                            smallest = Some(Driver {
+                                member: offset + driver.member,
+                                slots: driver.slots,
+                            });
+                        }
+                    }
+                    offset += $member::MEMBERS;
+                )+
+
+                smallest
+            }
+
+            #[allow(unused_assignments)]
+            fn slot_entity(&self, member: usize, slot: usize) -> Option<Entity> {
+                let ($($value,)+) = self;
+                let mut offset = 0;
+
+                $(
+                    if member < offset + $member::MEMBERS {
+                        return $value.slot_entity(member - offset, slot);
+                    }
+                    offset += $member::MEMBERS;
+                )+
+
+                None
+            }
+
+            #[allow(unused_assignments)]
+            unsafe fn get_slot(
+                &mut self,
+                member: usize,
+                slot: usize,
+            ) -> Option<(Entity, Self::Item)> {
+                let entity = self.slot_entity(member, slot)?;
+                let ($($value,)+) = self;
+                let mut offset = 0;
+
+                // the driving member is fetched by slot, the others by entity
+                Some((entity, ($({
+                    let item = if member >= offset && member < offset + $member::MEMBERS {
+                        $value.get_slot(member - offset, slot)?.1
+                    } else {
+                        $value.get(entity)?
+                    };
+                    offset += $member::MEMBERS;
+
+                    item
+                },)+)))
+            }
+
+            unsafe fn get(&mut self, entity: Entity) -> Option<Self::Item> {
+                let ($($value,)+) = self;
+
+                Some(($($value.get(entity)?,)+))
+            }
+
+            fn contains(&self, entity: Entity) -> bool {
+                let ($($value,)+) = self;
+
+                $($value.contains(entity))&&+
+            }
+
+            fn versions(&self) -> Option<Vec<u64>> {
+                let ($($value,)+) = self;
+                let mut versions = Vec::new();
+
+                $(versions.extend($value.versions()?);)+
+
+                Some(versions)
+            }
+        }
+    };
+}
+
+impl_join_tuple!(A a);
+impl_join_tuple!(A a, B b);
+impl_join_tuple!(A a, B b, C c);
+impl_join_tuple!(A a, B b, C c, D d);
+impl_join_tuple!(A a, B b, C c, D d, E e);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::system::SystemData;
+    use crate::ecs::world::{DefaultWorld, World};
+
+    #[derive(Debug, PartialEq)]
+    struct Pos(i32);
+
+    impl Component for Pos {}
+
+    #[derive(Debug, PartialEq)]
+    struct Vel(i32);
+
+    impl Component for Vel {}
+
+    fn world() -> DefaultWorld {
+        DefaultWorld::new()
+            .register::<Pos>()
+            .register::<Vel>()
+            .add_entity()
+            .with_component(Pos(0))
+            .with_component(Vel(1))
+            .add_entity()
+            .with_component(Pos(10))
+            .add_entity()
+            .with_component(Pos(20))
+            .with_component(Vel(2))
+            .build()
+    }
+
+    #[test]
+    fn join_matches_every_member() {
+        let world = world();
+        let (mut positions, velocities): (Write<Pos>, Read<Vel>) = SystemData::get(&world);
+
+        for (_, (mut position, velocity)) in (&mut positions, &velocities).join() {
+            position.0 += velocity.0;
+        }
+
+        let joined: Vec<_> = (&positions, Maybe(&velocities))
+            .join()
+            .map(|(entity, (position, velocity))| (entity.index(), position.0, velocity.is_some()))
+            .collect();
+
+        assert_eq!(joined, vec![(0, 1, true), (1, 10, false), (2, 22, true)]);
+
+        let without: Vec<_> = (&positions, Without(&velocities))
+            .join()
+            .map(|(entity, _)| entity.index())
+            .collect();
+
+        assert_eq!(without, vec![1]);
+    }
+
+    #[test]
+    fn join_is_driven_by_smallest_member() {
+        let world = DefaultWorld::new()
+            .register::<Pos>()
+            .register::<Vel>()
+            .add_entity()
+            .with_component(Vel(1))
+            .add_entity()
+            .with_component(Pos(10))
+            .build();
+        let (positions, velocities): (Read<Pos>, Read<Vel>) = SystemData::get(&world);
+
+        let driver = (Maybe(&positions), &positions, &velocities).driver();
+
+        assert_eq!(
+            driver.map(|driver| driver.member),
+            Some(2),
+            "velocities take the fewest slots"
+        );
+    }
+
+    #[test]
+    fn join_without_required_member_is_empty() {
+        let world = world();
+        let (positions, velocities): (Read<Pos>, Read<Vel>) = SystemData::get(&world);
+
+        assert_eq!((Maybe(&positions), Without(&velocities)).join().count(), 0);
+        assert_eq!(
+            (Maybe(&positions),)
+                .join_cached(&mut JoinCache::new())
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn join_cache_follows_structural_changes() {
+        let world = world();
+        let mut cache = JoinCache::new();
+        let entity = world.entities()[1];
+
+        {
+            let (positions, velocities): (Read<Pos>, Read<Vel>) = SystemData::get(&world);
+            assert_eq!((&positions, &velocities).join_cached(&mut cache).count(), 2);
+        }
+
+        {
+            let mut velocities: Write<Vel> = SystemData::get(&world);
+            velocities.set(entity, Vel(5));
+        }
+
+        let (positions, velocities): (Read<Pos>, Read<Vel>) = SystemData::get(&world);
+        let joined: Vec<_> = (&positions, &velocities)
+            .join_cached(&mut cache)
+            .map(|(entity, (_, velocity))| (entity.index(), velocity.0))
+            .collect();
+
+        assert_eq!(joined, vec![(0, 1), (1, 5), (2, 2)]);
+    }
+
+    #[test]
+    fn join_cache_visits_slots_in_order() {
+        let world = world();
+        let (mut positions, velocities): (Write<Pos>, Read<Vel>) = SystemData::get(&world);
+        let mut cache = JoinCache::new();
+
+        for _ in 0..2 {
+            for (_, (mut position, velocity)) in
+                (&mut positions, &velocities).join_cached(&mut cache)
+            {
+                position.0 += velocity.0;
+            }
+        }
+
+        assert_eq!(cache.driver.map(|driver| driver.member), Some(0));
+        assert_eq!(cache.slots, vec![0, 2]);
+
+        let joined: Vec<_> = (&positions, &velocities)
+            .join()
+            .map(|(_, (position, _))| position.0)
+            .collect();
+
+        assert_eq!(joined, vec![2, 24]);
+    }
+}
@@ -1,16 +1,34 @@
 //! my own entity component system
 
-use std::collections::VecDeque;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
 use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-pub use entity::Entity;
+pub use change::{ChangeTracker, Mut};
+pub use channel::{EventChannel, ReaderId};
+pub use command::Commands;
+pub use dispatcher::Dispatcher;
+pub use entity::{Entity, EntityAllocator};
 pub use event::Event;
+pub use join::{Join, JoinCache};
+pub use resource::{ReadRes, Resources, WriteRes};
+pub use schedule::Schedule;
 pub use system::System;
 
+pub mod change;
+pub mod channel;
+pub mod command;
 pub mod component;
+pub mod dispatcher;
 pub mod entity;
-mod event;
+pub mod event;
+pub mod join;
+pub mod resource;
+pub mod schedule;
+pub mod serialize;
 pub mod system;
 pub mod world;
 
@@ -26,6 +44,21 @@ pub trait ComponentStorage<C: Component> {
 
     /// writes new component value for entity
     fn write<'w, 'd: 'w>(&'d self) -> Self::Writer<'w>;
+
+    /// changes made to this storage in the last `MAX_AGE` frames, see `ChangeTracker`
+    fn changes(&self) -> &ChangeTracker;
+}
+
+/// Type erased interface for component storages, so Worlds can purge entities from storages
+/// without knowing their Component type.
+pub trait AnyStorage: Any + Send + Sync {
+    /// removes the Component of entity from this storage, if it has one
+    fn remove(&self, entity: Entity);
+
+    /// ends the frame of the changes recorded by this storage, see `ChangeTracker::maintain`
+    fn maintain_changes(&self);
+
+    fn as_any(&self) -> &dyn Any;
 }
 
 /// An interface for Component. Doesn't actually do anything yet, other than make sure our components are sized, and shareable across threads
@@ -38,6 +71,13 @@ pub trait ReadAccess<'access, C: Component> {
     where
         's: 'borrow,
         'access: 's;
+
+    /// number of slots in the storage. Slots are the positions Components are kept at, so
+    /// going over all of them visits every Component once without looking up any entity.
+    fn slots(&self) -> usize;
+
+    /// Component kept in slot, with the Entity it belongs to. None for empty slots.
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)>;
 }
 
 /// An interface that describes write access to a Component
@@ -48,10 +88,23 @@ pub trait WriteAccess<'access, C: Component> {
     /// unsets value of Component for Entity
     fn unset(&mut self, entity: Entity);
 
-    /// mutable iterator over component storage
+    /// returns mutable reference to the Component of Entity, and records a modification
+    fn fetch_mut(&mut self, entity: Entity) -> Option<&mut C> {
+        self.get_mut(entity).map(Mut::into_mut)
+    }
+
+    /// returns the Component of Entity. Records a modification only when written through.
+    fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>>;
+
+    /// returns the Component kept in slot, see `ReadAccess::slots`. Records a modification only
+    /// when written through.
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)>;
+
+    /// mutable iterator over component storage. Records a modification only for the Components
+    /// written through.
     fn iter_mut<'borrow, 's>(
         &'s mut self,
-    ) -> Box<dyn Iterator<Item = (Entity, &'borrow mut C)> + 'borrow>
+    ) -> Box<dyn Iterator<Item = (Entity, Mut<'borrow, C>)> + 'borrow>
     where
         's: 'borrow,
         'access: 's;
@@ -64,18 +117,25 @@ pub trait RwAccess<'a, C: Component>: ReadAccess<'a, C> + WriteAccess<'a, C> {}
 
 // Storage types
 
+type VecData<C> = Vec<Option<(Entity, C)>>;
+
 /// Sparse vector storage. Possible the fastest type in terms of read access.
 /// Write access can be horrible slow (might need to reallocate large vectors), and has bad
 /// memory usage.
+///
+/// Each slot remembers the entity it was set for, so stale entities from an older generation of
+/// the same index are never returned.
 #[derive(Debug)]
 pub struct VecStorage<C: Component + Debug> {
-    data: RwLock<Vec<Option<C>>>,
+    data: RwLock<VecData<C>>,
+    changes: ChangeTracker,
 }
 
 impl<C: Component + Debug> Default for VecStorage<C> {
     fn default() -> Self {
         VecStorage {
             data: RwLock::new(Vec::new()),
+            changes: ChangeTracker::new(),
         }
     }
 }
@@ -86,13 +146,28 @@ impl<C: Component + Debug> VecStorage<C> {
     }
 }
 
+fn vec_slot<C>(data: &VecData<C>, slot: usize) -> Option<(Entity, &C)> {
+    data.get(slot)?
+        .as_ref()
+        .map(|(entity, component)| (*entity, component))
+}
+
+fn vec_fetch<C>(data: &VecData<C>, entity: Entity) -> Option<&C> {
+    match data.get(entity.index()) {
+        Some(Some((other, component))) if other.generation() == entity.generation() => {
+            Some(component)
+        }
+        _ => None,
+    }
+}
+
 /// Read accessor for VecStorage
 pub struct VecReader<'r, C: 'r> {
-    data: RwLockReadGuard<'r, Vec<Option<C>>>,
+    data: RwLockReadGuard<'r, VecData<C>>,
 }
 
 impl<'r, C> VecReader<'r, C> {
-    pub fn new(data: RwLockReadGuard<'r, Vec<Option<C>>>) -> VecReader<'r, C> {
+    pub fn new(data: RwLockReadGuard<'r, VecData<C>>) -> VecReader<'r, C> {
         VecReader { data }
     }
 }
@@ -100,7 +175,7 @@ impl<'r, C> VecReader<'r, C> {
 // ReadAccess requires the struct to implement IntoIterator
 impl<'v: 'r, 'r, C: 'v + Component> ReadAccess<'r, C> for VecReader<'v, C> {
     fn fetch(&self, entity: Entity) -> Option<&C> {
-        self.data.get(usize::from(entity)).unwrap_or(&None).as_ref()
+        vec_fetch(&self.data, entity)
     }
 
     fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
@@ -111,29 +186,40 @@ impl<'v: 'r, 'r, C: 'v + Component> ReadAccess<'r, C> for VecReader<'v, C> {
         Box::new(
             self.data
                 .iter()
-                .enumerate()
-                .filter_map(|(index, maybe_val)| match maybe_val {
-                    Some(val) => Some((index.into(), val)),
-                    None => None,
-                }),
+                .filter_map(|slot| slot.as_ref().map(|(entity, val)| (*entity, val))),
         )
     }
+
+    fn slots(&self) -> usize {
+        self.data.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        vec_slot(&self.data, slot)
+    }
 }
 
 /// Write access to a VecStorage. Uses mutable borrow so there can only exists one writer at a time.
 pub struct VecWriter<'v, C> {
-    data: RwLockWriteGuard<'v, Vec<Option<C>>>,
+    data: RwLockWriteGuard<'v, VecData<C>>,
+    changes: &'v ChangeTracker,
 }
 
 impl<'v, C: Component> VecWriter<'v, C> {
-    pub fn new(data: RwLockWriteGuard<'v, Vec<Option<C>>>) -> VecWriter<'v, C> {
-        VecWriter { data }
+    pub fn new(
+        data: RwLockWriteGuard<'v, VecData<C>>,
+        changes: &'v ChangeTracker,
+    ) -> VecWriter<'v, C> {
+        VecWriter { data, changes }
     }
 }
 
 impl<'v: 'w, 'w, C: Component> WriteAccess<'w, C> for VecWriter<'v, C> {
     fn set(&mut self, entity: Entity, value: C) {
-        let index = entity.into();
+        let index = entity.index();
+
+        self.changes
+            .set(entity, vec_fetch(&self.data, entity).is_some());
 
         let cap = self.data.capacity();
         if cap <= index {
@@ -144,44 +230,66 @@ impl<'v: 'w, 'w, C: Component> WriteAccess<'w, C> for VecWriter<'v, C> {
             for _ in 0..index - self.data.len() {
                 self.data.push(None);
             }
-            self.data.push(Some(value));
+            self.data.push(Some((entity, value)));
         } else {
-            self.data[index] = Some(value);
+            self.data[index] = Some((entity, value));
         }
     }
 
     fn unset(&mut self, entity: Entity) {
-        let index = entity.into();
+        // only unset if the slot belongs to this generation of the entity
+        if let Some(slot) = self.data.get_mut(entity.index()) {
+            if let Some((other, _)) = slot {
+                if other.generation() == entity.generation() {
+                    *slot = None;
+                    self.changes.removed(entity);
+                }
+            }
+        }
+    }
 
-        // Can't be in this storage, so return early.
-        if self.data.capacity() <= index {
-            return;
+    fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>> {
+        match self.data.get_mut(entity.index()) {
+            Some(Some((other, component))) if other.generation() == entity.generation() => {
+                Some(Mut::new(entity, component, self.changes))
+            }
+            _ => None,
         }
+    }
 
-        self.data[index] = None;
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)> {
+        let (entity, component) = self.data.get_mut(slot)?.as_mut()?;
+
+        Some((*entity, Mut::new(*entity, component, self.changes)))
     }
 
-    fn iter_mut<'a, 's>(&'s mut self) -> Box<dyn Iterator<Item = (Entity, &'a mut C)> + 'a>
+    fn iter_mut<'a, 's>(&'s mut self) -> Box<dyn Iterator<Item = (Entity, Mut<'a, C>)> + 'a>
     where
         's: 'a,
         'w: 's,
     {
-        Box::new(self.data.iter_mut().enumerate().filter_map(
-            |(index, maybe_val)| match maybe_val {
-                Some(val) => Some((index.into(), val)),
-                None => None,
-            },
-        ))
+        let changes = self.changes;
+
+        Box::new(
+            self.data
+                .iter_mut()
+                .filter_map(|slot| slot.as_mut().map(|(entity, val)| (*entity, val)))
+                .map(move |(entity, component)| (entity, Mut::new(entity, component, changes))),
+        )
     }
 
     fn clear(&mut self) {
+        for (entity, _) in self.data.iter().flatten() {
+            self.changes.removed(*entity);
+        }
+
         self.data.clear();
     }
 }
 
 impl<'v: 'w, 'w, C: Component> ReadAccess<'w, C> for VecWriter<'v, C> {
     fn fetch(&self, entity: Entity) -> Option<&C> {
-        self.data.get(usize::from(entity)).unwrap_or(&None).as_ref()
+        vec_fetch(&self.data, entity)
     }
 
     fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
@@ -192,13 +300,17 @@ impl<'v: 'w, 'w, C: Component> ReadAccess<'w, C> for VecWriter<'v, C> {
         Box::new(
             self.data
                 .iter()
-                .enumerate()
-                .filter_map(|(index, maybe_val)| match maybe_val {
-                    Some(val) => Some((index.into(), val)),
-                    None => None,
-                }),
+                .filter_map(|slot| slot.as_ref().map(|(entity, val)| (*entity, val))),
         )
     }
+
+    fn slots(&self) -> usize {
+        self.data.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        vec_slot(&self.data, slot)
+    }
 }
 
 impl<'v: 'w, 'w, C: Component> RwAccess<'w, C> for VecWriter<'v, C> {}
@@ -217,7 +329,25 @@ impl<C: 'static + Component + Debug> ComponentStorage<C> for VecStorage<C> {
     }
 
     fn write<'w, 'd: 'w>(&'d self) -> Self::Writer<'w> {
-        VecWriter::new(self.data.write().unwrap())
+        VecWriter::new(self.data.write().unwrap(), &self.changes)
+    }
+
+    fn changes(&self) -> &ChangeTracker {
+        &self.changes
+    }
+}
+
+impl<C: 'static + Component + Debug> AnyStorage for VecStorage<C> {
+    fn remove(&self, entity: Entity) {
+        self.write().unset(entity);
+    }
+
+    fn maintain_changes(&self) {
+        self.changes.maintain();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
@@ -226,13 +356,15 @@ type DequeData<C> = VecDeque<(Entity, C)>;
 /// Deque-based storage for items that get added and cleared of (event-like)
 #[derive(Debug)]
 pub struct DequeStorage<C: Component + Debug> {
-    data: DequeData<C>,
+    data: RwLock<DequeData<C>>,
+    changes: ChangeTracker,
 }
 
 impl<C: Component + Debug> Default for DequeStorage<C> {
     fn default() -> Self {
         DequeStorage {
-            data: VecDeque::new(),
+            data: RwLock::new(VecDeque::new()),
+            changes: ChangeTracker::new(),
         }
     }
 }
@@ -243,25 +375,25 @@ impl<C: Component + Debug> DequeStorage<C> {
     }
 }
 
+fn deque_fetch<C>(data: &DequeData<C>, entity: Entity) -> Option<&C> {
+    data.iter()
+        .find(|(other, _)| other.is_same(entity))
+        .map(|(_, component)| component)
+}
+
 pub struct DequeReader<'d, C> {
-    data: &'d DequeData<C>,
+    data: RwLockReadGuard<'d, DequeData<C>>,
 }
 
 impl<'d, C> DequeReader<'d, C> {
-    pub fn new(data: &'d DequeData<C>) -> DequeReader<'d, C> {
+    pub fn new(data: RwLockReadGuard<'d, DequeData<C>>) -> DequeReader<'d, C> {
         DequeReader { data }
     }
 }
 
 impl<'d: 'r, 'r, C: 'd + Component> ReadAccess<'r, C> for DequeReader<'d, C> {
     fn fetch(&self, entity: Entity) -> Option<&C> {
-        for (other, component) in self.data {
-            if entity == *other {
-                return Some(component);
-            }
-        }
-
-        None
+        deque_fetch(&self.data, entity)
     }
 
     fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
@@ -271,52 +403,95 @@ impl<'d: 'r, 'r, C: 'd + Component> ReadAccess<'r, C> for DequeReader<'d, C> {
     {
         Box::new(self.data.iter().map(|(e, c)| (e.clone(), c)))
     }
+
+    fn slots(&self) -> usize {
+        self.data.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data
+            .get(slot)
+            .map(|(entity, component)| (*entity, component))
+    }
 }
 
 pub struct DequeWriter<'d, C> {
-    data: &'d mut DequeData<C>,
+    data: RwLockWriteGuard<'d, DequeData<C>>,
+    changes: &'d ChangeTracker,
 }
 
 impl<'d, C> DequeWriter<'d, C> {
-    pub fn new(data: &'d mut DequeData<C>) -> DequeWriter<'d, C> {
-        DequeWriter { data }
+    pub fn new(
+        data: RwLockWriteGuard<'d, DequeData<C>>,
+        changes: &'d ChangeTracker,
+    ) -> DequeWriter<'d, C> {
+        DequeWriter { data, changes }
     }
 }
 
 impl<'d: 'w, 'w, C: 'd + Component> WriteAccess<'w, C> for DequeWriter<'d, C> {
     fn set(&mut self, entity: Entity, value: C) {
-        match self.data.iter().position(|(e, _)| *e == entity) {
-            Some(index) => self.data[index] = (entity, value),
-            None => self.data.push_back((entity, value)),
+        match self.data.iter().position(|(e, _)| e.is_same(entity)) {
+            Some(index) => {
+                self.changes.set(entity, true);
+                self.data[index] = (entity, value)
+            }
+            None => {
+                self.changes.set(entity, false);
+                self.data.push_back((entity, value))
+            }
+        }
+    }
+
+    fn unset(&mut self, entity: Entity) {
+        if let Some(index) = self.data.iter().position(|(e, _)| e.is_same(entity)) {
+            self.data.remove(index);
+            self.changes.removed(entity);
         }
     }
 
-    fn unset(&mut self, _entity: Entity) {
-        unimplemented!()
+    fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>> {
+        let changes = self.changes;
+
+        self.data
+            .iter_mut()
+            .find(|(other, _)| other.is_same(entity))
+            .map(|(_, component)| Mut::new(entity, component, changes))
+    }
+
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)> {
+        let (entity, component) = self.data.get_mut(slot)?;
+
+        Some((*entity, Mut::new(*entity, component, self.changes)))
     }
 
-    fn iter_mut<'a, 's>(&'s mut self) -> Box<dyn Iterator<Item = (Entity, &'a mut C)> + 'a>
+    fn iter_mut<'a, 's>(&'s mut self) -> Box<dyn Iterator<Item = (Entity, Mut<'a, C>)> + 'a>
     where
         's: 'a,
         'w: 's,
     {
-        Box::new(self.data.iter_mut().map(|(e, c)| (e.clone(), c)))
+        let changes = self.changes;
+
+        Box::new(
+            self.data
+                .iter_mut()
+                .map(|(e, c)| (e.clone(), c))
+                .map(move |(entity, component)| (entity, Mut::new(entity, component, changes))),
+        )
     }
 
     fn clear(&mut self) {
+        for (entity, _) in self.data.iter() {
+            self.changes.removed(*entity);
+        }
+
         self.data.clear();
     }
 }
 
 impl<'d: 'r, 'r, C: 'd + Component> ReadAccess<'r, C> for DequeWriter<'d, C> {
     fn fetch(&self, entity: Entity) -> Option<&C> {
-        for (other, component) in self.data.iter() {
-            if entity == *other {
-                return Some(component);
-            }
-        }
-
-        None
+        deque_fetch(&self.data, entity)
     }
 
     fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
@@ -326,6 +501,16 @@ impl<'d: 'r, 'r, C: 'd + Component> ReadAccess<'r, C> for DequeWriter<'d, C> {
     {
         Box::new(self.data.iter().map(|(e, c)| (e.clone(), c)))
     }
+
+    fn slots(&self) -> usize {
+        self.data.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data
+            .get(slot)
+            .map(|(entity, component)| (*entity, component))
+    }
 }
 
 impl<'v: 'w, 'w, C: Component> RwAccess<'w, C> for DequeWriter<'v, C> {}
@@ -338,10 +523,900 @@ impl<C: 'static + Component + Debug> ComponentStorage<C> for DequeStorage<C> {
     where
         C: 'r,
     {
-        DequeReader::new(&self.data)
+        DequeReader::new(self.data.read().unwrap())
+    }
+
+    fn write<'w, 'd: 'w>(&'d self) -> Self::Writer<'w> {
+        DequeWriter::new(self.data.write().unwrap(), &self.changes)
+    }
+
+    fn changes(&self) -> &ChangeTracker {
+        &self.changes
+    }
+}
+
+impl<C: 'static + Component + Debug> AnyStorage for DequeStorage<C> {
+    fn remove(&self, entity: Entity) {
+        self.write().unset(entity);
+    }
+
+    fn maintain_changes(&self) {
+        self.changes.maintain();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Packed sparse set, the data behind `SparseSetStorage`.
+///
+/// `sparse` maps entity indices into `dense` and `data`, which are kept packed by swapping the
+/// last element into the hole on removal.
+#[derive(Debug)]
+pub(crate) struct SparseSet<C> {
+    sparse: Vec<Option<usize>>,
+    dense: Vec<Entity>,
+    data: Vec<C>,
+}
+
+impl<C> Default for SparseSet<C> {
+    fn default() -> Self {
+        SparseSet {
+            sparse: Vec::new(),
+            dense: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+}
+
+impl<C> SparseSet<C> {
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let dense_index = (*self.sparse.get(entity.index())?)?;
+
+        if self.dense[dense_index].generation() == entity.generation() {
+            Some(dense_index)
+        } else {
+            None
+        }
+    }
+
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.dense_index(entity).map(move |i| &self.data[i])
+    }
+
+    fn fetch_mut(&mut self, entity: Entity) -> Option<&mut C> {
+        match self.dense_index(entity) {
+            Some(i) => Some(&mut self.data[i]),
+            None => None,
+        }
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        Some((*self.dense.get(slot)?, &self.data[slot]))
+    }
+
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, &mut C)> {
+        Some((*self.dense.get(slot)?, &mut self.data[slot]))
+    }
+
+    fn insert(&mut self, entity: Entity, value: C) {
+        let index = entity.index();
+
+        if self.sparse.len() <= index {
+            self.sparse.resize(index + 1, None);
+        }
+
+        match self.sparse[index] {
+            // replaces older generations too
+            Some(dense_index) => {
+                self.dense[dense_index] = entity;
+                self.data[dense_index] = value;
+            }
+            None => {
+                self.sparse[index] = Some(self.dense.len());
+                self.dense.push(entity);
+                self.data.push(value);
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(dense_index) = self.dense_index(entity) {
+            self.sparse[entity.index()] = None;
+            self.dense.swap_remove(dense_index);
+            self.data.swap_remove(dense_index);
+
+            // fix the index of the element that got swapped into the hole
+            if let Some(moved) = self.dense.get(dense_index) {
+                self.sparse[moved.index()] = Some(dense_index);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.sparse.clear();
+        self.dense.clear();
+        self.data.clear();
+    }
+}
+
+/// Packed sparse set storage. Insert and remove are O(1), and iteration only touches entities
+/// that actually have the component, so this is a good default for most components.
+#[derive(Debug)]
+pub struct SparseSetStorage<C: Component + Debug> {
+    data: RwLock<SparseSet<C>>,
+    changes: ChangeTracker,
+}
+
+impl<C: Component + Debug> Default for SparseSetStorage<C> {
+    fn default() -> Self {
+        SparseSetStorage {
+            data: RwLock::new(SparseSet::default()),
+            changes: ChangeTracker::new(),
+        }
+    }
+}
+
+impl<C: Component + Debug> SparseSetStorage<C> {
+    pub fn new() -> SparseSetStorage<C> {
+        SparseSetStorage::default()
+    }
+}
+
+pub struct SparseSetReader<'r, C> {
+    data: RwLockReadGuard<'r, SparseSet<C>>,
+}
+
+impl<'r, C> SparseSetReader<'r, C> {
+    pub(crate) fn new(data: RwLockReadGuard<'r, SparseSet<C>>) -> SparseSetReader<'r, C> {
+        SparseSetReader { data }
+    }
+}
+
+impl<'v: 'r, 'r, C: 'v + Component> ReadAccess<'r, C> for SparseSetReader<'v, C> {
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.data.fetch(entity)
+    }
+
+    fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
+    where
+        's: 'a,
+        'r: 's,
+    {
+        Box::new(self.data.dense.iter().cloned().zip(self.data.data.iter()))
+    }
+
+    fn slots(&self) -> usize {
+        self.data.dense.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data.slot(slot)
+    }
+}
+
+pub struct SparseSetWriter<'w, C> {
+    data: RwLockWriteGuard<'w, SparseSet<C>>,
+    changes: &'w ChangeTracker,
+}
+
+impl<'w, C> SparseSetWriter<'w, C> {
+    pub(crate) fn new(
+        data: RwLockWriteGuard<'w, SparseSet<C>>,
+        changes: &'w ChangeTracker,
+    ) -> SparseSetWriter<'w, C> {
+        SparseSetWriter { data, changes }
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> WriteAccess<'w, C> for SparseSetWriter<'v, C> {
+    fn set(&mut self, entity: Entity, value: C) {
+        self.changes.set(entity, self.data.fetch(entity).is_some());
+        self.data.insert(entity, value);
+    }
+
+    fn unset(&mut self, entity: Entity) {
+        if self.data.fetch(entity).is_some() {
+            self.data.remove(entity);
+            self.changes.removed(entity);
+        }
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>> {
+        let changes = self.changes;
+
+        self.data
+            .fetch_mut(entity)
+            .map(|component| Mut::new(entity, component, changes))
+    }
+
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)> {
+        let (entity, component) = self.data.slot_mut(slot)?;
+
+        Some((entity, Mut::new(entity, component, self.changes)))
+    }
+
+    fn iter_mut<'a, 's>(&'s mut self) -> Box<dyn Iterator<Item = (Entity, Mut<'a, C>)> + 'a>
+    where
+        's: 'a,
+        'w: 's,
+    {
+        let changes = self.changes;
+        let SparseSet { dense, data, .. } = &mut *self.data;
+
+        Box::new(
+            dense
+                .iter()
+                .cloned()
+                .zip(data.iter_mut())
+                .map(move |(entity, component)| (entity, Mut::new(entity, component, changes))),
+        )
+    }
+
+    fn clear(&mut self) {
+        for entity in self.data.dense.iter() {
+            self.changes.removed(*entity);
+        }
+
+        self.data.clear();
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> ReadAccess<'w, C> for SparseSetWriter<'v, C> {
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.data.fetch(entity)
+    }
+
+    fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
+    where
+        's: 'a,
+        'w: 's,
+    {
+        Box::new(self.data.dense.iter().cloned().zip(self.data.data.iter()))
+    }
+
+    fn slots(&self) -> usize {
+        self.data.dense.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data.slot(slot)
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> RwAccess<'w, C> for SparseSetWriter<'v, C> {}
+
+impl<C: 'static + Component + Debug> ComponentStorage<C> for SparseSetStorage<C> {
+    type Reader<'r> = SparseSetReader<'r, C>;
+    type Writer<'w> = SparseSetWriter<'w, C>;
+
+    fn read<'r, 'd: 'r>(&'d self) -> Self::Reader<'r>
+    where
+        C: 'r,
+    {
+        SparseSetReader::new(self.data.read().unwrap())
     }
 
     fn write<'w, 'd: 'w>(&'d self) -> Self::Writer<'w> {
-        unimplemented!()
+        SparseSetWriter::new(self.data.write().unwrap(), &self.changes)
+    }
+
+    fn changes(&self) -> &ChangeTracker {
+        &self.changes
+    }
+}
+
+impl<C: 'static + Component + Debug> AnyStorage for SparseSetStorage<C> {
+    fn remove(&self, entity: Entity) {
+        self.write().unset(entity);
+    }
+
+    fn maintain_changes(&self) {
+        self.changes.maintain();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Packed components with a HashMap from entity index to their position, the data behind
+/// `HashMapStorage`. Removal swaps the last component into the hole, like `SparseSet`.
+#[derive(Debug)]
+pub(crate) struct HashMapData<C> {
+    indices: HashMap<usize, usize>,
+    data: Vec<(Entity, C)>,
+}
+
+impl<C> Default for HashMapData<C> {
+    fn default() -> Self {
+        HashMapData {
+            indices: HashMap::new(),
+            data: Vec::new(),
+        }
+    }
+}
+
+impl<C> HashMapData<C> {
+    fn position(&self, entity: Entity) -> Option<usize> {
+        let position = *self.indices.get(&entity.index())?;
+
+        if self.data[position].0.generation() == entity.generation() {
+            Some(position)
+        } else {
+            None
+        }
+    }
+
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.position(entity).map(move |i| &self.data[i].1)
+    }
+
+    fn fetch_mut(&mut self, entity: Entity) -> Option<&mut C> {
+        match self.position(entity) {
+            Some(i) => Some(&mut self.data[i].1),
+            None => None,
+        }
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data
+            .get(slot)
+            .map(|(entity, component)| (*entity, component))
+    }
+
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, &mut C)> {
+        self.data
+            .get_mut(slot)
+            .map(|(entity, component)| (*entity, component))
+    }
+
+    fn insert(&mut self, entity: Entity, value: C) {
+        match self.indices.get(&entity.index()) {
+            // replaces older generations too
+            Some(&position) => self.data[position] = (entity, value),
+            None => {
+                self.indices.insert(entity.index(), self.data.len());
+                self.data.push((entity, value));
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(position) = self.position(entity) {
+            self.indices.remove(&entity.index());
+            self.data.swap_remove(position);
+
+            // fix the position of the component that got swapped into the hole
+            if let Some((moved, _)) = self.data.get(position) {
+                self.indices.insert(moved.index(), position);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.indices.clear();
+        self.data.clear();
+    }
+}
+
+/// HashMap storage, keyed by entity index. Uses memory only for entities that have the
+/// component, so it's good for rare components. Iteration order is not defined.
+#[derive(Debug)]
+pub struct HashMapStorage<C: Component + Debug> {
+    data: RwLock<HashMapData<C>>,
+    changes: ChangeTracker,
+}
+
+impl<C: Component + Debug> Default for HashMapStorage<C> {
+    fn default() -> Self {
+        HashMapStorage {
+            data: RwLock::new(HashMapData::default()),
+            changes: ChangeTracker::new(),
+        }
+    }
+}
+
+impl<C: Component + Debug> HashMapStorage<C> {
+    pub fn new() -> HashMapStorage<C> {
+        HashMapStorage::default()
+    }
+}
+
+pub struct HashMapReader<'r, C> {
+    data: RwLockReadGuard<'r, HashMapData<C>>,
+}
+
+impl<'r, C> HashMapReader<'r, C> {
+    pub(crate) fn new(data: RwLockReadGuard<'r, HashMapData<C>>) -> HashMapReader<'r, C> {
+        HashMapReader { data }
+    }
+}
+
+impl<'v: 'r, 'r, C: 'v + Component> ReadAccess<'r, C> for HashMapReader<'v, C> {
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.data.fetch(entity)
+    }
+
+    fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
+    where
+        's: 'a,
+        'r: 's,
+    {
+        Box::new(self.data.data.iter().map(|(entity, val)| (*entity, val)))
+    }
+
+    fn slots(&self) -> usize {
+        self.data.data.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data.slot(slot)
+    }
+}
+
+pub struct HashMapWriter<'w, C> {
+    data: RwLockWriteGuard<'w, HashMapData<C>>,
+    changes: &'w ChangeTracker,
+}
+
+impl<'w, C> HashMapWriter<'w, C> {
+    pub(crate) fn new(
+        data: RwLockWriteGuard<'w, HashMapData<C>>,
+        changes: &'w ChangeTracker,
+    ) -> HashMapWriter<'w, C> {
+        HashMapWriter { data, changes }
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> WriteAccess<'w, C> for HashMapWriter<'v, C> {
+    fn set(&mut self, entity: Entity, value: C) {
+        self.changes.set(entity, self.data.fetch(entity).is_some());
+        self.data.insert(entity, value);
+    }
+
+    fn unset(&mut self, entity: Entity) {
+        if self.data.fetch(entity).is_some() {
+            self.data.remove(entity);
+            self.changes.removed(entity);
+        }
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>> {
+        let changes = self.changes;
+
+        self.data
+            .fetch_mut(entity)
+            .map(|component| Mut::new(entity, component, changes))
+    }
+
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)> {
+        let (entity, component) = self.data.slot_mut(slot)?;
+
+        Some((entity, Mut::new(entity, component, self.changes)))
+    }
+
+    fn iter_mut<'a, 's>(&'s mut self) -> Box<dyn Iterator<Item = (Entity, Mut<'a, C>)> + 'a>
+    where
+        's: 'a,
+        'w: 's,
+    {
+        let changes = self.changes;
+
+        Box::new(
+            self.data
+                .data
+                .iter_mut()
+                .map(|(entity, val)| (*entity, val))
+                .map(move |(entity, component)| (entity, Mut::new(entity, component, changes))),
+        )
+    }
+
+    fn clear(&mut self) {
+        for (entity, _) in self.data.data.iter() {
+            self.changes.removed(*entity);
+        }
+
+        self.data.clear();
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> ReadAccess<'w, C> for HashMapWriter<'v, C> {
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.data.fetch(entity)
+    }
+
+    fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
+    where
+        's: 'a,
+        'w: 's,
+    {
+        Box::new(self.data.data.iter().map(|(entity, val)| (*entity, val)))
+    }
+
+    fn slots(&self) -> usize {
+        self.data.data.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data.slot(slot)
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> RwAccess<'w, C> for HashMapWriter<'v, C> {}
+
+impl<C: 'static + Component + Debug> ComponentStorage<C> for HashMapStorage<C> {
+    type Reader<'r> = HashMapReader<'r, C>;
+    type Writer<'w> = HashMapWriter<'w, C>;
+
+    fn read<'r, 'd: 'r>(&'d self) -> Self::Reader<'r>
+    where
+        C: 'r,
+    {
+        HashMapReader::new(self.data.read().unwrap())
+    }
+
+    fn write<'w, 'd: 'w>(&'d self) -> Self::Writer<'w> {
+        HashMapWriter::new(self.data.write().unwrap(), &self.changes)
+    }
+
+    fn changes(&self) -> &ChangeTracker {
+        &self.changes
+    }
+}
+
+impl<C: 'static + Component + Debug> AnyStorage for HashMapStorage<C> {
+    fn remove(&self, entity: Entity) {
+        self.write().unset(entity);
+    }
+
+    fn maintain_changes(&self) {
+        self.changes.maintain();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Flags of a `FlagStorage`, a bit per entity index and the generation the index was flagged
+/// for. The flags themselves are zero sized, so they aren't stored at all.
+#[derive(Debug)]
+pub(crate) struct FlagData<C> {
+    bits: Vec<u64>,
+    generations: Vec<u16>,
+    marker: PhantomData<C>,
+}
+
+impl<C> Default for FlagData<C> {
+    fn default() -> Self {
+        FlagData {
+            bits: Vec::new(),
+            generations: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<C> FlagData<C> {
+    fn is_set(&self, index: usize) -> bool {
+        self.bits
+            .get(index / 64)
+            .map_or(false, |bits| bits & (1 << (index % 64)) != 0)
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        let index = entity.index();
+
+        self.is_set(index) && self.generations[index] == entity.generation()
+    }
+
+    /// flagged entity in slot, slots being entity indices
+    fn slot(&self, slot: usize) -> Option<Entity> {
+        if self.is_set(slot) {
+            Some(Entity::with_generation(slot, self.generations[slot]))
+        } else {
+            None
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        (0..self.generations.len()).filter_map(move |slot| self.slot(slot))
+    }
+
+    fn insert(&mut self, entity: Entity) {
+        let index = entity.index();
+
+        if self.generations.len() <= index {
+            self.generations.resize(index + 1, 0);
+            self.bits.resize(index / 64 + 1, 0);
+        }
+
+        self.bits[index / 64] |= 1 << (index % 64);
+        self.generations[index] = entity.generation();
+    }
+
+    /// returns false if entity wasn't flagged
+    fn remove(&mut self, entity: Entity) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+
+        let index = entity.index();
+        self.bits[index / 64] &= !(1 << (index % 64));
+
+        true
+    }
+
+    fn clear(&mut self) {
+        self.bits.clear();
+        self.generations.clear();
+    }
+}
+
+/// reference to a flag. Flags are zero sized, so every aligned pointer points to one.
+fn flag<'a, C>() -> &'a C {
+    unsafe { &*NonNull::dangling().as_ptr() }
+}
+
+fn flag_mut<'a, C>() -> &'a mut C {
+    unsafe { &mut *NonNull::dangling().as_ptr() }
+}
+
+/// Storage for zero sized marker components, kept as a bitset. Only the generation of each
+/// flagged entity is stored next to its bit, so it takes a few bytes per entity instead of a
+/// whole `Entity` like `VecStorage`.
+///
+/// Using it for a Component that isn't zero sized, or that has to be dropped, fails to compile.
+#[derive(Debug)]
+pub struct FlagStorage<C: Component + Debug> {
+    data: RwLock<FlagData<C>>,
+    changes: ChangeTracker,
+}
+
+impl<C: Component + Debug> Default for FlagStorage<C> {
+    fn default() -> Self {
+        let () = FlagStorage::<C>::IS_FLAG;
+
+        FlagStorage {
+            data: RwLock::new(FlagData::default()),
+            changes: ChangeTracker::new(),
+        }
+    }
+}
+
+impl<C: Component + Debug> FlagStorage<C> {
+    /// evaluated when the storage is created, so flags can be conjured up instead of stored
+    const IS_FLAG: () = assert!(
+        std::mem::size_of::<C>() == 0 && !std::mem::needs_drop::<C>(),
+        "FlagStorage only supports zero sized components without drop glue"
+    );
+
+    pub fn new() -> FlagStorage<C> {
+        FlagStorage::default()
+    }
+}
+
+pub struct FlagReader<'r, C> {
+    data: RwLockReadGuard<'r, FlagData<C>>,
+}
+
+impl<'r, C> FlagReader<'r, C> {
+    pub(crate) fn new(data: RwLockReadGuard<'r, FlagData<C>>) -> FlagReader<'r, C> {
+        FlagReader { data }
+    }
+}
+
+impl<'v: 'r, 'r, C: 'v + Component> ReadAccess<'r, C> for FlagReader<'v, C> {
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        if self.data.contains(entity) {
+            Some(flag())
+        } else {
+            None
+        }
+    }
+
+    fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
+    where
+        's: 'a,
+        'r: 's,
+    {
+        Box::new(self.data.iter().map(|entity| (entity, flag())))
+    }
+
+    fn slots(&self) -> usize {
+        self.data.generations.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data.slot(slot).map(|entity| (entity, flag()))
+    }
+}
+
+pub struct FlagWriter<'w, C> {
+    data: RwLockWriteGuard<'w, FlagData<C>>,
+    changes: &'w ChangeTracker,
+}
+
+impl<'w, C> FlagWriter<'w, C> {
+    pub(crate) fn new(
+        data: RwLockWriteGuard<'w, FlagData<C>>,
+        changes: &'w ChangeTracker,
+    ) -> FlagWriter<'w, C> {
+        FlagWriter { data, changes }
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> WriteAccess<'w, C> for FlagWriter<'v, C> {
+    fn set(&mut self, entity: Entity, _value: C) {
+        self.changes.set(entity, self.data.contains(entity));
+        self.data.insert(entity);
+    }
+
+    fn unset(&mut self, entity: Entity) {
+        if self.data.remove(entity) {
+            self.changes.removed(entity);
+        }
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>> {
+        if self.data.contains(entity) {
+            Some(Mut::new(entity, flag_mut(), self.changes))
+        } else {
+            None
+        }
+    }
+
+    fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)> {
+        let entity = self.data.slot(slot)?;
+
+        Some((entity, Mut::new(entity, flag_mut(), self.changes)))
+    }
+
+    fn iter_mut<'a, 's>(&'s mut self) -> Box<dyn Iterator<Item = (Entity, Mut<'a, C>)> + 'a>
+    where
+        's: 'a,
+        'w: 's,
+    {
+        let changes = self.changes;
+
+        Box::new(
+            self.data
+                .iter()
+                .map(|entity| (entity, flag_mut()))
+                .map(move |(entity, component)| (entity, Mut::new(entity, component, changes))),
+        )
+    }
+
+    fn clear(&mut self) {
+        for entity in self.data.iter() {
+            self.changes.removed(entity);
+        }
+
+        self.data.clear();
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> ReadAccess<'w, C> for FlagWriter<'v, C> {
+    fn fetch(&self, entity: Entity) -> Option<&C> {
+        if self.data.contains(entity) {
+            Some(flag())
+        } else {
+            None
+        }
+    }
+
+    fn iter<'a, 's>(&'s self) -> Box<dyn Iterator<Item = (Entity, &'a C)> + 'a>
+    where
+        's: 'a,
+        'w: 's,
+    {
+        Box::new(self.data.iter().map(|entity| (entity, flag())))
+    }
+
+    fn slots(&self) -> usize {
+        self.data.generations.len()
+    }
+
+    fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.data.slot(slot).map(|entity| (entity, flag()))
+    }
+}
+
+impl<'v: 'w, 'w, C: Component> RwAccess<'w, C> for FlagWriter<'v, C> {}
+
+impl<C: 'static + Component + Debug> ComponentStorage<C> for FlagStorage<C> {
+    type Reader<'r> = FlagReader<'r, C>;
+    type Writer<'w> = FlagWriter<'w, C>;
+
+    fn read<'r, 'd: 'r>(&'d self) -> Self::Reader<'r>
+    where
+        C: 'r,
+    {
+        FlagReader::new(self.data.read().unwrap())
+    }
+
+    fn write<'w, 'd: 'w>(&'d self) -> Self::Writer<'w> {
+        FlagWriter::new(self.data.write().unwrap(), &self.changes)
+    }
+
+    fn changes(&self) -> &ChangeTracker {
+        &self.changes
+    }
+}
+
+impl<C: 'static + Component + Debug> AnyStorage for FlagStorage<C> {
+    fn remove(&self, entity: Entity) {
+        self.write().unset(entity);
+    }
+
+    fn maintain_changes(&self) {
+        self.changes.maintain();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Pos(i32);
+
+    impl Component for Pos {}
+
+    #[test]
+    fn vec_storage_rejects_stale_handles() {
+        let mut allocator = EntityAllocator::new();
+        let storage = VecStorage::<Pos>::new();
+        let stale = allocator.allocate();
+
+        storage.write().set(stale, Pos(1));
+        allocator.free(stale);
+        let fresh = allocator.allocate();
+        assert_eq!(stale.index(), fresh.index());
+
+        storage.write().set(fresh, Pos(2));
+
+        assert_eq!(storage.read().fetch(stale), None);
+        assert_eq!(storage.read().fetch(fresh), Some(&Pos(2)));
+        assert!(storage.write().fetch_mut(stale).is_none());
+
+        // unsetting through the stale handle leaves the new component alone
+        storage.write().unset(stale);
+        assert_eq!(storage.read().fetch(fresh), Some(&Pos(2)));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Flag;
+
+    impl Component for Flag {}
+
+    #[test]
+    fn flag_storage_sets_bits() {
+        let mut allocator = EntityAllocator::new();
+        let storage = FlagStorage::<Flag>::new();
+        let entities: Vec<Entity> = (0..70).map(|_| allocator.allocate()).collect();
+
+        for entity in entities.iter().step_by(3) {
+            storage.write().set(*entity, Flag);
+        }
+        storage.write().unset(entities[3]);
+
+        let flagged: Vec<usize> = storage.read().iter().map(|(e, _)| e.index()).collect();
+        let expected: Vec<usize> = (0..70).step_by(3).filter(|i| *i != 3).collect();
+
+        assert_eq!(flagged, expected);
+        assert_eq!(storage.read().fetch(entities[69]), Some(&Flag));
+        assert_eq!(storage.read().fetch(entities[68]), None);
+
+        allocator.free(entities[69]);
+        let fresh = allocator.allocate();
+
+        assert_eq!(storage.read().fetch(fresh), None, "stale flag");
     }
 }
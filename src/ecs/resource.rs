@@ -0,0 +1,276 @@
+//! World-level singleton resources, eq. physics world, input state or time
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+use crate::ecs::channel::EventChannel;
+use crate::game::IoState;
+
+type ResourceCell = RwLock<Box<dyn Any + Send + Sync>>;
+
+/// Type map of resources, at most one of each type
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, ResourceCell>,
+    /// maintenance functions of inserted event channels
+    channels: Vec<fn(&Resources)>,
+}
+
+impl Resources {
+    pub fn new() -> Resources {
+        Resources::default()
+    }
+
+    /// inserts resource, replacing the old resource of same type
+    pub fn insert<T: 'static + Send + Sync>(&mut self, resource: T) {
+        self.resources
+            .insert(TypeId::of::<T>(), RwLock::new(Box::new(resource)));
+    }
+
+    /// removes resource of type T, returning it
+    pub fn remove<T: 'static + Send + Sync>(&mut self) -> Option<T> {
+        self.resources.remove(&TypeId::of::<T>()).map(|cell| {
+            let resource: Box<dyn Any> = cell.into_inner().unwrap();
+            *resource.downcast().unwrap()
+        })
+    }
+
+    /// inserts an event channel resource, and has `maintain` maintain it
+    pub fn insert_channel<T: 'static + Send + Sync>(&mut self, channel: EventChannel<T>) {
+        if !self.contains::<EventChannel<T>>() {
+            self.channels.push(maintain_channel::<T>);
+        }
+
+        self.insert(channel);
+    }
+
+    /// maintains all event channels inserted with `insert_channel`. Called once per frame by
+    /// `World::clear_events`.
+    pub fn maintain(&self) {
+        for maintain in &self.channels {
+            maintain(self);
+        }
+    }
+
+    /// updates the `Time` and `IoState` resources, if there are any, before the systems of a
+    /// frame run. Called by `Dispatcher::dispatch` and `Schedule::dispatch`.
+    pub(crate) fn begin_frame(&self, delta: Duration, alpha: f32, io_state: &IoState) {
+        if let Some(mut time) = self.write::<Time>() {
+            time.advance(delta);
+            time.alpha = alpha;
+        }
+        if let Some(mut resource) = self.write::<IoState>() {
+            resource.clone_from(io_state);
+        }
+    }
+
+    pub fn contains<T: 'static + Send + Sync>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    /// read access to resource of type T, or None if there is no such resource
+    pub fn read<T: 'static + Send + Sync>(&self) -> Option<ReadRes<'_, T>> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .map(|cell| ReadRes::new(cell.read().unwrap()))
+    }
+
+    /// write access to resource of type T, or None if there is no such resource
+    pub fn write<T: 'static + Send + Sync>(&self) -> Option<WriteRes<'_, T>> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .map(|cell| WriteRes::new(cell.write().unwrap()))
+    }
+}
+
+fn maintain_channel<T: 'static + Send + Sync>(resources: &Resources) {
+    // the channel might have been removed since
+    if let Some(mut channel) = resources.write::<EventChannel<T>>() {
+        channel.maintain();
+    }
+}
+
+/// Read access to a resource
+pub struct ReadRes<'a, T: 'static> {
+    guard: RwLockReadGuard<'a, Box<dyn Any + Send + Sync>>,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: 'static> ReadRes<'a, T> {
+    fn new(guard: RwLockReadGuard<'a, Box<dyn Any + Send + Sync>>) -> ReadRes<'a, T> {
+        ReadRes {
+            guard,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'static> Deref for ReadRes<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // resources are keyed by their TypeId, so this can't fail
+        (**self.guard).downcast_ref().unwrap()
+    }
+}
+
+/// Write access to a resource
+pub struct WriteRes<'a, T: 'static> {
+    guard: RwLockWriteGuard<'a, Box<dyn Any + Send + Sync>>,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: 'static> WriteRes<'a, T> {
+    fn new(guard: RwLockWriteGuard<'a, Box<dyn Any + Send + Sync>>) -> WriteRes<'a, T> {
+        WriteRes {
+            guard,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'static> Deref for WriteRes<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        (**self.guard).downcast_ref().unwrap()
+    }
+}
+
+impl<'a, T: 'static> DerefMut for WriteRes<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        (**self.guard).downcast_mut().unwrap()
+    }
+}
+
+/// Frame timing resource. Updated by the `Dispatcher` before systems are run.
+#[derive(Clone, Debug, Default)]
+pub struct Time {
+    /// time since last frame
+    pub delta: Duration,
+    /// total time since first frame
+    pub elapsed: Duration,
+    /// number of frames
+    pub frame: u64,
+    /// how far this frame is between the last two fixed ticks, see `RenderContext::alpha`
+    pub alpha: f32,
+}
+
+impl Time {
+    pub fn advance(&mut self, delta: Duration) {
+        self.delta = delta;
+        self.elapsed += delta;
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::system::SystemData;
+    use crate::ecs::world::{DefaultWorld, World};
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+
+    #[test]
+    fn resources_are_read_and_written_in_place() {
+        let mut resources = Resources::new();
+        resources.insert(Score(1));
+
+        resources.write::<Score>().unwrap().0 += 2;
+
+        {
+            // any number of readers at once
+            let (a, b) = (resources.read::<Score>(), resources.read::<Score>());
+            assert_eq!(*a.unwrap(), Score(3));
+            assert_eq!(*b.unwrap(), Score(3));
+        }
+
+        resources.insert(Score(5));
+
+        assert_eq!(resources.remove::<Score>(), Some(Score(5)));
+        assert!(!resources.contains::<Score>());
+        assert!(resources.read::<Score>().is_none());
+        assert!(resources.write::<Score>().is_none());
+    }
+
+    #[test]
+    fn system_data_accesses_world_resources() {
+        let world = DefaultWorld::new().with_resource(Score(1));
+
+        {
+            let mut score: WriteRes<Score> = SystemData::get(&world);
+            score.0 = 4;
+        }
+
+        let score: ReadRes<Score> = SystemData::get(&world);
+        let missing: Option<ReadRes<Time>> = SystemData::get(&world);
+
+        assert_eq!(*score, Score(4));
+        assert!(missing.is_none());
+        assert_eq!(
+            <ReadRes<Score> as SystemData<DefaultWorld>>::reads(),
+            vec![TypeId::of::<Score>()]
+        );
+        assert_eq!(
+            <WriteRes<Score> as SystemData<DefaultWorld>>::writes(),
+            vec![TypeId::of::<Score>()]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not found in world")]
+    fn missing_resources_panic() {
+        let world = DefaultWorld::new();
+
+        let _: ReadRes<Score> = SystemData::get(&world);
+    }
+
+    #[test]
+    fn begin_frame_updates_time_and_io_state() {
+        let world = DefaultWorld::new()
+            .with_resource(Time::default())
+            .with_resource(IoState::default());
+        let mut io_state = IoState::default();
+        io_state.mouse_position = [3., 4.];
+
+        for _ in 0..2 {
+            world
+                .resources()
+                .begin_frame(Duration::from_millis(10), 0.5, &io_state);
+        }
+
+        let time = world.resources().read::<Time>().unwrap();
+
+        assert_eq!(time.delta, Duration::from_millis(10));
+        assert_eq!(time.elapsed, Duration::from_millis(20));
+        assert_eq!(time.frame, 2);
+        assert_eq!(time.alpha, 0.5);
+        assert_eq!(
+            world.resources().read::<IoState>().unwrap().mouse_position,
+            [3., 4.]
+        );
+    }
+
+    #[test]
+    fn maintain_skips_removed_channels() {
+        let mut resources = Resources::new();
+        resources.insert_channel(EventChannel::<u32>::new());
+        resources
+            .write::<EventChannel<u32>>()
+            .unwrap()
+            .single_write(1);
+
+        // no readers, so the event is dropped by maintenance
+        resources.maintain();
+        assert!(resources.read::<EventChannel<u32>>().unwrap().is_empty());
+
+        resources.remove::<EventChannel<u32>>();
+        resources.maintain();
+    }
+}
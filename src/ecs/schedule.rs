@@ -0,0 +1,470 @@
+//! Systems grouped into stages that run one after another
+//!
+//! ```ignore
+//! let mut schedule = Schedule::new()
+//!     .with(stage::PRE_UPDATE, InputSystem::new())
+//!     .with_parallel(stage::UPDATE, MovementSystem::new())
+//!     .with_parallel(stage::UPDATE, AiSystem::new())
+//!     .before("MovementSystem")
+//!     .run_if(schedule::in_state(GameMode::Playing))
+//!     .with_parallel(stage::POST_UPDATE, TransformSystem::<f32>::new())
+//!     .with(stage::RENDER_PREP, SceneSystem::new());
+//!
+//! // systems that run at a fixed rate get a schedule of their own
+//! let mut ticks = Schedule::empty()
+//!     .with_stage(stage::PHYSICS)
+//!     .with_parallel(stage::PHYSICS, TransformHistorySystem::<f32>::new())
+//!     .with(stage::PHYSICS, PhysicsSystem::<f32>::new());
+//!
+//! // in Playable::fixed_update
+//! ticks.tick(&world, step, io_state, render_ctx, debug_ctx);
+//!
+//! // in Playable::update
+//! schedule.dispatch(&world, delta, io_state, render_ctx, debug_ctx);
+//! let world = world.clear_events().apply_commands();
+//! ```
+//!
+//! Fixed ticks are timed by the `Application`, see `Settings::tick_rate`, so all of them share
+//! one clock and `RenderContext::alpha` is right for every system.
+
+use std::time::Duration;
+
+use crate::debug::DebugContext;
+use crate::ecs::dispatcher::{DispatchMode, Dispatcher};
+use crate::ecs::system::{ParallelSystem, System};
+use crate::ecs::world::World;
+use crate::game::IoState;
+use crate::gfx::RenderContext;
+
+/// Decides if a system or stage runs this frame
+pub type RunCriteria<W> = Box<dyn FnMut(&W) -> bool + Send>;
+
+/// Names of the stages every `Schedule::new` starts with, in the order they run
+pub mod stage {
+    /// input handling, and anything else the rest of the frame depends on
+    pub const PRE_UPDATE: &str = "pre_update";
+    /// game logic
+    pub const UPDATE: &str = "update";
+    /// cleanup after game logic, like transform propagation
+    pub const POST_UPDATE: &str = "post_update";
+    /// systems that prepare what gets drawn this frame
+    pub const RENDER_PREP: &str = "render_prep";
+    /// not part of a new `Schedule`, but a good name for a stage run every fixed tick
+    pub const PHYSICS: &str = "physics";
+}
+
+struct Stage<W: World> {
+    name: &'static str,
+    dispatcher: Dispatcher<W>,
+    run_criteria: Option<RunCriteria<W>>,
+}
+
+impl<W: World> Stage<W> {
+    fn new(name: &'static str, mode: DispatchMode) -> Stage<W> {
+        Stage {
+            name,
+            dispatcher: Dispatcher::new().with_mode(mode),
+            run_criteria: None,
+        }
+    }
+}
+
+/// Runs stages of systems in order. Systems in a stage are run by a `Dispatcher`, so they can be
+/// ordered with `before` and `after` and run in parallel, but never across stages.
+///
+/// Builder methods that configure a system, like `label`, `before`, `after` and `run_if`, apply
+/// to the last added system.
+pub struct Schedule<W: World> {
+    stages: Vec<Stage<W>>,
+    mode: DispatchMode,
+    /// stage the last system was added to
+    last: Option<&'static str>,
+}
+
+impl<W: World> Default for Schedule<W> {
+    fn default() -> Self {
+        Schedule::empty().with_stages(&[
+            stage::PRE_UPDATE,
+            stage::UPDATE,
+            stage::POST_UPDATE,
+            stage::RENDER_PREP,
+        ])
+    }
+}
+
+impl<W: World> Schedule<W> {
+    /// creates a schedule with the stages in `stage`, except PHYSICS
+    pub fn new() -> Schedule<W> {
+        Schedule::default()
+    }
+
+    /// creates a schedule without any stages
+    pub fn empty() -> Schedule<W> {
+        Schedule {
+            stages: Vec::new(),
+            mode: DispatchMode::Parallel,
+            last: None,
+        }
+    }
+
+    /// sets the dispatch mode of every stage, see `DispatchMode`
+    pub fn with_mode(self, mode: DispatchMode) -> Schedule<W> {
+        let Schedule { stages, .. } = self;
+
+        let stages = stages
+            .into_iter()
+            .map(|stage| Stage {
+                dispatcher: stage.dispatcher.with_mode(mode),
+                ..stage
+            })
+            .collect();
+
+        Schedule {
+            stages,
+            mode,
+            ..self
+        }
+    }
+
+    fn with_stages(self, names: &[&'static str]) -> Schedule<W> {
+        names
+            .iter()
+            .fold(self, |schedule, name| schedule.with_stage(name))
+    }
+
+    /// adds a stage that runs after all other stages
+    pub fn with_stage(self, name: &'static str) -> Schedule<W> {
+        let index = self.stages.len();
+        self.insert_stage(index, name)
+    }
+
+    /// adds a stage that runs right before stage
+    pub fn with_stage_before(self, stage: &'static str, name: &'static str) -> Schedule<W> {
+        let index = self.stage_index(stage);
+        self.insert_stage(index, name)
+    }
+
+    /// adds a stage that runs right after stage
+    pub fn with_stage_after(self, stage: &'static str, name: &'static str) -> Schedule<W> {
+        let index = self.stage_index(stage) + 1;
+        self.insert_stage(index, name)
+    }
+
+    fn insert_stage(self, index: usize, name: &'static str) -> Schedule<W> {
+        assert!(
+            self.stages.iter().all(|stage| stage.name != name),
+            "stage {} already exists",
+            name
+        );
+
+        let Schedule { mut stages, .. } = self;
+        stages.insert(index, Stage::new(name, self.mode));
+
+        Schedule { stages, ..self }
+    }
+
+    fn stage_index(&self, name: &str) -> usize {
+        self.stages
+            .iter()
+            .position(|stage| stage.name == name)
+            .unwrap_or_else(|| panic!("stage {} not found", name))
+    }
+
+    /// only runs the systems of stage when criteria returns true
+    pub fn with_stage_run_if<F>(self, stage: &'static str, criteria: F) -> Schedule<W>
+    where
+        F: 'static + FnMut(&W) -> bool + Send,
+    {
+        self.with_stage_config(stage, |stage| stage.run_criteria = Some(Box::new(criteria)))
+    }
+
+    fn with_stage_config<F>(self, stage: &'static str, f: F) -> Schedule<W>
+    where
+        F: FnOnce(&mut Stage<W>),
+    {
+        let index = self.stage_index(stage);
+        let Schedule { mut stages, .. } = self;
+
+        f(&mut stages[index]);
+
+        Schedule { stages, ..self }
+    }
+
+    fn with_dispatcher<F>(self, stage: &'static str, f: F) -> Schedule<W>
+    where
+        F: FnOnce(Dispatcher<W>) -> Dispatcher<W>,
+    {
+        let index = self.stage_index(stage);
+        let Schedule { mut stages, .. } = self;
+
+        let stage = &mut stages[index];
+        let dispatcher = std::mem::take(&mut stage.dispatcher);
+        stage.dispatcher = f(dispatcher);
+
+        Schedule { stages, ..self }
+    }
+
+    fn with_last<F>(self, f: F) -> Schedule<W>
+    where
+        F: FnOnce(Dispatcher<W>) -> Dispatcher<W>,
+    {
+        let stage = self.last.expect("no system added yet");
+        self.with_dispatcher(stage, f)
+    }
+
+    /// adds a thread local system to stage
+    pub fn with<S: 'static + System<W>>(self, stage: &'static str, system: S) -> Schedule<W> {
+        Schedule {
+            last: Some(stage),
+            ..self.with_dispatcher(stage, |dispatcher| dispatcher.with(system))
+        }
+    }
+
+    /// adds a system that can be run on the thread pool to stage
+    pub fn with_parallel<S>(self, stage: &'static str, system: S) -> Schedule<W>
+    where
+        S: 'static + ParallelSystem<W>,
+    {
+        Schedule {
+            last: Some(stage),
+            ..self.with_dispatcher(stage, |dispatcher| dispatcher.with_parallel(system))
+        }
+    }
+
+    /// gives the last added system another label, see `Dispatcher::label`
+    pub fn label(self, label: &'static str) -> Schedule<W> {
+        self.with_last(|dispatcher| dispatcher.label(label))
+    }
+
+    /// runs the last added system before the systems with label in the same stage
+    pub fn before(self, label: &'static str) -> Schedule<W> {
+        self.with_last(|dispatcher| dispatcher.before(label))
+    }
+
+    /// runs the last added system after the systems with label in the same stage
+    pub fn after(self, label: &'static str) -> Schedule<W> {
+        self.with_last(|dispatcher| dispatcher.after(label))
+    }
+
+    /// only runs the last added system when criteria returns true
+    pub fn run_if<F>(self, criteria: F) -> Schedule<W>
+    where
+        F: 'static + FnMut(&W) -> bool + Send,
+    {
+        self.with_last(|dispatcher| dispatcher.run_if(criteria))
+    }
+
+    /// names of the stages, in the order they run
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name).collect()
+    }
+
+    /// names of the systems in stage, in the order they run
+    pub fn system_names(&self, stage: &str) -> Vec<&'static str> {
+        self.stages[self.stage_index(stage)]
+            .dispatcher
+            .system_names()
+    }
+
+    /// runs every stage once.
+    ///
+    /// If the World has `Time` or `IoState` resources, they are updated before any stage runs.
+    pub fn dispatch(
+        &mut self,
+        world: &W,
+        delta: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) where
+        W: Sync,
+    {
+        world
+            .resources()
+            .begin_frame(delta, render_ctx.alpha, io_state);
+
+        self.tick(world, delta, io_state, render_ctx, debug_ctx);
+    }
+
+    /// runs every stage like `dispatch`, without updating `Time` or `IoState`. For schedules run
+    /// from `Playable::fixed_update`, with the fixed step as delta.
+    pub fn tick(
+        &mut self,
+        world: &W,
+        delta: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) where
+        W: Sync,
+    {
+        for stage in &mut self.stages {
+            if let Some(criteria) = &mut stage.run_criteria {
+                if !criteria(world) {
+                    continue;
+                }
+            }
+
+            stage
+                .dispatcher
+                .run(world, delta, io_state, render_ctx, debug_ctx);
+        }
+    }
+
+    /// draws all thread local systems, stage by stage
+    pub fn render(&self, render_ctx: &mut RenderContext) {
+        for stage in &self.stages {
+            stage.dispatcher.render(render_ctx);
+        }
+    }
+
+    /// draws all thread local systems to views, stage by stage
+    pub fn render_to(&self, views: &[&wgpu::TextureView], render_ctx: &mut RenderContext) {
+        for stage in &self.stages {
+            stage.dispatcher.render_to(views, render_ctx);
+        }
+    }
+}
+
+/// runs on the first frame, and every n frames after that
+pub fn every_n_frames<W>(n: u64) -> impl FnMut(&W) -> bool + Send {
+    assert!(n > 0, "n must not be zero");
+
+    let mut frame = 0;
+    move |_| {
+        let run = frame % n == 0;
+        frame += 1;
+        run
+    }
+}
+
+/// runs while the World has a resource T equal to state, like a game mode enum
+pub fn in_state<W, T>(state: T) -> impl FnMut(&W) -> bool + Send
+where
+    W: World,
+    T: 'static + PartialEq + Send + Sync,
+{
+    move |world| match world.resources().read::<T>() {
+        Some(current) => *current == state,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::ecs::world::DefaultWorld;
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    /// logs its name when run
+    struct Named(&'static str, Log);
+
+    impl ParallelSystem<DefaultWorld> for Named {
+        type SystemData<'a> = ();
+
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn update<'f>(&mut self, _: (), _delta: Duration, _io_state: &IoState) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    #[derive(PartialEq)]
+    enum Mode {
+        Menu,
+        Playing,
+    }
+
+    fn dispatch(schedule: &mut Schedule<DefaultWorld>, world: &DefaultWorld) {
+        schedule.dispatch(
+            world,
+            Duration::from_millis(10),
+            &IoState::default(),
+            &mut RenderContext::null((0, 0)),
+            &mut DebugContext::null(),
+        );
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let log = Log::default();
+        let mut schedule = Schedule::new()
+            .with_stage_after(stage::UPDATE, stage::PHYSICS)
+            .with_stage_before(stage::PRE_UPDATE, "first")
+            .with_parallel(stage::RENDER_PREP, Named("render_prep", log.clone()))
+            .with_parallel(stage::PHYSICS, Named("physics", log.clone()))
+            .with_parallel(stage::UPDATE, Named("update2", log.clone()))
+            .with_parallel(stage::UPDATE, Named("update1", log.clone()))
+            .before("update2")
+            .with_parallel("first", Named("first", log.clone()));
+
+        assert_eq!(
+            schedule.stage_names(),
+            vec![
+                "first",
+                "pre_update",
+                "update",
+                "physics",
+                "post_update",
+                "render_prep"
+            ]
+        );
+        assert_eq!(
+            schedule.system_names(stage::UPDATE),
+            vec!["update1", "update2"]
+        );
+
+        dispatch(&mut schedule, &DefaultWorld::new());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["first", "update1", "update2", "physics", "render_prep"]
+        );
+    }
+
+    #[test]
+    fn run_criteria_skip_systems_and_stages() {
+        let log = Log::default();
+        let mut world = DefaultWorld::new();
+        world.resources_mut().insert(Mode::Menu);
+
+        let mut schedule = Schedule::new()
+            .with_mode(DispatchMode::Sequential)
+            .with_parallel(stage::UPDATE, Named("every_other", log.clone()))
+            .run_if(every_n_frames(2))
+            .with_parallel(stage::UPDATE, Named("playing", log.clone()))
+            .run_if(in_state(Mode::Playing))
+            .with_parallel(stage::POST_UPDATE, Named("post_update", log.clone()))
+            .with_stage_run_if(stage::POST_UPDATE, in_state(Mode::Menu));
+
+        dispatch(&mut schedule, &world);
+        assert_eq!(*log.lock().unwrap(), vec!["every_other", "post_update"]);
+        log.lock().unwrap().clear();
+
+        *world.resources().write::<Mode>().unwrap() = Mode::Playing;
+        dispatch(&mut schedule, &world);
+        assert_eq!(*log.lock().unwrap(), vec!["playing"]);
+        log.lock().unwrap().clear();
+
+        dispatch(&mut schedule, &world);
+        assert_eq!(*log.lock().unwrap(), vec!["every_other", "playing"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "stage update already exists")]
+    fn duplicate_stages_panic() {
+        let _ = Schedule::<DefaultWorld>::new().with_stage(stage::UPDATE);
+    }
+
+    #[test]
+    #[should_panic(expected = "stage physics not found")]
+    fn systems_need_their_stage() {
+        let log = Log::default();
+        let _ = Schedule::<DefaultWorld>::new().with_parallel(stage::PHYSICS, Named("a", log));
+    }
+}
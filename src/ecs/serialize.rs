@@ -0,0 +1,531 @@
+//! World snapshots, for save games, checkpoints and golden-file tests
+//!
+//! ```ignore
+//! let serializer = WorldSerializer::new()
+//!     .with::<Transform<f32>>()
+//!     .with::<Parent>();
+//!
+//! let json = serializer.to_json(&world)?;
+//! let restored = serializer.from_json(new_world(), &json)?;
+//! ```
+//!
+//! The same registry instantiates `Prefab`s, see `EntityBuilder::with_prefab`.
+//!
+//! Only the components registered with `WorldSerializer::with` are saved. Entities get new ids
+//! when loaded, so components that refer to other entities have to remap them in
+//! `SerializableComponent::map_entities`.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::asset::prefab::Prefab;
+use crate::ecs::component::Parent;
+use crate::ecs::entity::EntityBuilder;
+use crate::ecs::world::{World, WorldStorage};
+use crate::ecs::{Component, ComponentStorage, Entity, ReadAccess, WriteAccess};
+
+/// A Component that can be saved in a world snapshot
+pub trait SerializableComponent: 'static + Component + Serialize + DeserializeOwned {
+    /// name of the component in snapshots. Must be unique, and shouldn't change between
+    /// versions, or old snapshots can't be loaded anymore.
+    const NAME: &'static str;
+
+    /// replaces entities referred to by this component with their new ids, after loading.
+    /// Returns false if the component should be dropped, eq. because the entity it refers to
+    /// wasn't in the snapshot.
+    fn map_entities(&mut self, _entities: &EntityMap) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+pub enum SerializeError {
+    SerdeJsonError(serde_json::Error),
+    BincodeError(bincode::Error),
+    /// snapshot has a component that isn't registered to the WorldSerializer
+    UnknownComponent(String),
+    /// prefab refers to a prefab file that wasn't loaded, see `Prefab::resolve`
+    UnresolvedPrefab(String),
+}
+
+impl From<serde_json::Error> for SerializeError {
+    fn from(err: serde_json::Error) -> Self {
+        SerializeError::SerdeJsonError(err)
+    }
+}
+
+impl From<bincode::Error> for SerializeError {
+    fn from(err: bincode::Error) -> Self {
+        SerializeError::BincodeError(err)
+    }
+}
+
+/// Maps entity ids of a snapshot to the entities created for them when it was loaded
+#[derive(Debug, Default)]
+pub struct EntityMap {
+    entities: HashMap<Entity, Entity>,
+}
+
+impl EntityMap {
+    /// the loaded entity of a saved entity, or None if the entity wasn't part of the snapshot
+    pub fn get(&self, saved: Entity) -> Option<Entity> {
+        self.entities.get(&saved).cloned()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<V> {
+    entities: Vec<Entity>,
+    /// sorted by name, so snapshots of equal worlds are equal
+    components: BTreeMap<String, Vec<(Entity, V)>>,
+}
+
+/// Format of serialized components inside a snapshot
+trait Format {
+    type Value: Serialize + DeserializeOwned;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Self::Value, SerializeError>;
+    fn decode<T: DeserializeOwned>(value: Self::Value) -> Result<T, SerializeError>;
+}
+
+struct Json;
+
+impl Format for Json {
+    type Value = serde_json::Value;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Self::Value, SerializeError> {
+        Ok(serde_json::to_value(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(value: Self::Value) -> Result<T, SerializeError> {
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+struct Binary;
+
+impl Format for Binary {
+    type Value = Vec<u8>;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Self::Value, SerializeError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(value: Self::Value) -> Result<T, SerializeError> {
+        Ok(bincode::deserialize(&value)?)
+    }
+}
+
+type SaveFn<W, V> = fn(&W, &HashSet<Entity>) -> Result<Vec<(Entity, V)>, SerializeError>;
+type DecodeFn<V> = fn(Vec<(Entity, V)>) -> Result<Box<dyn Any>, SerializeError>;
+type InsertFn<W> = fn(&W, Box<dyn Any>, &EntityMap);
+type DecodeOneFn = fn(serde_json::Value) -> Result<Box<dyn Any>, SerializeError>;
+type SetFn<W> = fn(&W, Entity, Box<dyn Any>);
+
+struct ComponentSerializer<W> {
+    save_json: SaveFn<W, serde_json::Value>,
+    save_binary: SaveFn<W, Vec<u8>>,
+    decode_json: DecodeFn<serde_json::Value>,
+    decode_binary: DecodeFn<Vec<u8>>,
+    insert: InsertFn<W>,
+    decode_prefab: DecodeOneFn,
+    set: SetFn<W>,
+}
+
+/// Prefab with its components decoded, ready to be spawned
+struct DecodedPrefab<W> {
+    components: Vec<(SetFn<W>, Box<dyn Any>)>,
+    children: Vec<DecodedPrefab<W>>,
+}
+
+fn save<W, C, F>(
+    world: &W,
+    entities: &HashSet<Entity>,
+) -> Result<Vec<(Entity, F::Value)>, SerializeError>
+where
+    W: WorldStorage<C>,
+    C: SerializableComponent,
+    F: Format,
+{
+    world
+        .storage()
+        .read()
+        .iter()
+        .filter(|(entity, _)| entities.contains(entity))
+        .map(|(entity, component)| Ok((entity, F::encode(component)?)))
+        .collect()
+}
+
+fn decode<C, F>(components: Vec<(Entity, F::Value)>) -> Result<Box<dyn Any>, SerializeError>
+where
+    C: SerializableComponent,
+    F: Format,
+{
+    let components = components
+        .into_iter()
+        .map(|(entity, value)| Ok((entity, F::decode::<C>(value)?)))
+        .collect::<Result<Vec<_>, SerializeError>>()?;
+
+    Ok(Box::new(components))
+}
+
+fn insert<W, C>(world: &W, components: Box<dyn Any>, entities: &EntityMap)
+where
+    W: WorldStorage<C>,
+    C: SerializableComponent,
+{
+    // decoded by `decode::<C, _>`, so this can't fail
+    let components: Box<Vec<(Entity, C)>> = components.downcast().unwrap();
+    let mut writer = world.storage().write();
+
+    for (saved, mut component) in *components {
+        if let Some(entity) = entities.get(saved) {
+            if component.map_entities(entities) {
+                writer.set(entity, component);
+            }
+        }
+    }
+}
+
+fn decode_prefab<C: SerializableComponent>(
+    value: serde_json::Value,
+) -> Result<Box<dyn Any>, SerializeError> {
+    Ok(Box::new(Json::decode::<C>(value)?))
+}
+
+fn set<W, C>(world: &W, entity: Entity, component: Box<dyn Any>)
+where
+    W: WorldStorage<C>,
+    C: SerializableComponent,
+{
+    // decoded by `decode_prefab::<C>`, so this can't fail
+    let component: Box<C> = component.downcast().unwrap();
+
+    world.storage().write().set(entity, *component);
+}
+
+/// Saves and loads snapshots of the entities of a World, and their registered Components
+pub struct WorldSerializer<W: World> {
+    components: BTreeMap<&'static str, ComponentSerializer<W>>,
+}
+
+impl<W: World> Default for WorldSerializer<W> {
+    fn default() -> Self {
+        WorldSerializer {
+            components: BTreeMap::new(),
+        }
+    }
+}
+
+impl<W: World> WorldSerializer<W> {
+    pub fn new() -> WorldSerializer<W> {
+        WorldSerializer::default()
+    }
+
+    /// includes Component C in snapshots and prefabs
+    pub fn with<C>(self) -> WorldSerializer<W>
+    where
+        W: WorldStorage<C>,
+        C: SerializableComponent,
+    {
+        let WorldSerializer { mut components } = self;
+
+        components.insert(
+            C::NAME,
+            ComponentSerializer {
+                save_json: save::<W, C, Json>,
+                save_binary: save::<W, C, Binary>,
+                decode_json: decode::<C, Json>,
+                decode_binary: decode::<C, Binary>,
+                insert: insert::<W, C>,
+                decode_prefab: decode_prefab::<C>,
+                set: set::<W, C>,
+            },
+        );
+
+        WorldSerializer { components }
+    }
+
+    /// saves all living entities of world as pretty printed JSON
+    pub fn to_json(&self, world: &W) -> Result<String, SerializeError> {
+        let snapshot = self.snapshot(world, |c| c.save_json)?;
+
+        Ok(serde_json::to_string_pretty(&snapshot)?)
+    }
+
+    /// saves all living entities of world in a compact binary form
+    pub fn to_binary(&self, world: &W) -> Result<Vec<u8>, SerializeError> {
+        let snapshot = self.snapshot(world, |c| c.save_binary)?;
+
+        Ok(bincode::serialize(&snapshot)?)
+    }
+
+    /// adds the entities saved by `to_json` into world
+    pub fn from_json(&self, world: W, json: &str) -> Result<W, SerializeError> {
+        let snapshot = serde_json::from_str(json)?;
+
+        self.restore(world, snapshot, |c| c.decode_json)
+    }
+
+    /// adds the entities saved by `to_binary` into world
+    pub fn from_binary(&self, world: W, bytes: &[u8]) -> Result<W, SerializeError> {
+        let snapshot = bincode::deserialize(bytes)?;
+
+        self.restore(world, snapshot, |c| c.decode_binary)
+    }
+
+    /// adds the components of prefab to the entity being built, and spawns its children as
+    /// entities with a `Parent`. Fails without touching the world if any of the components can't
+    /// be decoded.
+    pub fn instantiate(
+        &self,
+        prefab: &Prefab,
+        builder: EntityBuilder<W>,
+    ) -> Result<EntityBuilder<W>, SerializeError>
+    where
+        W: WorldStorage<Parent>,
+    {
+        let decoded = self.decode_prefab(prefab)?;
+
+        Ok(self.spawn(decoded, builder))
+    }
+
+    fn decode_prefab(&self, prefab: &Prefab) -> Result<DecodedPrefab<W>, SerializeError> {
+        if let Some(path) = &prefab.prefab {
+            return Err(SerializeError::UnresolvedPrefab(path.clone()));
+        }
+
+        let components = prefab
+            .components
+            .iter()
+            .map(|(name, value)| match self.components.get(name.as_str()) {
+                Some(serializer) => {
+                    Ok((serializer.set, (serializer.decode_prefab)(value.clone())?))
+                }
+                None => Err(SerializeError::UnknownComponent(name.clone())),
+            })
+            .collect::<Result<_, SerializeError>>()?;
+
+        let children = prefab
+            .children
+            .iter()
+            .map(|child| self.decode_prefab(child))
+            .collect::<Result<_, SerializeError>>()?;
+
+        Ok(DecodedPrefab {
+            components,
+            children,
+        })
+    }
+
+    fn spawn(&self, prefab: DecodedPrefab<W>, builder: EntityBuilder<W>) -> EntityBuilder<W>
+    where
+        W: WorldStorage<Parent>,
+    {
+        let entity = builder.entity();
+        let world = builder.build();
+
+        for (set, component) in prefab.components {
+            set(&world, entity, component);
+        }
+
+        let world = prefab.children.into_iter().fold(world, |world, child| {
+            let child_builder = world.add_entity().with_component(Parent(entity));
+
+            self.spawn(child, child_builder).build()
+        });
+
+        EntityBuilder::new(entity, world)
+    }
+
+    fn snapshot<V>(
+        &self,
+        world: &W,
+        save: impl Fn(&ComponentSerializer<W>) -> SaveFn<W, V>,
+    ) -> Result<Snapshot<V>, SerializeError> {
+        let entities: Vec<Entity> = world
+            .entities()
+            .iter()
+            .filter(|entity| !entity.is_dead())
+            .cloned()
+            .collect();
+        let alive = entities.iter().cloned().collect();
+
+        let components = self
+            .components
+            .iter()
+            .map(|(name, serializer)| Ok((name.to_string(), save(serializer)(world, &alive)?)))
+            .collect::<Result<_, SerializeError>>()?;
+
+        Ok(Snapshot {
+            entities,
+            components,
+        })
+    }
+
+    fn restore<V>(
+        &self,
+        world: W,
+        snapshot: Snapshot<V>,
+        decode: impl Fn(&ComponentSerializer<W>) -> DecodeFn<V>,
+    ) -> Result<W, SerializeError> {
+        // decode everything before touching the world, so a bad snapshot doesn't leave it
+        // half loaded
+        let decoded = snapshot
+            .components
+            .into_iter()
+            .map(
+                |(name, components)| match self.components.get(name.as_str()) {
+                    Some(serializer) => Ok((serializer, decode(serializer)(components)?)),
+                    None => Err(SerializeError::UnknownComponent(name)),
+                },
+            )
+            .collect::<Result<Vec<_>, SerializeError>>()?;
+
+        let mut entities = EntityMap::default();
+        let world = snapshot.entities.into_iter().fold(world, |world, saved| {
+            let builder = world.add_entity();
+            entities.entities.insert(saved, builder.entity());
+            builder.build()
+        });
+
+        for (serializer, components) in decoded {
+            (serializer.insert)(&world, components, &entities);
+        }
+
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::component::Children;
+    use crate::ecs::world::DefaultWorld;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Name(String);
+
+    impl Component for Name {}
+
+    impl SerializableComponent for Name {
+        const NAME: &'static str = "Name";
+    }
+
+    fn new_world() -> DefaultWorld {
+        DefaultWorld::new()
+            .register::<Name>()
+            .register::<Parent>()
+            .register::<Children>()
+    }
+
+    fn serializer() -> WorldSerializer<DefaultWorld> {
+        WorldSerializer::new()
+            .with::<Name>()
+            .with::<Parent>()
+            .with::<Children>()
+    }
+
+    /// a root with two children, one of which lost its parent before being saved
+    fn saved_world() -> DefaultWorld {
+        // taken and freed first, so the loaded entities get other ids than the saved ones
+        let world = new_world().add_entity().build().add_entity().build();
+        let taken = world.entities().to_vec();
+        let world = taken
+            .into_iter()
+            .fold(world, |world, entity| world.remove_entity(entity))
+            .remove_dead();
+
+        let builder = world.add_entity();
+        let root = builder.entity();
+        let builder = builder
+            .with_component(Name("root".into()))
+            .build()
+            .add_entity();
+        let child = builder.entity();
+        let builder = builder
+            .with_component(Name("child".into()))
+            .with_component(Parent(root))
+            .build()
+            .add_entity();
+        let gone = builder.entity();
+        let builder = builder.build().add_entity();
+        let orphan = builder.entity();
+        let world = builder
+            .with_component(Name("orphan".into()))
+            .with_component(Parent(gone))
+            .build();
+
+        let world = world.remove_entity(gone).remove_dead();
+        WorldStorage::<Children>::storage(&world)
+            .write()
+            .set(root, Children(vec![child, gone, orphan]));
+
+        world
+    }
+
+    /// entity named name in world
+    fn named(world: &DefaultWorld, name: &str) -> Entity {
+        let names = WorldStorage::<Name>::storage(world).read();
+        let found = names.iter().find(|(_, other)| other.0 == name);
+
+        found.expect("not loaded").0
+    }
+
+    fn assert_loaded(saved: &DefaultWorld, loaded: &DefaultWorld) {
+        let (root, child, orphan) = (
+            named(loaded, "root"),
+            named(loaded, "child"),
+            named(loaded, "orphan"),
+        );
+        let parents = WorldStorage::<Parent>::storage(loaded).read();
+        let children = WorldStorage::<Children>::storage(loaded).read();
+
+        assert_eq!(loaded.entities().len(), 3);
+        assert_ne!(root, named(saved, "root"));
+        assert_eq!(parents.fetch(child), Some(&Parent(root)));
+        assert_eq!(parents.fetch(orphan), None, "parent wasn't saved");
+        assert_eq!(children.fetch(root), Some(&Children(vec![child, orphan])));
+        assert_eq!(parents.iter().count(), 1);
+    }
+
+    #[test]
+    fn json_round_trip_maps_entities() {
+        let saved = saved_world();
+        let json = serializer().to_json(&saved).unwrap();
+        let loaded = serializer().from_json(new_world(), &json).unwrap();
+
+        assert_loaded(&saved, &loaded);
+    }
+
+    #[test]
+    fn binary_round_trip_maps_entities() {
+        let saved = saved_world();
+        let bytes = serializer().to_binary(&saved).unwrap();
+        let loaded = serializer().from_binary(new_world(), &bytes).unwrap();
+
+        assert_loaded(&saved, &loaded);
+        assert!(bytes.len() < serializer().to_json(&saved).unwrap().len());
+    }
+
+    #[test]
+    fn unregistered_components_are_errors() {
+        let saved = saved_world();
+        let without_parent = WorldSerializer::new().with::<Name>().with::<Children>();
+
+        let json = serializer().to_json(&saved).unwrap();
+        match without_parent.from_json(new_world(), &json) {
+            Err(SerializeError::UnknownComponent(name)) => assert_eq!(name, Parent::NAME),
+            other => panic!("expected an unknown component, got {:?}", other.map(|_| ())),
+        }
+
+        let bytes = serializer().to_binary(&saved).unwrap();
+        match without_parent.from_binary(new_world(), &bytes) {
+            Err(SerializeError::UnknownComponent(name)) => assert_eq!(name, Parent::NAME),
+            other => panic!("expected an unknown component, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -1,24 +1,40 @@
 //! entity component Systems
 
+use std::any::TypeId;
+use std::collections::HashSet;
+use std::marker::PhantomData;
 use std::time::Duration;
 
 use crate::debug::DebugContext;
+use crate::ecs::change::ChangeCursors;
+use crate::ecs::join::{Join, JoinIter};
+use crate::ecs::resource::{ReadRes, WriteRes};
 use crate::ecs::world::{World, WorldStorage};
-use crate::ecs::{Component, ComponentStorage, Entity, ReadAccess, RwAccess};
+use crate::ecs::{
+    ChangeTracker, Component, ComponentStorage, Entity, Event, Mut, ReadAccess, RwAccess,
+    WriteAccess,
+};
 use crate::game::IoState;
 use crate::gfx::RenderContext;
 
 pub mod physics;
 #[cfg(feature = "3d")]
 pub mod scene;
+pub mod transform;
 
 pub struct Read<'a, C> {
     reader: Box<dyn ReadAccess<'a, C> + 'a>,
+    changes: &'a ChangeTracker,
 }
 
 impl<'a, C: Component> Read<'a, C> {
-    pub fn new(reader: Box<dyn ReadAccess<'a, C> + 'a>) -> Read<'a, C> {
-        Read { reader }
+    pub fn new(reader: Box<dyn ReadAccess<'a, C> + 'a>, changes: &'a ChangeTracker) -> Read<'a, C> {
+        Read { reader, changes }
+    }
+
+    /// changes recorded by the underlying storage
+    pub fn changes(&self) -> &ChangeTracker {
+        self.changes
     }
 }
 
@@ -33,15 +49,29 @@ where
     pub fn fetch(&self, entity: Entity) -> Option<&C> {
         self.reader.fetch(entity)
     }
+
+    pub(crate) fn slots(&self) -> usize {
+        self.reader.slots()
+    }
+
+    pub(crate) fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.reader.slot(slot)
+    }
 }
 
 pub struct Write<'a, C> {
     writer: Box<dyn RwAccess<'a, C> + 'a>,
+    changes: &'a ChangeTracker,
 }
 
 impl<'a, C: Component> Write<'a, C> {
-    pub fn new(writer: Box<dyn RwAccess<'a, C> + 'a>) -> Write<'a, C> {
-        Write { writer }
+    pub fn new(writer: Box<dyn RwAccess<'a, C> + 'a>, changes: &'a ChangeTracker) -> Write<'a, C> {
+        Write { writer, changes }
+    }
+
+    /// changes recorded by the underlying storage
+    pub fn changes(&self) -> &ChangeTracker {
+        self.changes
     }
 }
 
@@ -67,10 +97,33 @@ where
         self.writer.unset(entity)
     }
 
-    /// mutable iterator over component storage
+    /// returns mutable reference to the Component of Entity
+    pub fn fetch_mut(&mut self, entity: Entity) -> Option<&mut C> {
+        self.writer.fetch_mut(entity)
+    }
+
+    /// returns the Component of Entity. Records a modification only when written through.
+    pub fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>> {
+        self.writer.get_mut(entity)
+    }
+
+    pub(crate) fn slots(&self) -> usize {
+        self.writer.slots()
+    }
+
+    pub(crate) fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.writer.slot(slot)
+    }
+
+    pub(crate) fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)> {
+        self.writer.slot_mut(slot)
+    }
+
+    /// mutable iterator over component storage. Only the Components written through are
+    /// recorded as modified.
     pub fn iter_mut<'borrow, 's: 'borrow>(
         &'s mut self,
-    ) -> Box<dyn Iterator<Item = (Entity, &'borrow mut C)> + 'borrow> {
+    ) -> Box<dyn Iterator<Item = (Entity, Mut<'borrow, C>)> + 'borrow> {
         self.writer.iter_mut()
     }
 
@@ -80,8 +133,256 @@ where
     }
 }
 
+type StorageOf<W, C> = <W as WorldStorage<C>>::Storage;
+
+/// Like `Read`, but keeps the concrete reader of the World's storage instead of boxing it, so
+/// fetches are statically dispatched. Meant for hot loops over large worlds.
+pub struct ReadStorage<'a, W, C>
+where
+    W: 'a + WorldStorage<C>,
+    C: 'a + Component,
+{
+    reader: <StorageOf<W, C> as ComponentStorage<C>>::Reader<'a>,
+    changes: &'a ChangeTracker,
+}
+
+impl<'a, W, C> ReadStorage<'a, W, C>
+where
+    W: 'a + WorldStorage<C>,
+    C: 'a + Component,
+{
+    pub fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.reader.fetch(entity)
+    }
+
+    /// iterator over the storage, going through its slots without boxing an iterator
+    pub fn iter(&self) -> JoinIter<&ReadStorage<'a, W, C>> {
+        self.join()
+    }
+
+    pub(crate) fn slots(&self) -> usize {
+        self.reader.slots()
+    }
+
+    pub(crate) fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.reader.slot(slot)
+    }
+
+    /// changes recorded by the underlying storage
+    pub fn changes(&self) -> &ChangeTracker {
+        self.changes
+    }
+}
+
+/// Like `Write`, but keeps the concrete writer of the World's storage instead of boxing it, so
+/// fetches are statically dispatched. Meant for hot loops over large worlds.
+pub struct WriteStorage<'a, W, C>
+where
+    W: 'a + WorldStorage<C>,
+    C: 'a + Component,
+{
+    writer: <StorageOf<W, C> as ComponentStorage<C>>::Writer<'a>,
+    changes: &'a ChangeTracker,
+}
+
+impl<'a, W, C> WriteStorage<'a, W, C>
+where
+    W: 'a + WorldStorage<C>,
+    C: 'a + Component,
+{
+    pub fn fetch(&self, entity: Entity) -> Option<&C> {
+        self.writer.fetch(entity)
+    }
+
+    pub fn fetch_mut(&mut self, entity: Entity) -> Option<&mut C> {
+        self.writer.fetch_mut(entity)
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<Mut<C>> {
+        self.writer.get_mut(entity)
+    }
+
+    pub fn set(&mut self, entity: Entity, value: C) {
+        self.writer.set(entity, value)
+    }
+
+    pub fn unset(&mut self, entity: Entity) {
+        self.writer.unset(entity)
+    }
+
+    /// iterator over the storage, going through its slots without boxing an iterator
+    pub fn iter(&self) -> JoinIter<&WriteStorage<'a, W, C>> {
+        self.join()
+    }
+
+    /// mutable iterator over the storage, going through its slots without boxing an iterator.
+    /// Only the Components written through are recorded as modified.
+    pub fn iter_mut(&mut self) -> JoinIter<&mut WriteStorage<'a, W, C>> {
+        self.join()
+    }
+
+    pub(crate) fn slots(&self) -> usize {
+        self.writer.slots()
+    }
+
+    pub(crate) fn slot(&self, slot: usize) -> Option<(Entity, &C)> {
+        self.writer.slot(slot)
+    }
+
+    pub(crate) fn slot_mut(&mut self, slot: usize) -> Option<(Entity, Mut<C>)> {
+        self.writer.slot_mut(slot)
+    }
+
+    /// changes recorded by the underlying storage
+    pub fn changes(&self) -> &ChangeTracker {
+        self.changes
+    }
+}
+
+/// Entity events emitted by the World since its events were last cleared
+pub struct EntityEvents<'a> {
+    events: &'a [Event],
+}
+
+impl<'a> EntityEvents<'a> {
+    pub fn new(events: &'a [Event]) -> EntityEvents<'a> {
+        EntityEvents { events }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a Event> {
+        self.events.iter()
+    }
+
+    /// entities added to the World
+    pub fn added(&self) -> impl Iterator<Item = Entity> + 'a {
+        self.events.iter().filter_map(|event| match event {
+            Event::EntityAdded(entity) => Some(*entity),
+            _ => None,
+        })
+    }
+
+    /// entities removed from the World. Their Components are already gone.
+    pub fn removed(&self) -> impl Iterator<Item = Entity> + 'a {
+        self.events.iter().filter_map(|event| match event {
+            Event::EntityRemoved(entity) => Some(*entity),
+            _ => None,
+        })
+    }
+}
+
+macro_rules! change_reader {
+    ($(#[$meta:meta])* $name:ident, $entities:ident, $read:ident) => {
+        $(#[$meta])*
+        pub struct $name<'a, C> {
+            /// sorted, so they can be searched
+            entities: Vec<Entity>,
+            marker: PhantomData<&'a C>,
+        }
+
+        impl<'a, C> $name<'a, C> {
+            pub fn new(entities: HashSet<Entity>) -> $name<'a, C> {
+                let mut entities: Vec<Entity> = entities.into_iter().collect();
+                entities.sort_unstable_by_key(|entity| entity.0);
+
+                $name {
+                    entities,
+                    marker: PhantomData,
+                }
+            }
+
+            pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+                self.entities.iter().cloned()
+            }
+
+            pub fn contains(&self, entity: Entity) -> bool {
+                let entity = Entity::with_generation(entity.index(), entity.generation());
+
+                self.entities
+                    .binary_search_by_key(&entity.0, |other| other.0)
+                    .is_ok()
+            }
+
+            pub fn len(&self) -> usize {
+                self.entities.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.entities.is_empty()
+            }
+
+            pub(crate) fn entity_at(&self, index: usize) -> Option<Entity> {
+                self.entities.get(index).cloned()
+            }
+        }
+
+        impl<'access, W, C> SystemData<'access, W> for $name<'access, C>
+        where
+            C: 'static + Component,
+            W: World + WorldStorage<C>,
+        {
+            fn get(world: &'access W) -> Self {
+                $name::new(world.storage().changes().$entities())
+            }
+
+            fn fetch(world: &'access W, cursors: &mut ChangeCursors) -> Self {
+                let changes = world.storage().changes();
+                let cursor = cursors.cursor::<C>(stringify!($name), changes);
+
+                $name::new(changes.$read(cursor))
+            }
+
+            fn reads() -> Vec<TypeId> {
+                vec![TypeId::of::<C>()]
+            }
+        }
+    };
+}
+
+change_reader!(
+    /// entities whose Component C was inserted since the system last ran, or since the last
+    /// frame when not fetched by a `Dispatcher`
+    Added,
+    inserted_entities,
+    read_inserted
+);
+change_reader!(
+    /// entities whose Component C was inserted or modified since the system last ran, or since
+    /// the last frame when not fetched by a `Dispatcher`
+    Changed,
+    changed_entities,
+    read_changed
+);
+change_reader!(
+    /// entities whose Component C was removed since the system last ran, or since the last
+    /// frame when not fetched by a `Dispatcher`
+    Removed,
+    removed_entities,
+    read_removed
+);
+
 pub trait SystemData<'access, W: World> {
     fn get(world: &'access W) -> Self;
+
+    /// like `get`, but change readers like `Changed` only return the changes made since the
+    /// last fetch with the same cursors. The `Dispatcher` keeps cursors for every system, so
+    /// systems see every change once, however many frames pass between their runs. Call
+    /// `ChangeCursors::begin_fetch` before fetching.
+    fn fetch(world: &'access W, _cursors: &mut ChangeCursors) -> Self
+    where
+        Self: Sized,
+    {
+        Self::get(world)
+    }
+
+    /// Components read through this SystemData, used for scheduling
+    fn reads() -> Vec<TypeId> {
+        Vec::new()
+    }
+
+    /// Components written through this SystemData, used for scheduling
+    fn writes() -> Vec<TypeId> {
+        Vec::new()
+    }
 }
 
 impl<'a, W: World> SystemData<'a, W> for () {
@@ -92,21 +393,126 @@ impl<'a, W: World> SystemData<'a, W> for () {
 
 impl<'access, W, C> SystemData<'access, W> for Write<'access, C>
 where
-    C: 'access + Component,
+    C: 'static + Component,
     W: World + WorldStorage<C>,
 {
     fn get(world: &'access W) -> Self {
-        Write::new(Box::new(world.storage().write()))
+        let storage = world.storage();
+
+        Write::new(Box::new(storage.write()), storage.changes())
+    }
+
+    fn writes() -> Vec<TypeId> {
+        vec![TypeId::of::<C>()]
     }
 }
 
 impl<'access, W, C> SystemData<'access, W> for Read<'access, C>
 where
-    C: 'access + Component,
+    C: 'static + Component,
     W: World + WorldStorage<C>,
 {
     fn get(world: &'access W) -> Self {
-        Read::new(Box::new(world.storage().read()))
+        let storage = world.storage();
+
+        Read::new(Box::new(storage.read()), storage.changes())
+    }
+
+    fn reads() -> Vec<TypeId> {
+        vec![TypeId::of::<C>()]
+    }
+}
+
+impl<'access, W, C> SystemData<'access, W> for ReadStorage<'access, W, C>
+where
+    C: 'static + Component,
+    W: World + WorldStorage<C>,
+{
+    fn get(world: &'access W) -> Self {
+        let storage = world.storage();
+
+        ReadStorage {
+            reader: storage.read(),
+            changes: storage.changes(),
+        }
+    }
+
+    fn reads() -> Vec<TypeId> {
+        vec![TypeId::of::<C>()]
+    }
+}
+
+impl<'access, W, C> SystemData<'access, W> for WriteStorage<'access, W, C>
+where
+    C: 'static + Component,
+    W: World + WorldStorage<C>,
+{
+    fn get(world: &'access W) -> Self {
+        let storage = world.storage();
+
+        WriteStorage {
+            writer: storage.write(),
+            changes: storage.changes(),
+        }
+    }
+
+    fn writes() -> Vec<TypeId> {
+        vec![TypeId::of::<C>()]
+    }
+}
+
+impl<'access, W: World> SystemData<'access, W> for EntityEvents<'access> {
+    fn get(world: &'access W) -> Self {
+        EntityEvents::new(world.events())
+    }
+}
+
+impl<'access, W, T> SystemData<'access, W> for ReadRes<'access, T>
+where
+    T: 'static + Send + Sync,
+    W: World,
+{
+    fn get(world: &'access W) -> Self {
+        world
+            .resources()
+            .read()
+            .unwrap_or_else(|| panic!("resource {} not found in world", std::any::type_name::<T>()))
+    }
+
+    fn reads() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
+    }
+}
+
+/// resource that might not be in the World
+impl<'access, W, T> SystemData<'access, W> for Option<ReadRes<'access, T>>
+where
+    T: 'static + Send + Sync,
+    W: World,
+{
+    fn get(world: &'access W) -> Self {
+        world.resources().read()
+    }
+
+    fn reads() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
+    }
+}
+
+impl<'access, W, T> SystemData<'access, W> for WriteRes<'access, T>
+where
+    T: 'static + Send + Sync,
+    W: World,
+{
+    fn get(world: &'access W) -> Self {
+        world
+            .resources()
+            .write()
+            .unwrap_or_else(|| panic!("resource {} not found in world", std::any::type_name::<T>()))
+    }
+
+    fn writes() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
     }
 }
 
@@ -118,6 +524,18 @@ where
     fn get(world: &'a W) -> Self {
         (A::get(world),)
     }
+
+    fn fetch(world: &'a W, cursors: &mut ChangeCursors) -> Self {
+        (A::fetch(world, cursors),)
+    }
+
+    fn reads() -> Vec<TypeId> {
+        A::reads()
+    }
+
+    fn writes() -> Vec<TypeId> {
+        A::writes()
+    }
 }
 
 impl<'a, A, B, W> SystemData<'a, W> for (A, B)
@@ -129,6 +547,24 @@ where
     fn get(world: &'a W) -> Self {
         (A::get(world), B::get(world))
     }
+
+    fn fetch(world: &'a W, cursors: &mut ChangeCursors) -> Self {
+        (A::fetch(world, cursors), B::fetch(world, cursors))
+    }
+
+    fn reads() -> Vec<TypeId> {
+        let mut reads = Vec::new();
+        reads.extend(A::reads());
+        reads.extend(B::reads());
+        reads
+    }
+
+    fn writes() -> Vec<TypeId> {
+        let mut writes = Vec::new();
+        writes.extend(A::writes());
+        writes.extend(B::writes());
+        writes
+    }
 }
 
 impl<'a, A, B, C, W> SystemData<'a, W> for (A, B, C)
@@ -141,6 +577,30 @@ where
     fn get(world: &'a W) -> Self {
         (A::get(world), B::get(world), C::get(world))
     }
+
+    fn fetch(world: &'a W, cursors: &mut ChangeCursors) -> Self {
+        (
+            A::fetch(world, cursors),
+            B::fetch(world, cursors),
+            C::fetch(world, cursors),
+        )
+    }
+
+    fn reads() -> Vec<TypeId> {
+        let mut reads = Vec::new();
+        reads.extend(A::reads());
+        reads.extend(B::reads());
+        reads.extend(C::reads());
+        reads
+    }
+
+    fn writes() -> Vec<TypeId> {
+        let mut writes = Vec::new();
+        writes.extend(A::writes());
+        writes.extend(B::writes());
+        writes.extend(C::writes());
+        writes
+    }
 }
 
 impl<'a, A, B, C, D, W> SystemData<'a, W> for (A, B, C, D)
@@ -154,6 +614,33 @@ where
     fn get(world: &'a W) -> Self {
         (A::get(world), B::get(world), C::get(world), D::get(world))
     }
+
+    fn fetch(world: &'a W, cursors: &mut ChangeCursors) -> Self {
+        (
+            A::fetch(world, cursors),
+            B::fetch(world, cursors),
+            C::fetch(world, cursors),
+            D::fetch(world, cursors),
+        )
+    }
+
+    fn reads() -> Vec<TypeId> {
+        let mut reads = Vec::new();
+        reads.extend(A::reads());
+        reads.extend(B::reads());
+        reads.extend(C::reads());
+        reads.extend(D::reads());
+        reads
+    }
+
+    fn writes() -> Vec<TypeId> {
+        let mut writes = Vec::new();
+        writes.extend(A::writes());
+        writes.extend(B::writes());
+        writes.extend(C::writes());
+        writes.extend(D::writes());
+        writes
+    }
 }
 
 impl<'a, A, B, C, D, E, W> SystemData<'a, W> for (A, B, C, D, E)
@@ -174,6 +661,151 @@ where
             E::get(world),
         )
     }
+
+    fn fetch(world: &'a W, cursors: &mut ChangeCursors) -> Self {
+        (
+            A::fetch(world, cursors),
+            B::fetch(world, cursors),
+            C::fetch(world, cursors),
+            D::fetch(world, cursors),
+            E::fetch(world, cursors),
+        )
+    }
+
+    fn reads() -> Vec<TypeId> {
+        let mut reads = Vec::new();
+        reads.extend(A::reads());
+        reads.extend(B::reads());
+        reads.extend(C::reads());
+        reads.extend(D::reads());
+        reads.extend(E::reads());
+        reads
+    }
+
+    fn writes() -> Vec<TypeId> {
+        let mut writes = Vec::new();
+        writes.extend(A::writes());
+        writes.extend(B::writes());
+        writes.extend(C::writes());
+        writes.extend(D::writes());
+        writes.extend(E::writes());
+        writes
+    }
+}
+
+impl<'a, A, B, C, D, E, F, W> SystemData<'a, W> for (A, B, C, D, E, F)
+where
+    A: SystemData<'a, W>,
+    B: SystemData<'a, W>,
+    C: SystemData<'a, W>,
+    D: SystemData<'a, W>,
+    E: SystemData<'a, W>,
+    F: SystemData<'a, W>,
+    W: World,
+{
+    fn get(world: &'a W) -> Self {
+        (
+            A::get(world),
+            B::get(world),
+            C::get(world),
+            D::get(world),
+            E::get(world),
+            F::get(world),
+        )
+    }
+
+    fn fetch(world: &'a W, cursors: &mut ChangeCursors) -> Self {
+        (
+            A::fetch(world, cursors),
+            B::fetch(world, cursors),
+            C::fetch(world, cursors),
+            D::fetch(world, cursors),
+            E::fetch(world, cursors),
+            F::fetch(world, cursors),
+        )
+    }
+
+    fn reads() -> Vec<TypeId> {
+        let mut reads = Vec::new();
+        reads.extend(A::reads());
+        reads.extend(B::reads());
+        reads.extend(C::reads());
+        reads.extend(D::reads());
+        reads.extend(E::reads());
+        reads.extend(F::reads());
+        reads
+    }
+
+    fn writes() -> Vec<TypeId> {
+        let mut writes = Vec::new();
+        writes.extend(A::writes());
+        writes.extend(B::writes());
+        writes.extend(C::writes());
+        writes.extend(D::writes());
+        writes.extend(E::writes());
+        writes.extend(F::writes());
+        writes
+    }
+}
+
+impl<'a, A, B, C, D, E, F, G, W> SystemData<'a, W> for (A, B, C, D, E, F, G)
+where
+    A: SystemData<'a, W>,
+    B: SystemData<'a, W>,
+    C: SystemData<'a, W>,
+    D: SystemData<'a, W>,
+    E: SystemData<'a, W>,
+    F: SystemData<'a, W>,
+    G: SystemData<'a, W>,
+    W: World,
+{
+    fn get(world: &'a W) -> Self {
+        (
+            A::get(world),
+            B::get(world),
+            C::get(world),
+            D::get(world),
+            E::get(world),
+            F::get(world),
+            G::get(world),
+        )
+    }
+
+    fn fetch(world: &'a W, cursors: &mut ChangeCursors) -> Self {
+        (
+            A::fetch(world, cursors),
+            B::fetch(world, cursors),
+            C::fetch(world, cursors),
+            D::fetch(world, cursors),
+            E::fetch(world, cursors),
+            F::fetch(world, cursors),
+            G::fetch(world, cursors),
+        )
+    }
+
+    fn reads() -> Vec<TypeId> {
+        let mut reads = Vec::new();
+        reads.extend(A::reads());
+        reads.extend(B::reads());
+        reads.extend(C::reads());
+        reads.extend(D::reads());
+        reads.extend(E::reads());
+        reads.extend(F::reads());
+        reads.extend(G::reads());
+        reads
+    }
+
+    fn writes() -> Vec<TypeId> {
+        let mut writes = Vec::new();
+        writes.extend(A::writes());
+        writes.extend(B::writes());
+        writes.extend(C::writes());
+        writes.extend(D::writes());
+        writes.extend(E::writes());
+        writes.extend(F::writes());
+        writes.extend(G::writes());
+        writes
+    }
 }
 
 pub trait System<W: World> {
@@ -194,10 +826,31 @@ pub trait System<W: World> {
     fn draw_to(&self, _view: &[&wgpu::TextureView], _render_ctx: &mut RenderContext) {}
 }
 
+/// A System that doesn't need the render or debug contexts, so the `Dispatcher` can run it on a
+/// worker thread, in parallel with other systems that don't access the same Components.
+pub trait ParallelSystem<W: World>: Send {
+    type SystemData<'a>: SystemData<'a, W>;
+
+    fn name(&self) -> &'static str;
+    fn update<'f>(&mut self, data: Self::SystemData<'f>, delta: Duration, io_state: &IoState)
+        -> ();
+}
+
 pub trait SystemCaller<W: World> {
+    fn name(&self) -> &'static str;
+
+    /// Components read by this system
+    fn reads<'a>(&self) -> Vec<TypeId>;
+
+    /// Components written by this system
+    fn writes<'a>(&self) -> Vec<TypeId>;
+
+    /// runs the system once. cursors are the change cursors of this system, see
+    /// `SystemData::fetch`.
     fn dispatch<'a, 's>(
         &'s mut self,
         world: &'a W,
+        cursors: &mut ChangeCursors,
         delta: Duration,
         io_state: &IoState,
         render_ctx: &mut RenderContext,
@@ -205,22 +858,43 @@ pub trait SystemCaller<W: World> {
     ) -> ();
 
     fn render<'a, 's>(&'s self, render_ctx: &mut RenderContext) -> ();
+
+    fn render_to<'a, 's>(
+        &'s self,
+        views: &[&wgpu::TextureView],
+        render_ctx: &mut RenderContext,
+    ) -> ();
 }
 
 impl<W: World, S> SystemCaller<W> for S
 where
     S: System<W>,
 {
+    fn name(&self) -> &'static str {
+        System::name(self)
+    }
+
+    fn reads<'a>(&self) -> Vec<TypeId> {
+        <<S as System<W>>::SystemData<'a> as SystemData<'a, W>>::reads()
+    }
+
+    fn writes<'a>(&self) -> Vec<TypeId> {
+        <<S as System<W>>::SystemData<'a> as SystemData<'a, W>>::writes()
+    }
+
     fn dispatch<'a, 's>(
         &'s mut self,
         world: &'a W,
+        cursors: &mut ChangeCursors,
         delta: Duration,
         io_state: &IoState,
         render_ctx: &mut RenderContext,
         debug_ctx: &mut DebugContext,
     ) -> () {
+        cursors.begin_fetch();
+
         self.update(
-            <<S as System<W>>::SystemData<'a> as SystemData<'a, W>>::get(world),
+            <<S as System<W>>::SystemData<'a> as SystemData<'a, W>>::fetch(world, cursors),
             delta,
             io_state,
             render_ctx,
@@ -231,4 +905,64 @@ where
     fn render<'a, 's>(&'s self, render_ctx: &mut RenderContext) -> () {
         self.draw(render_ctx)
     }
+
+    fn render_to<'a, 's>(
+        &'s self,
+        views: &[&wgpu::TextureView],
+        render_ctx: &mut RenderContext,
+    ) -> () {
+        self.draw_to(views, render_ctx)
+    }
+}
+
+pub trait ParallelSystemCaller<W: World>: Send {
+    fn name(&self) -> &'static str;
+
+    /// Components read by this system
+    fn reads<'a>(&self) -> Vec<TypeId>;
+
+    /// Components written by this system
+    fn writes<'a>(&self) -> Vec<TypeId>;
+
+    /// runs the system once, see `SystemCaller::dispatch`
+    fn dispatch<'a, 's>(
+        &'s mut self,
+        world: &'a W,
+        cursors: &mut ChangeCursors,
+        delta: Duration,
+        io_state: &IoState,
+    ) -> ();
+}
+
+impl<W: World, S> ParallelSystemCaller<W> for S
+where
+    S: ParallelSystem<W>,
+{
+    fn name(&self) -> &'static str {
+        ParallelSystem::name(self)
+    }
+
+    fn reads<'a>(&self) -> Vec<TypeId> {
+        <<S as ParallelSystem<W>>::SystemData<'a> as SystemData<'a, W>>::reads()
+    }
+
+    fn writes<'a>(&self) -> Vec<TypeId> {
+        <<S as ParallelSystem<W>>::SystemData<'a> as SystemData<'a, W>>::writes()
+    }
+
+    fn dispatch<'a, 's>(
+        &'s mut self,
+        world: &'a W,
+        cursors: &mut ChangeCursors,
+        delta: Duration,
+        io_state: &IoState,
+    ) -> () {
+        cursors.begin_fetch();
+
+        self.update(
+            <<S as ParallelSystem<W>>::SystemData<'a> as SystemData<'a, W>>::fetch(world, cursors),
+            delta,
+            io_state,
+        )
+    }
 }
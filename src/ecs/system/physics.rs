@@ -1,31 +1,39 @@
 //! Physics related systems
 
 use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
 use std::ops::DerefMut;
-use std::rc::Rc;
-use std::sync::RwLock;
 use std::time::Duration;
 
 use crate::nphysics::{
     force_generator::DefaultForceGeneratorSet,
-    joint::DefaultJointConstraintSet,
-    math::Vector,
+    joint::{DefaultJointConstraintSet, FixedJoint, FreeJoint, PrismaticJoint, RevoluteJoint},
+    math::{self, Isometry, Vector},
+    ncollide::{pipeline::ContactEvent, query::Proximity},
     object::{
-        BodyPartHandle, DefaultBodyHandle, DefaultBodySet, DefaultColliderSet, RigidBody,
-        RigidBodyDesc,
+        Body, BodyPart, BodyPartHandle, BodyStatus, DefaultBodyHandle, DefaultBodySet,
+        DefaultColliderHandle, DefaultColliderSet, MultibodyDesc, RigidBody, RigidBodyDesc,
     },
     world::{DefaultGeometricalWorld, DefaultMechanicalWorld, GeometricalWorld, MechanicalWorld},
 };
 use nalgebra::RealField;
 
 use crate::debug::DebugContext;
-use crate::ecs::component::{PhysicsBody, Transform};
-use crate::ecs::system::Write;
+use crate::ecs::component::{
+    Forces, LinkJoint, MultibodyLink, Parent, PhysicsBody, Transform, Velocity,
+};
+use crate::ecs::resource::WriteRes;
+use crate::ecs::system::{Read, Write};
 use crate::ecs::world::{World, WorldStorage};
-use crate::ecs::{Entity, System};
+use crate::ecs::{Entity, EventChannel, System};
 use crate::game::IoState;
 use crate::gfx::RenderContext;
 
+pub mod character;
+pub mod query;
+
+/// The nphysics world, as a resource. See `query` for ray casts and overlap tests.
 pub struct PhysicsWorld<T: RealField> {
     pub mechanical_world: DefaultMechanicalWorld<T>,
     pub geometrical_world: DefaultGeometricalWorld<T>,
@@ -33,6 +41,9 @@ pub struct PhysicsWorld<T: RealField> {
     pub colliders: DefaultColliderSet<T>,
     pub constraints: DefaultJointConstraintSet<T>,
     pub force_generators: DefaultForceGeneratorSet<T>,
+    /// owner of every collider, a `PhysicsBody` or `MultibodyLink` entity. Maintained by
+    /// `PhysicsSystem`.
+    collider_entities: HashMap<DefaultColliderHandle, Entity>,
 }
 
 impl<T: RealField> PhysicsWorld<T> {
@@ -44,33 +55,146 @@ impl<T: RealField> PhysicsWorld<T> {
             colliders: DefaultColliderSet::new(),
             constraints: DefaultJointConstraintSet::new(),
             force_generators: DefaultForceGeneratorSet::new(),
+            collider_entities: HashMap::new(),
+        }
+    }
+}
+
+/// Collision between the colliders of two entities, written by `PhysicsSystem` after every step
+/// to an `EventChannel<CollisionEvent>` resource, see `DefaultWorld::with_event_channel`.
+///
+/// Entities with several colliders get one event per pair of entities, not per pair of colliders:
+/// started when the first of their colliders start touching, stopped when the last stop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionEvent {
+    /// solid colliders of two entities started touching, the entity with the lower id first
+    ContactStarted(Entity, Entity),
+    /// solid colliders of two entities stopped touching, in the same order as when they started
+    ContactStopped(Entity, Entity),
+    /// entity started overlapping a sensor collider, see `ColliderDesc::sensor`
+    SensorEntered { sensor: Entity, entity: Entity },
+    /// entity stopped overlapping a sensor collider
+    SensorExited { sensor: Entity, entity: Entity },
+}
+
+/// How far a `Transform` or `Velocity` can be from what `PhysicsSystem` last wrote, relative to
+/// its size, before it counts as set by another system. Keeps rounding, eq. from a round trip
+/// through a saved scene, from teleporting bodies and waking them up.
+const SYNC_TOLERANCE: f64 = 1.0e-4;
+
+/// whether position is further than `SYNC_TOLERANCE` from synced
+fn moved<T: RealField>(position: &Isometry<T>, synced: &Isometry<T>) -> bool {
+    let tolerance: T = nalgebra::convert(SYNC_TOLERANCE);
+    let (translation, synced_translation) =
+        (&position.translation.vector, &synced.translation.vector);
+    let scale = T::one() + translation.norm().max(synced_translation.norm());
+
+    (translation - synced_translation).norm() > tolerance * scale
+        || position.rotation.angle_to(&synced.rotation) > tolerance
+}
+
+/// whether velocity is further than `SYNC_TOLERANCE` from synced
+fn accelerated<T: RealField>(velocity: &math::Velocity<T>, synced: &math::Velocity<T>) -> bool {
+    let tolerance: T = nalgebra::convert(SYNC_TOLERANCE);
+    let (velocity, synced) = (velocity.as_vector(), synced.as_vector());
+    let scale = T::one() + velocity.norm().max(synced.norm());
+
+    (velocity - synced).norm() > tolerance * scale
+}
+
+/// counts a pair of colliders of key starting or stopping to touch, returns true when that made
+/// the first pair start or the last pair stop
+fn count_touch<K: Hash + Eq>(counts: &mut HashMap<K, usize>, key: K, started: bool) -> bool {
+    if started {
+        let count = counts.entry(key).or_insert(0);
+        *count += 1;
+
+        *count == 1
+    } else {
+        match counts.get_mut(&key) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+
+                false
+            }
+            Some(_) => {
+                counts.remove(&key);
+
+                true
+            }
+            None => false,
         }
     }
 }
 
+/// A multibody built from `MultibodyLink`s
+struct BuiltMultibody {
+    handle: DefaultBodyHandle,
+    /// link entities, in the order of their links in the multibody
+    links: Vec<Entity>,
+    colliders: Vec<DefaultColliderHandle>,
+}
+
+/// A rigid body as it was last synced with its components, to tell what other systems changed
+struct SyncedBody<T: RealField> {
+    position: Isometry<T>,
+    velocity: math::Velocity<T>,
+    body_status: BodyStatus,
+    mass: T,
+    linear_damping: T,
+    angular_damping: T,
+}
+
+/// Steps the physics world once per update, by the update delta. Meant for fixed ticks, see
+/// `Playable::fixed_update` and `Schedule::tick`.
 pub struct PhysicsSystem<T: RealField> {
-    update_interval: Duration,
-    update_timer: Duration,
     handle_lookup: HashMap<Entity, DefaultBodyHandle>,
-    physics_world: Rc<RwLock<PhysicsWorld<T>>>,
+    collider_lookup: HashMap<Entity, Vec<DefaultColliderHandle>>,
+    /// multibodies by their root link
+    multibody_lookup: HashMap<Entity, BuiltMultibody>,
+    synced: HashMap<Entity, SyncedBody<T>>,
+    /// number of touching collider pairs of two entities, the lower id first
+    contacts: HashMap<(Entity, Entity), usize>,
+    /// number of colliders of entity overlapping the sensor colliders of an entity, by
+    /// (sensor, entity)
+    overlaps: HashMap<(Entity, Entity), usize>,
+    marker: PhantomData<T>,
 }
 
 impl<T: RealField> PhysicsSystem<T> {
-    pub fn new(physics_world: Rc<RwLock<PhysicsWorld<T>>>) -> PhysicsSystem<T> {
+    /// creates a new PhysicsSystem. The World needs a `PhysicsWorld` resource, an
+    /// `EventChannel<CollisionEvent>`, and the `Velocity` and `Forces` components registered.
+    pub fn new() -> PhysicsSystem<T> {
         PhysicsSystem {
-            update_interval: Duration::from_secs_f64(1. / 60.),
-            update_timer: Duration::new(0, 0),
-            physics_world,
             handle_lookup: Default::default(),
+            collider_lookup: Default::default(),
+            multibody_lookup: Default::default(),
+            synced: Default::default(),
+            contacts: Default::default(),
+            overlaps: Default::default(),
+            marker: PhantomData,
         }
     }
 }
 
 impl<W: World, T: RealField> System<W> for PhysicsSystem<T>
 where
-    W: WorldStorage<PhysicsBody<T>> + WorldStorage<Transform<T>>,
+    W: WorldStorage<PhysicsBody<T>>
+        + WorldStorage<MultibodyLink<T>>
+        + WorldStorage<Transform<T>>
+        + WorldStorage<Velocity<T>>
+        + WorldStorage<Forces<T>>
+        + WorldStorage<Parent>,
 {
-    type SystemData<'a> = (Write<'a, PhysicsBody<T>>, Write<'a, Transform<T>>);
+    type SystemData<'a> = (
+        WriteRes<'a, PhysicsWorld<T>>,
+        (Write<'a, PhysicsBody<T>>, Write<'a, MultibodyLink<T>>),
+        Write<'a, Transform<T>>,
+        Read<'a, Parent>,
+        WriteRes<'a, EventChannel<CollisionEvent>>,
+        Write<'a, Velocity<T>>,
+        Write<'a, Forces<T>>,
+    );
 
     fn name(&self) -> &'static str {
         "PhysicsSystem"
@@ -78,13 +202,22 @@ where
 
     fn update<'f>(
         &mut self,
-        (mut body_reader, mut transform_reader): Self::SystemData<'f>,
+        data: Self::SystemData<'f>,
         delta: Duration,
         _io_state: &IoState,
         _render_ctx: &mut RenderContext,
         _debug_ctx: &mut DebugContext,
     ) -> () {
-        let mut physics_world_guard = self.physics_world.write().unwrap();
+        let (
+            mut physics_world,
+            (mut body_reader, mut link_reader),
+            mut transform_reader,
+            parent_reader,
+            mut collision_events,
+            mut velocity_reader,
+            mut forces_reader,
+        ) = data;
+
         let &mut PhysicsWorld {
             ref mut mechanical_world,
             ref mut geometrical_world,
@@ -92,52 +225,229 @@ where
             ref mut colliders,
             ref mut constraints,
             ref mut force_generators,
-        } = physics_world_guard.deref_mut();
+            ref mut collider_entities,
+        } = physics_world.deref_mut();
+
+        // Bodies are matched against our own lookup instead of the Added and Removed changes.
+        // Changes expire after `MAX_AGE` frames, which a paused game or a low tick rate can go
+        // by without a tick, and a missed addition or removal would leak a body for good.
+        let removed: Vec<Entity> = self
+            .handle_lookup
+            .keys()
+            .filter(|entity| body_reader.fetch(**entity).is_none())
+            .cloned()
+            .collect();
+        let added: Vec<Entity> = body_reader
+            .iter()
+            .map(|(entity, _)| entity)
+            .filter(|entity| !self.handle_lookup.contains_key(entity))
+            .collect();
+
+        // Removed colliders stay in collider_entities until after the step, which reports the
+        // contacts and overlaps they had as stopped, so their counts go back down.
+        let mut removed_colliders = Vec::new();
+
+        // remove bodies whose component was removed
+        for entity in removed {
+            for collider_handle in self.collider_lookup.remove(&entity).unwrap_or_default() {
+                colliders.remove(collider_handle);
+                removed_colliders.push(collider_handle);
+            }
+
+            if let Some(body_handle) = self.handle_lookup.remove(&entity) {
+                bodies.remove(body_handle);
+            }
+
+            self.synced.remove(&entity);
+        }
+
+        // create bodies for newly added components
+        for entity in added {
+            let body_desc = body_reader.fetch_mut(entity).unwrap();
+
+            let transform = transform_reader
+                .fetch(entity)
+                .expect("entity missing transformation");
+            let position = world_pose(entity, &transform.0, &transform_reader, &parent_reader);
+
+            let velocity = velocity_reader
+                .fetch(entity)
+                .map_or(math::Velocity::zero(), |velocity| velocity.0);
+
+            let body = RigidBodyDesc::new()
+                .mass(body_desc.mass)
+                .status(body_desc.body_status)
+                .position(position)
+                .velocity(velocity)
+                .linear_damping(body_desc.linear_damping)
+                .angular_damping(body_desc.angular_damping)
+                .build();
+
+            let body_handle = bodies.insert(body);
+
+            body_desc.handle = Some(body_handle);
+
+            // add all colliders associated with this body, a rigid body only has the one part
+            let collider_handles: Vec<DefaultColliderHandle> = body_desc
+                .colliders
+                .iter()
+                .map(|collider_desc| {
+                    colliders.insert(collider_desc.build(BodyPartHandle(body_handle, 0)))
+                })
+                .collect();
+
+            for collider_handle in &collider_handles {
+                collider_entities.insert(*collider_handle, entity);
+            }
 
-        self.update_timer += delta;
-        if self.update_timer < self.update_interval {
-            return;
+            self.handle_lookup.insert(entity, body_handle);
+            self.collider_lookup.insert(entity, collider_handles);
+            self.synced.insert(
+                entity,
+                SyncedBody {
+                    position,
+                    velocity,
+                    body_status: body_desc.body_status,
+                    mass: body_desc.mass,
+                    linear_damping: body_desc.linear_damping,
+                    angular_damping: body_desc.angular_damping,
+                },
+            );
         }
 
-        self.update_timer -= self.update_interval;
+        // Like additions and removals, changes made by other systems are found by comparing the
+        // components with what was last synced. Changed<Transform> would also hold the writes of
+        // this system, and comparing is cheap next to the step.
+        for (entity, body_desc) in body_reader.iter() {
+            let synced = self.synced.get_mut(&entity).unwrap();
+            let body: &mut RigidBody<T> = bodies
+                .get_mut(self.handle_lookup[&entity])
+                .unwrap()
+                .downcast_mut()
+                .unwrap();
+            let mut changed = false;
 
-        for (entity, body_desc) in body_reader.iter_mut() {
-            if !self.handle_lookup.contains_key(&entity) {
-                let transform = transform_reader
-                    .fetch(entity)
-                    .expect("entity missing transformation");
+            if body_desc.body_status != synced.body_status {
+                body.set_status(body_desc.body_status);
+                synced.body_status = body_desc.body_status;
+                changed = true;
+            }
 
-                // try converting transform matrix into isometry
-                let position = nalgebra::try_convert_ref(&transform.0).unwrap();
+            if body_desc.mass != synced.mass {
+                body.set_mass(body_desc.mass);
+                synced.mass = body_desc.mass;
+                changed = true;
+            }
 
-                let body = RigidBodyDesc::new()
-                    .mass(body_desc.mass)
-                    .status(body_desc.body_status)
-                    .position(position)
-                    .linear_damping(body_desc.linear_damping)
-                    .angular_damping(body_desc.angular_damping)
-                    .build();
+            if body_desc.linear_damping != synced.linear_damping {
+                body.set_linear_damping(body_desc.linear_damping);
+                synced.linear_damping = body_desc.linear_damping;
+            }
 
-                let body_handle = bodies.insert(body);
+            if body_desc.angular_damping != synced.angular_damping {
+                body.set_angular_damping(body_desc.angular_damping);
+                synced.angular_damping = body_desc.angular_damping;
+            }
+
+            // moved by another system, eq. teleported, or along with its parent
+            if let Some(transform) = transform_reader.fetch(entity) {
+                let position = world_pose(entity, &transform.0, &transform_reader, &parent_reader);
+
+                if moved(&position, &synced.position) {
+                    body.set_position(position);
+                    changed = true;
+                }
+            }
 
-                body_desc.handle = Some(body_handle);
+            if let Some(velocity) = velocity_reader.fetch(entity) {
+                if accelerated(&velocity.0, &synced.velocity) {
+                    body.set_velocity(velocity.0);
+                    changed = true;
+                }
+            }
 
-                if body_desc.colliders.len() > 1 {
-                    unimplemented!("multibodies")
+            if let Some(forces) = forces_reader.fetch_mut(entity) {
+                for (force, force_type) in forces.0.drain(..) {
+                    body.apply_force(0, &force, force_type, true);
                 }
+            }
+
+            // sleeping bodies don't notice being moved
+            if changed {
+                body.activate();
+            }
+        }
 
-                // add all colliders associated with this body
-                for (i, collider_desc) in body_desc.colliders.iter().enumerate() {
-                    let collider = collider_desc.build(BodyPartHandle(body_handle, i));
-                    colliders.insert(collider);
+        // multibodies whose links changed are rebuilt from scratch
+        let trees = multibody_trees(&link_reader);
+
+        let changed: Vec<Entity> = self
+            .multibody_lookup
+            .iter()
+            .filter(|(root, multibody)| trees.get(root) != Some(&multibody.links))
+            .map(|(root, _)| *root)
+            .collect();
+
+        for root in changed {
+            let multibody = self.multibody_lookup.remove(&root).unwrap();
+
+            for collider_handle in multibody.colliders {
+                colliders.remove(collider_handle);
+                removed_colliders.push(collider_handle);
+            }
+
+            bodies.remove(multibody.handle);
+
+            for entity in multibody.links {
+                if let Some(link) = link_reader.fetch_mut(entity) {
+                    link.handle = None;
                 }
+            }
+        }
+
+        // in a set order, so replays build them the same way
+        let mut new_trees: Vec<(Entity, Vec<Entity>)> = trees
+            .into_iter()
+            .filter(|(root, _)| !self.multibody_lookup.contains_key(root))
+            .collect();
+        new_trees.sort_by_key(|(root, _)| root.0);
+
+        for (root, links) in new_trees {
+            let position = transform_reader
+                .fetch(root)
+                .map(|transform| world_pose(root, &transform.0, &transform_reader, &parent_reader))
+                .expect("multibody root missing transformation");
+
+            let desc = multibody_desc(&links, &link_reader, position);
+            let handle = bodies.insert(desc.build());
+
+            let mut collider_handles = Vec::new();
+
+            for (i, entity) in links.iter().enumerate() {
+                let link = link_reader.fetch_mut(*entity).unwrap();
+                let part_handle = BodyPartHandle(handle, i);
+
+                link.handle = Some(part_handle);
 
-                self.handle_lookup.insert(entity, body_handle);
+                for collider_desc in &link.colliders {
+                    let collider_handle = colliders.insert(collider_desc.build(part_handle));
+
+                    collider_entities.insert(collider_handle, *entity);
+                    collider_handles.push(collider_handle);
+                }
             }
 
-            // TODO: remove entity bodies
+            self.multibody_lookup.insert(
+                root,
+                BuiltMultibody {
+                    handle,
+                    links,
+                    colliders: collider_handles,
+                },
+            );
         }
 
+        mechanical_world.set_timestep(nalgebra::convert(delta.as_secs_f64()));
         mechanical_world.step(
             geometrical_world,
             bodies,
@@ -146,18 +456,690 @@ where
             force_generators,
         );
 
-        // update transformations
+        for event in geometrical_world.contact_events().iter() {
+            let (handle1, handle2, started) = match *event {
+                ContactEvent::Started(handle1, handle2) => (handle1, handle2, true),
+                ContactEvent::Stopped(handle1, handle2) => (handle1, handle2, false),
+            };
+
+            if let (Some(entity1), Some(entity2)) = (
+                collider_entities.get(&handle1),
+                collider_entities.get(&handle2),
+            ) {
+                let (entity1, entity2) = if entity1.0 <= entity2.0 {
+                    (*entity1, *entity2)
+                } else {
+                    (*entity2, *entity1)
+                };
+
+                if count_touch(&mut self.contacts, (entity1, entity2), started) {
+                    collision_events.single_write(if started {
+                        CollisionEvent::ContactStarted(entity1, entity2)
+                    } else {
+                        CollisionEvent::ContactStopped(entity1, entity2)
+                    });
+                }
+            }
+        }
+
+        for event in geometrical_world.proximity_events().iter() {
+            let entered = event.new_status == Proximity::Intersecting;
+            let exited = event.prev_status == Proximity::Intersecting;
+
+            // proximity also goes between disjoint and within margin, which isn't an overlap
+            if entered == exited {
+                continue;
+            }
+
+            // collider1 is taken as the sensor if both are
+            let is_sensor1 = colliders
+                .get(event.collider1)
+                .map_or(false, |collider| collider.is_sensor());
+            let (sensor, other) = if is_sensor1 {
+                (event.collider1, event.collider2)
+            } else {
+                (event.collider2, event.collider1)
+            };
+
+            if let (Some(sensor), Some(entity)) = (
+                collider_entities.get(&sensor),
+                collider_entities.get(&other),
+            ) {
+                let (sensor, entity) = (*sensor, *entity);
+
+                if count_touch(&mut self.overlaps, (sensor, entity), entered) {
+                    collision_events.single_write(if entered {
+                        CollisionEvent::SensorEntered { sensor, entity }
+                    } else {
+                        CollisionEvent::SensorExited { sensor, entity }
+                    });
+                }
+            }
+        }
+
+        for collider_handle in removed_colliders {
+            collider_entities.remove(&collider_handle);
+        }
+
+        // update transformations, all poses are taken before any is written, so parents that
+        // are bodies themselves are found where they are after the step
+        let mut poses: HashMap<Entity, Isometry<T>> = HashMap::new();
+
         for (entity, _) in body_reader.iter() {
-            // we can unwrap here, since we already made sure all entities who have bodies are in
-            // the lookup table, and since we have a Read on the body component storage, we can be
-            // sure it didn't get altered during physics world step
+            // we can unwrap here, since every added body component got a body above, and since we
+            // have a Write on the body component storage, we can be sure it didn't get altered
+            // during physics world step
             let body_handle = self.handle_lookup.get(&entity).unwrap();
             // we only support rigid bodies for now, so downcasting is OK here
             let body: &RigidBody<T> = bodies.get(*body_handle).unwrap().downcast_ref().unwrap();
+            let synced = self.synced.get_mut(&entity).unwrap();
+
+            synced.position = body.position().clone();
+            synced.velocity = *body.velocity();
+
+            poses.insert(entity, synced.position.clone());
+
+            if velocity_reader.fetch(entity).is_some() {
+                velocity_reader.set(entity, Velocity(synced.velocity));
+            }
+        }
+
+        for multibody in self.multibody_lookup.values() {
+            let body = bodies.get(multibody.handle).unwrap();
+
+            for (i, entity) in multibody.links.iter().enumerate() {
+                poses.insert(*entity, body.part(i).unwrap().position());
+            }
+        }
+
+        let locals: Vec<(Entity, Isometry<T>)> = poses
+            .iter()
+            .map(|(entity, pose)| {
+                let local = match parent_pose(*entity, &transform_reader, &parent_reader, &poses) {
+                    Some(parent) => parent.inverse() * pose,
+                    None => pose.clone(),
+                };
+
+                (*entity, local)
+            })
+            .collect();
+
+        for (entity, local) in locals {
+            transform_reader.set(entity, Transform(local));
+        }
+    }
+}
 
-            let new_transform = Transform(body.position().clone());
+/// pose in world space of entity with local transform, see `parent_pose`
+fn world_pose<T: RealField>(
+    entity: Entity,
+    local: &Isometry<T>,
+    transforms: &Write<Transform<T>>,
+    parents: &Read<Parent>,
+) -> Isometry<T> {
+    match parent_pose(entity, transforms, parents, &HashMap::new()) {
+        Some(parent) => parent * local,
+        None => local.clone(),
+    }
+}
+
+/// pose in world space of the parent of entity, or None if entity is a root like in
+/// `TransformSystem`. Composed from the Transforms of its ancestors, up to the first of them in
+/// stepped, whose pose is taken from there instead. `GlobalTransform` can't be used, as it's
+/// interpolated for rendering and a frame behind.
+fn parent_pose<T: RealField>(
+    entity: Entity,
+    transforms: &Write<Transform<T>>,
+    parents: &Read<Parent>,
+    stepped: &HashMap<Entity, Isometry<T>>,
+) -> Option<Isometry<T>> {
+    let mut ancestors = vec![entity];
+    let mut pose: Option<Isometry<T>> = None;
+    let compose = |ancestor: &Isometry<T>, pose: Option<Isometry<T>>| match pose {
+        Some(pose) => ancestor * pose,
+        None => ancestor.clone(),
+    };
+
+    while let Some(parent) = parents.fetch(*ancestors.last().unwrap()) {
+        // entities in parent cycles are roots, as they have no GlobalTransform either
+        if ancestors.contains(&parent.0) {
+            return None;
+        }
+
+        if let Some(stepped) = stepped.get(&parent.0) {
+            return Some(compose(stepped, pose));
+        }
 
-            transform_reader.set(entity, new_transform);
+        match transforms.fetch(parent.0) {
+            Some(transform) => pose = Some(compose(&transform.0, pose)),
+            None => break,
         }
+
+        ancestors.push(parent.0);
+    }
+
+    pose
+}
+
+/// link entities of every multibody by their root, in depth first order like nphysics numbers
+/// the links. Links whose parent isn't a link are left out until it is.
+fn multibody_trees<T: RealField>(links: &Write<MultibodyLink<T>>) -> HashMap<Entity, Vec<Entity>> {
+    let mut children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    let mut roots = Vec::new();
+
+    for (entity, link) in links.iter() {
+        match link.parent {
+            Some(parent) => children.entry(parent).or_default().push(entity),
+            None => roots.push(entity),
+        }
+    }
+
+    // keep link numbering the same no matter the storage order
+    for siblings in children.values_mut() {
+        siblings.sort_by_key(|entity| entity.0);
+    }
+
+    roots
+        .into_iter()
+        .map(|root| {
+            let mut tree = Vec::new();
+            let mut stack = vec![root];
+
+            while let Some(entity) = stack.pop() {
+                tree.push(entity);
+
+                if let Some(siblings) = children.get(&entity) {
+                    stack.extend(siblings.iter().rev());
+                }
+            }
+
+            (root, tree)
+        })
+        .collect()
+}
+
+/// calls $build with the nphysics joint of a `LinkJoint`, placed at $position if it's free or
+/// fixed
+macro_rules! with_joint {
+    ($joint:expr, $position:expr, $($build:tt)+) => {
+        match $joint {
+            LinkJoint::Free => $($build)+(FreeJoint::new($position)),
+            LinkJoint::Fixed => $($build)+(FixedJoint::new($position)),
+            #[cfg(feature = "3d")]
+            LinkJoint::Revolute { axis, angle } => $($build)+(RevoluteJoint::new(*axis, *angle)),
+            #[cfg(feature = "2d")]
+            LinkJoint::Revolute { angle } => $($build)+(RevoluteJoint::new(*angle)),
+            LinkJoint::Prismatic { axis, offset } => {
+                $($build)+(PrismaticJoint::new(*axis, *offset))
+            }
+        }
+    };
+}
+
+/// describes the multibody of tree, as given by `multibody_trees`, with its root at position
+fn multibody_desc<T: RealField>(
+    tree: &[Entity],
+    links: &Write<MultibodyLink<T>>,
+    position: Isometry<T>,
+) -> MultibodyDesc<T> {
+    let root = links.fetch(tree[0]).unwrap();
+
+    // free and fixed joints take the position, others are moved there
+    let parent_shift = match root.joint {
+        LinkJoint::Free | LinkJoint::Fixed => root.parent_shift,
+        _ => root.parent_shift + position.translation.vector,
+    };
+
+    let mut desc = with_joint!(&root.joint, position, MultibodyDesc::new)
+        .parent_shift(parent_shift)
+        .body_shift(root.body_shift)
+        .mass(root.mass);
+
+    add_children(&mut desc, tree[0], &tree[1..], links);
+
+    desc
+}
+
+/// adds the children of parent, found in the rest of its tree, and theirs, to desc
+fn add_children<T: RealField>(
+    desc: &mut MultibodyDesc<T>,
+    parent: Entity,
+    rest: &[Entity],
+    links: &Write<MultibodyLink<T>>,
+) {
+    for (i, entity) in rest.iter().enumerate() {
+        let link = links.fetch(*entity).unwrap();
+
+        if link.parent != Some(parent) {
+            continue;
+        }
+
+        let child = with_joint!(&link.joint, Isometry::identity(), desc.add_child);
+
+        child
+            .set_parent_shift(link.parent_shift)
+            .set_body_shift(link.body_shift)
+            .set_mass(link.mass);
+
+        // depth first, so the descendants of the child come right after it
+        add_children(child, *entity, &rest[i + 1..], links);
+    }
+}
+
+// the geometry is 2d, with y up
+#[cfg(all(test, feature = "2d"))]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::ecs::resource::ReadRes;
+    use crate::ecs::system::SystemData;
+    use crate::ecs::world::DefaultWorld;
+    use crate::ecs::Dispatcher;
+    use crate::nphysics::ncollide::shape::{Ball, ShapeHandle};
+    use crate::nphysics::object::ColliderDesc;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    /// a world without gravity, so bodies move by their velocity only
+    fn world() -> DefaultWorld {
+        DefaultWorld::new()
+            .register::<PhysicsBody<f32>>()
+            .register::<MultibodyLink<f32>>()
+            .register::<Transform<f32>>()
+            .register::<Parent>()
+            .register::<Velocity<f32>>()
+            .register::<Forces<f32>>()
+            .with_resource(PhysicsWorld::new(Vector::zeros()))
+            .with_event_channel::<CollisionEvent>()
+    }
+
+    /// a body made of a ball with a radius of 0.25
+    fn body(body_status: BodyStatus) -> PhysicsBody<f32> {
+        PhysicsBody {
+            colliders: vec![ColliderDesc::new(ShapeHandle::new(Ball::new(0.25)))],
+            body_status,
+            mass: 1.,
+            linear_damping: 0.,
+            angular_damping: 0.,
+            handle: None,
+        }
+    }
+
+    fn run(dispatcher: &mut Dispatcher<DefaultWorld>, world: &DefaultWorld, ticks: usize) {
+        for _ in 0..ticks {
+            dispatcher.dispatch(
+                world,
+                TICK,
+                &IoState::default(),
+                &mut RenderContext::null((0, 0)),
+                &mut DebugContext::null(),
+            );
+        }
+    }
+
+    /// a link made of a ball with a radius of 0.25
+    fn link(
+        parent: Option<Entity>,
+        joint: LinkJoint<f32>,
+        parent_shift: Vector<f32>,
+        body_shift: Vector<f32>,
+    ) -> MultibodyLink<f32> {
+        MultibodyLink {
+            parent,
+            joint,
+            parent_shift,
+            body_shift,
+            mass: 1.,
+            colliders: vec![ColliderDesc::new(ShapeHandle::new(Ball::new(0.25)))],
+            handle: None,
+        }
+    }
+
+    /// numbers of bodies and colliders in the physics world
+    fn counts(world: &DefaultWorld) -> (usize, usize) {
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(world);
+
+        (
+            physics_world.bodies.iter().count(),
+            physics_world.colliders.iter().count(),
+        )
+    }
+
+    fn translation(world: &DefaultWorld, entity: Entity) -> Vector<f32> {
+        let transforms: Read<Transform<f32>> = SystemData::get(world);
+
+        transforms.fetch(entity).unwrap().0.translation.vector
+    }
+
+    fn assert_near(value: Vector<f32>, expected: Vector<f32>) {
+        assert!(
+            (value - expected).norm() < 1.0e-3,
+            "{} is not near {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn parented_bodies_move_in_their_parents_space() {
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::translation(10., 0.)));
+        let parent = builder.entity();
+        let builder = builder
+            .build()
+            .add_entity()
+            .with_component(Transform(Isometry::translation(1., 0.)))
+            .with_component(Parent(parent))
+            .with_component(Velocity(math::Velocity::linear(1., 0.)))
+            .with_component(body(BodyStatus::Dynamic));
+        let child = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+
+        run(&mut dispatcher, &world, 10);
+        assert_near(translation(&world, child), Vector::new(1.1, 0.));
+
+        // the body follows its parent
+        {
+            let mut transforms: Write<Transform<f32>> = SystemData::get(&world);
+            transforms.set(parent, Transform(Isometry::translation(20., 0.)));
+        }
+        run(&mut dispatcher, &world, 10);
+        assert_near(translation(&world, child), Vector::new(1.2, 0.));
+    }
+
+    #[test]
+    fn children_of_bodies_are_placed_after_the_step() {
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::translation(10., 0.)))
+            .with_component(Velocity(math::Velocity::linear(1., 0.)))
+            .with_component(body(BodyStatus::Dynamic));
+        let parent = builder.entity();
+        let builder = builder
+            .build()
+            .add_entity()
+            .with_component(Transform(Isometry::translation(1., 0.)))
+            .with_component(Parent(parent))
+            .with_component(Velocity(math::Velocity::linear(1., 0.)))
+            .with_component(body(BodyStatus::Dynamic));
+        let child = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+
+        run(&mut dispatcher, &world, 10);
+
+        // moving together, so the child stays where it is relative to the parent
+        assert_near(translation(&world, parent), Vector::new(10.1, 0.));
+        assert_near(translation(&world, child), Vector::new(1., 0.));
+    }
+
+    #[test]
+    fn compound_bodies_get_a_collider_per_shape() {
+        let shapes = vec![
+            ColliderDesc::new(ShapeHandle::new(Ball::new(0.25))).translation(Vector::new(1., 0.)),
+            ColliderDesc::new(ShapeHandle::new(Ball::new(0.25))).translation(Vector::new(-1., 0.)),
+        ];
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::new(Vector::new(10., 0.), FRAC_PI_2)))
+            .with_component(PhysicsBody {
+                colliders: shapes,
+                ..body(BodyStatus::Dynamic)
+            });
+        let entity = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+
+        run(&mut dispatcher, &world, 1);
+        assert_eq!(counts(&world), (1, 2));
+
+        // the offsets turn with the body
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(&world);
+        let mut positions: Vec<Vector<f32>> = physics_world
+            .colliders
+            .iter()
+            .map(|(_, collider)| collider.position().translation.vector)
+            .collect();
+        positions.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap());
+
+        assert_near(positions[0], Vector::new(10., -1.));
+        assert_near(positions[1], Vector::new(10., 1.));
+        assert!(physics_world
+            .collider_entities
+            .values()
+            .all(|owner| *owner == entity));
+    }
+
+    #[test]
+    fn multibody_links_are_placed_by_their_joints() {
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::translation(5., 0.)))
+            .with_component(link(
+                None,
+                LinkJoint::Fixed,
+                Vector::zeros(),
+                Vector::zeros(),
+            ));
+        let root = builder.entity();
+        let builder = builder
+            .build()
+            .add_entity()
+            .with_component(Transform(Isometry::identity()))
+            .with_component(link(
+                Some(root),
+                LinkJoint::Revolute { angle: FRAC_PI_2 },
+                Vector::new(1., 0.),
+                Vector::new(1., 0.),
+            ));
+        let child = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+
+        run(&mut dispatcher, &world, 1);
+        assert_eq!(counts(&world), (1, 2));
+
+        // the joint is a step to the right of the root, the child turned up from there
+        assert_near(translation(&world, root), Vector::new(5., 0.));
+        assert_near(translation(&world, child), Vector::new(6., 1.));
+
+        let links: Read<MultibodyLink<f32>> = SystemData::get(&world);
+        let child_part = links.fetch(child).unwrap().handle.unwrap();
+
+        // parts of the same multibody, numbered from the root
+        assert_eq!(
+            links.fetch(root).unwrap().handle,
+            Some(BodyPartHandle(child_part.0, 0))
+        );
+        assert_eq!(child_part.1, 1);
+    }
+
+    #[test]
+    fn removed_components_take_their_bodies_along() {
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::translation(-5., 0.)))
+            .with_component(body(BodyStatus::Dynamic));
+        let entity = builder.entity();
+        let builder = builder
+            .build()
+            .add_entity()
+            .with_component(Transform(Isometry::translation(5., 0.)))
+            .with_component(link(
+                None,
+                LinkJoint::Fixed,
+                Vector::zeros(),
+                Vector::zeros(),
+            ));
+        let root = builder.entity();
+        let builder = builder
+            .build()
+            .add_entity()
+            .with_component(Transform(Isometry::identity()))
+            .with_component(link(
+                Some(root),
+                LinkJoint::Revolute { angle: 0. },
+                Vector::new(1., 0.),
+                Vector::zeros(),
+            ));
+        let child = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+        let owners = |world: &DefaultWorld| {
+            let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(world);
+            let mut owners: Vec<Entity> =
+                physics_world.collider_entities.values().cloned().collect();
+            owners.sort_by_key(|owner| owner.0);
+
+            owners
+        };
+
+        run(&mut dispatcher, &world, 1);
+        assert_eq!(counts(&world), (2, 3));
+        assert_eq!(owners(&world), vec![entity, root, child]);
+
+        Write::<PhysicsBody<f32>>::get(&world).unset(entity);
+        run(&mut dispatcher, &world, 1);
+        assert_eq!(counts(&world), (1, 2));
+        assert_eq!(owners(&world), vec![root, child]);
+
+        // the multibody is rebuilt without the link
+        Write::<MultibodyLink<f32>>::get(&world).unset(child);
+        run(&mut dispatcher, &world, 1);
+        assert_eq!(counts(&world), (1, 1));
+        assert_eq!(owners(&world), vec![root]);
+
+        Write::<MultibodyLink<f32>>::get(&world).unset(root);
+        run(&mut dispatcher, &world, 1);
+        assert_eq!(counts(&world), (0, 0));
+        assert!(owners(&world).is_empty());
+    }
+
+    #[test]
+    fn written_transforms_teleport_bodies() {
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::identity()))
+            .with_component(Velocity(math::Velocity::linear(1., 0.)))
+            .with_component(body(BodyStatus::Dynamic));
+        let entity = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+
+        run(&mut dispatcher, &world, 10);
+        assert_near(translation(&world, entity), Vector::new(0.1, 0.));
+
+        Write::<Transform<f32>>::get(&world).set(entity, Transform(Isometry::translation(5., 5.)));
+        run(&mut dispatcher, &world, 10);
+
+        // and keep going from there
+        assert_near(translation(&world, entity), Vector::new(5.1, 5.));
+    }
+
+    #[test]
+    fn body_changes_reach_the_rigid_body() {
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::identity()))
+            .with_component(body(BodyStatus::Dynamic));
+        let entity = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+
+        run(&mut dispatcher, &world, 1);
+
+        {
+            let mut bodies: Write<PhysicsBody<f32>> = SystemData::get(&world);
+            let body = bodies.fetch_mut(entity).unwrap();
+
+            body.body_status = BodyStatus::Kinematic;
+            body.mass = 2.;
+            body.linear_damping = 0.5;
+            body.angular_damping = 0.25;
+        }
+        run(&mut dispatcher, &world, 1);
+
+        let handle = Read::<PhysicsBody<f32>>::get(&world)
+            .fetch(entity)
+            .unwrap()
+            .handle
+            .unwrap();
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(&world);
+        let body = physics_world.bodies.rigid_body(handle).unwrap();
+
+        assert_eq!(body.status(), BodyStatus::Kinematic);
+        assert_eq!(body.local_inertia().linear, 2.);
+        assert_eq!(body.linear_damping(), 0.5);
+        assert_eq!(body.angular_damping(), 0.25);
+    }
+
+    #[test]
+    fn velocities_and_forces_are_applied_and_drained() {
+        let builder = world()
+            .add_entity()
+            .with_component(Transform(Isometry::identity()))
+            .with_component(Velocity(math::Velocity::zero()))
+            .with_component(Forces::default())
+            .with_component(body(BodyStatus::Dynamic));
+        let entity = builder.entity();
+        let world = builder.build();
+        let mut dispatcher = Dispatcher::new().with(PhysicsSystem::<f32>::new());
+        let velocity = |world: &DefaultWorld| {
+            let velocities: Read<Velocity<f32>> = SystemData::get(world);
+
+            velocities.fetch(entity).unwrap().0.linear
+        };
+        let drained = |world: &DefaultWorld| {
+            let forces: Read<Forces<f32>> = SystemData::get(world);
+
+            forces.fetch(entity).unwrap().0.is_empty()
+        };
+
+        run(&mut dispatcher, &world, 1);
+        Write::<Velocity<f32>>::get(&world).set(entity, Velocity(math::Velocity::linear(2., 0.)));
+        run(&mut dispatcher, &world, 10);
+        assert_near(translation(&world, entity), Vector::new(0.2, 0.));
+        assert_near(velocity(&world), Vector::new(2., 0.));
+
+        // an impulse changes the velocity at once, for a mass of 1 by as much
+        Write::<Forces<f32>>::get(&world)
+            .fetch_mut(entity)
+            .unwrap()
+            .add_impulse(math::Force::linear(Vector::new(0., 1.)));
+        run(&mut dispatcher, &world, 1);
+        assert_near(velocity(&world), Vector::new(2., 1.));
+        assert!(drained(&world));
+
+        // a force of 100 accelerates by 100 per second, for a 10ms tick
+        Write::<Forces<f32>>::get(&world)
+            .fetch_mut(entity)
+            .unwrap()
+            .add_force(math::Force::linear(Vector::new(-100., 0.)));
+        run(&mut dispatcher, &world, 1);
+        assert_near(velocity(&world), Vector::new(1., 1.));
+        assert!(drained(&world));
+
+        // drained forces aren't applied again
+        run(&mut dispatcher, &world, 1);
+        assert_near(velocity(&world), Vector::new(1., 1.));
+    }
+
+    #[test]
+    fn touches_are_counted_per_pair() {
+        let mut counts = HashMap::new();
+        let (a, b) = (Entity(0), Entity(1));
+
+        assert!(count_touch(&mut counts, (a, b), true));
+        assert!(!count_touch(&mut counts, (a, b), true));
+        assert!(count_touch(&mut counts, (b, a), true), "pairs are ordered");
+
+        assert!(!count_touch(&mut counts, (a, b), false));
+        assert!(count_touch(&mut counts, (a, b), false));
+        assert!(!count_touch(&mut counts, (a, b), false), "already stopped");
+
+        assert!(count_touch(&mut counts, (a, b), true));
     }
 }
@@ -0,0 +1,500 @@
+//! Kinematic character controller
+
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::nphysics::{
+    math::{Isometry, Vector},
+    ncollide::{pipeline::CollisionGroups, shape::Shape},
+};
+use nalgebra::RealField;
+
+use crate::debug::DebugContext;
+use crate::ecs::component::{CharacterController, Transform};
+use crate::ecs::resource::ReadRes;
+use crate::ecs::system::physics::PhysicsWorld;
+use crate::ecs::system::Write;
+use crate::ecs::world::{World, WorldStorage};
+use crate::ecs::{Entity, System};
+use crate::game::IoState;
+use crate::gfx::RenderContext;
+
+/// times a move slides along what it hit before the rest of it is dropped
+const MAX_SLIDES: usize = 4;
+
+/// Moves `CharacterController`s by their desired velocity and gravity, against the colliders of
+/// the `PhysicsWorld`. Characters don't push anything, so give them a kinematic `PhysicsBody` if
+/// bodies should bump into them, and leave it out with `CharacterController::collision_groups`.
+/// Characters are moved in world space, so their entity can't have a `Parent`. Meant for fixed
+/// ticks, before `PhysicsSystem`.
+pub struct CharacterControllerSystem<T: RealField> {
+    marker: PhantomData<T>,
+}
+
+impl<T: RealField> CharacterControllerSystem<T> {
+    /// creates a new CharacterControllerSystem. The World needs a `PhysicsWorld` resource.
+    pub fn new() -> CharacterControllerSystem<T> {
+        CharacterControllerSystem {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<W: World, T: RealField> System<W> for CharacterControllerSystem<T>
+where
+    W: WorldStorage<CharacterController<T>> + WorldStorage<Transform<T>>,
+{
+    type SystemData<'a> = (
+        ReadRes<'a, PhysicsWorld<T>>,
+        Write<'a, CharacterController<T>>,
+        Write<'a, Transform<T>>,
+    );
+
+    fn name(&self) -> &'static str {
+        "CharacterControllerSystem"
+    }
+
+    fn update<'f>(
+        &mut self,
+        (physics_world, mut controller_writer, mut transform_writer): Self::SystemData<'f>,
+        delta: Duration,
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> () {
+        let dt: T = nalgebra::convert(delta.as_secs_f64());
+        let gravity = physics_world.mechanical_world.gravity;
+        let up = (-gravity)
+            .try_normalize(T::default_epsilon())
+            .unwrap_or_else(Vector::y);
+
+        for (entity, mut controller) in controller_writer.iter_mut() {
+            let start = match transform_writer.fetch(entity) {
+                Some(transform) => transform.0.clone(),
+                None => continue,
+            };
+
+            let mover = Mover {
+                physics_world: &physics_world,
+                entity,
+                shape: &*controller.shape,
+                groups: &controller.collision_groups,
+                up,
+                min_ground_dot: controller.max_slope.cos(),
+                skin: nalgebra::convert(1.0e-3),
+            };
+
+            // worked on in copies, and only written back if they changed, so characters that
+            // stand still don't show up as changed
+            let mut position = start;
+            let mut vertical_speed = controller.vertical_speed;
+
+            if controller.grounded {
+                vertical_speed = if controller.jump {
+                    controller.jump_speed
+                } else {
+                    T::zero()
+                };
+            }
+            vertical_speed -= gravity.norm() * dt;
+
+            let walking = controller.grounded && vertical_speed <= T::zero();
+
+            // step up first, so the walk goes over anything lower than the step
+            let mut stepped = T::zero();
+            if walking && controller.step_offset > T::zero() {
+                let before = position.translation.vector;
+
+                mover.step(&mut position, &(up * controller.step_offset));
+                stepped = (position.translation.vector - before).dot(&up);
+            }
+
+            let walk = controller.desired_velocity - up * controller.desired_velocity.dot(&up);
+            mover.slide(&mut position, walk * dt, true);
+
+            let vertical = vertical_speed * dt - stepped;
+            let mut grounded = false;
+
+            if vertical > T::zero() {
+                // bumped head
+                if mover.step(&mut position, &(up * vertical)).is_some() {
+                    vertical_speed = T::zero();
+                }
+            } else {
+                // while walking, look for ground a step lower too, to stay on it going down
+                let snap = if walking {
+                    controller.step_offset
+                } else {
+                    T::zero()
+                };
+                let mut ground = position;
+
+                match mover.step(&mut ground, &(-up * (snap - vertical + mover.skin))) {
+                    Some(normal) if normal.dot(&up) >= mover.min_ground_dot => {
+                        position = ground;
+                        grounded = true;
+                        vertical_speed = T::zero();
+                    }
+                    // in the air or on a slope too steep to stand on, which it slides down
+                    _ => mover.slide(&mut position, up * vertical, false),
+                }
+            }
+
+            if controller.jump
+                || controller.grounded != grounded
+                || controller.vertical_speed != vertical_speed
+            {
+                controller.jump = false;
+                controller.grounded = grounded;
+                controller.vertical_speed = vertical_speed;
+            }
+
+            if position != start {
+                transform_writer.set(entity, Transform(position));
+            }
+        }
+    }
+}
+
+/// Moves a character shape through the physics world
+struct Mover<'a, T: RealField> {
+    physics_world: &'a PhysicsWorld<T>,
+    /// the character, so its own body isn't hit
+    entity: Entity,
+    shape: &'a dyn Shape<T>,
+    groups: &'a CollisionGroups,
+    /// against gravity
+    up: Vector<T>,
+    /// cosine of the steepest slope to stand on
+    min_ground_dot: T,
+    /// gap kept to whatever is hit, so the next move doesn't start overlapping it
+    skin: T,
+}
+
+impl<'a, T: RealField> Mover<'a, T> {
+    /// moves position by motion, or up to the first hit. Returns the normal of the hit.
+    fn step(&self, position: &mut Isometry<T>, motion: &Vector<T>) -> Option<Vector<T>> {
+        let length = motion.norm();
+
+        if length <= T::default_epsilon() {
+            return None;
+        }
+
+        let direction = motion / length;
+        let entity = self.entity;
+        let hit = self.physics_world.cast_shape_filtered(
+            self.shape,
+            position,
+            &direction,
+            length + self.skin,
+            self.groups,
+            |hit| hit != entity,
+        );
+
+        match hit {
+            Some(hit) => {
+                position.translation.vector += direction * (hit.toi - self.skin).max(T::zero());
+
+                Some(hit.normal.into_inner())
+            }
+            None => {
+                position.translation.vector += motion;
+
+                None
+            }
+        }
+    }
+
+    /// moves position by motion, sliding along what's hit. With walls set, slopes too steep to
+    /// stand on block like walls do, instead of being walked up.
+    fn slide(&self, position: &mut Isometry<T>, motion: Vector<T>, walls: bool) {
+        let mut remaining = motion;
+
+        for _ in 0..MAX_SLIDES {
+            let before = position.translation.vector;
+            let mut normal = match self.step(position, &remaining) {
+                Some(normal) => normal,
+                None => return,
+            };
+
+            remaining -= position.translation.vector - before;
+
+            let along_up = normal.dot(&self.up);
+
+            if walls && along_up > T::zero() && along_up < self.min_ground_dot {
+                normal = (normal - self.up * along_up)
+                    .try_normalize(T::default_epsilon())
+                    .unwrap_or(normal);
+            }
+
+            // the part of the move into the surface is dropped
+            let into = remaining.dot(&normal);
+
+            if into < T::zero() {
+                remaining -= normal * into;
+            }
+        }
+    }
+}
+
+// the geometry is 2d, with y up
+#[cfg(all(test, feature = "2d"))]
+mod tests {
+    use super::*;
+    use crate::ecs::component::{Forces, MultibodyLink, Parent, PhysicsBody, Velocity};
+    use crate::ecs::system::physics::{CollisionEvent, PhysicsSystem};
+    use crate::ecs::system::{Read, SystemData};
+    use crate::ecs::world::DefaultWorld;
+    use crate::ecs::Dispatcher;
+    use crate::nphysics::ncollide::shape::{Cuboid, ShapeHandle};
+    use crate::nphysics::object::{BodyStatus, ColliderDesc};
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    /// a world with a character in it, and the systems that move it
+    struct Sim {
+        world: DefaultWorld,
+        dispatcher: Dispatcher<DefaultWorld>,
+    }
+
+    impl Sim {
+        /// a world with the ground's top at a height of 0, the static boxes of boxes, by their
+        /// half extents and position, and a character 0.5 wide and 1 high standing at x = 0
+        fn new(boxes: &[(Vector<f32>, Isometry<f32>)]) -> Sim {
+            let mut world = DefaultWorld::new()
+                .register::<PhysicsBody<f32>>()
+                .register::<MultibodyLink<f32>>()
+                .register::<Transform<f32>>()
+                .register::<Parent>()
+                .register::<Velocity<f32>>()
+                .register::<Forces<f32>>()
+                .register::<CharacterController<f32>>()
+                .with_resource(PhysicsWorld::new(Vector::y() * -10.))
+                .with_event_channel::<CollisionEvent>();
+
+            let ground = (Vector::new(50., 1.), Isometry::translation(0., -1.));
+
+            for (half_extents, position) in boxes.iter().chain(Some(&ground)) {
+                let collider = ColliderDesc::new(ShapeHandle::new(Cuboid::new(*half_extents)));
+
+                world = world
+                    .add_entity()
+                    .with_component(Transform(*position))
+                    .with_component(PhysicsBody {
+                        colliders: vec![collider],
+                        body_status: BodyStatus::Static,
+                        mass: 1.,
+                        linear_damping: 0.,
+                        angular_damping: 0.,
+                        handle: None,
+                    })
+                    .build();
+            }
+
+            let world = world
+                .add_entity()
+                .with_component(Transform(Isometry::translation(0., 0.5)))
+                .with_component(CharacterController {
+                    step_offset: 0.3,
+                    jump_speed: 5.,
+                    grounded: true,
+                    ..CharacterController::new(ShapeHandle::new(Cuboid::new(Vector::new(
+                        0.25, 0.5,
+                    ))))
+                })
+                .build();
+
+            let dispatcher = Dispatcher::new()
+                .with(PhysicsSystem::<f32>::new())
+                .with(CharacterControllerSystem::<f32>::new());
+
+            Sim { world, dispatcher }
+        }
+
+        fn run(&mut self, ticks: usize) {
+            for _ in 0..ticks {
+                self.dispatcher.dispatch(
+                    &self.world,
+                    TICK,
+                    &IoState::default(),
+                    &mut RenderContext::null((0, 0)),
+                    &mut DebugContext::null(),
+                );
+            }
+        }
+
+        fn character(&self) -> Entity {
+            *self.world.entities().last().unwrap()
+        }
+
+        /// position, grounded and vertical speed of the character
+        fn state(&self) -> (Vector<f32>, bool, f32) {
+            let (controllers, transforms): (Read<CharacterController<f32>>, Read<Transform<f32>>) =
+                SystemData::get(&self.world);
+            let controller = controllers.fetch(self.character()).unwrap();
+            let transform = transforms.fetch(self.character()).unwrap();
+
+            (
+                transform.0.translation.vector,
+                controller.grounded,
+                controller.vertical_speed,
+            )
+        }
+
+        fn place(&self, position: Isometry<f32>) {
+            let mut transforms: Write<Transform<f32>> = SystemData::get(&self.world);
+
+            transforms.set(self.character(), Transform(position));
+        }
+
+        fn control(&self, control: impl FnOnce(&mut CharacterController<f32>)) {
+            let mut controllers: Write<CharacterController<f32>> = SystemData::get(&self.world);
+
+            for (_, mut controller) in controllers.iter_mut() {
+                control(&mut *controller);
+            }
+        }
+    }
+
+    fn assert_near(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < 0.01,
+            "{} is not near {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn walks_on_flat_ground() {
+        let mut sim = Sim::new(&[]);
+
+        sim.run(10);
+        let (position, grounded, vertical_speed) = sim.state();
+        assert_near(position.y, 0.5);
+        assert!(grounded);
+        assert_eq!(vertical_speed, 0.);
+
+        sim.control(|controller| controller.desired_velocity = Vector::new(2., 0.));
+        sim.run(50);
+        let (position, grounded, _) = sim.state();
+        assert_near(position.x, 1.);
+        assert_near(position.y, 0.5);
+        assert!(grounded);
+    }
+
+    #[test]
+    fn walks_up_and_down_steps() {
+        // a step 0.2 high, from x = 1 on
+        let step = (Vector::new(10., 0.1), Isometry::translation(11., 0.1));
+        let mut sim = Sim::new(&[step]);
+
+        sim.control(|controller| controller.desired_velocity = Vector::new(2., 0.));
+        sim.run(100);
+        let (position, grounded, _) = sim.state();
+        assert_near(position.x, 2.);
+        assert_near(position.y, 0.7);
+        assert!(grounded);
+
+        sim.control(|controller| controller.desired_velocity = Vector::new(-2., 0.));
+        sim.run(100);
+        let (position, grounded, _) = sim.state();
+        assert_near(position.x, 0.);
+        assert_near(position.y, 0.5);
+        assert!(grounded, "stays on the ground going down the step");
+    }
+
+    #[test]
+    fn slides_down_slopes_too_steep_to_stand_on() {
+        let slope = |angle: f32| {
+            (
+                Vector::new(5., 0.5),
+                Isometry::new(Vector::new(0., 3.), angle.to_radians()),
+            )
+        };
+
+        // the character starts above the slopes, with the ground far below
+        let mut shallow = Sim::new(&[slope(30.)]);
+        let mut steep = Sim::new(&[slope(60.)]);
+
+        shallow.place(Isometry::translation(0., 6.));
+        steep.place(Isometry::translation(0., 6.));
+
+        shallow.run(100);
+        let (position, grounded, _) = shallow.state();
+        assert!(grounded);
+        assert_near(position.x, 0.);
+
+        steep.run(70);
+        let (position, grounded, _) = steep.state();
+        assert!(!grounded);
+        assert!(
+            position.x < -0.1,
+            "slid down to the left, to {}",
+            position.x
+        );
+    }
+
+    #[test]
+    fn walls_stop_walking() {
+        // a wall at x = 1.5, and a slope too steep to walk up from x = -3.4 on
+        let wall = (Vector::new(0.5, 5.), Isometry::translation(2., 5.));
+        let slope = (
+            Vector::new(5., 0.5),
+            Isometry::new(Vector::new(-4., 0.), 120_f32.to_radians()),
+        );
+        let mut sim = Sim::new(&[wall, slope]);
+
+        sim.control(|controller| controller.desired_velocity = Vector::new(2., 0.));
+        sim.run(100);
+        let (position, grounded, _) = sim.state();
+        assert_near(position.x, 1.25);
+        assert_near(position.y, 0.5);
+        assert!(grounded);
+
+        sim.control(|controller| controller.desired_velocity = Vector::new(-2., 0.));
+        sim.run(200);
+        let (position, _, _) = sim.state();
+        assert!(position.y < 0.8, "walked up the slope to {}", position.y);
+    }
+
+    #[test]
+    fn jumps_and_lands() {
+        let mut sim = Sim::new(&[]);
+
+        sim.run(10);
+        sim.control(|controller| controller.jump = true);
+        sim.run(1);
+        let (position, grounded, vertical_speed) = sim.state();
+        assert!(!grounded);
+        assert_near(vertical_speed, 4.9);
+        assert!(position.y > 0.5);
+
+        // at the top after half a second, a little under v² / 2g up as gravity is applied first
+        sim.run(49);
+        assert_near(sim.state().0.y, 0.5 + 1.225);
+
+        sim.run(60);
+        let (position, grounded, vertical_speed) = sim.state();
+        assert_near(position.y, 0.5);
+        assert!(grounded);
+        assert_eq!(vertical_speed, 0.);
+    }
+
+    #[test]
+    fn standing_still_changes_no_controllers() {
+        let mut sim = Sim::new(&[]);
+
+        sim.run(10);
+
+        let mut cursor = {
+            let controllers: Read<CharacterController<f32>> = SystemData::get(&sim.world);
+            controllers.changes().tick()
+        };
+
+        sim.run(10);
+
+        let controllers: Read<CharacterController<f32>> = SystemData::get(&sim.world);
+        assert!(controllers.changes().read_changed(&mut cursor).is_empty());
+    }
+}
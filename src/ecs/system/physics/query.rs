@@ -0,0 +1,406 @@
+//! Scene queries on the physics world
+//!
+//! ```ignore
+//! // system with a ReadRes<'a, PhysicsWorld<f32>>
+//! let ray = Ray::new(eye, direction);
+//!
+//! if let Some(hit) = physics_world.cast_ray(&ray, 100., &CollisionGroups::new()) {
+//!     println!("looking at {:?}, {} away", hit.entity, hit.toi);
+//! }
+//! ```
+//!
+//! Hits are reported by the entity of the `PhysicsBody` or `MultibodyLink` the collider belongs
+//! to, so only bodies built by `PhysicsSystem` are found.
+
+use crate::nphysics::{
+    math::{Isometry, Point, Vector},
+    ncollide::{
+        bounding_volume::{BoundingVolume, AABB},
+        pipeline::CollisionGroups,
+        query::{self, Ray},
+        shape::Shape,
+    },
+    object::DefaultColliderHandle,
+};
+use nalgebra::{RealField, Unit};
+use std::cmp::Ordering;
+
+use crate::ecs::system::physics::PhysicsWorld;
+use crate::ecs::Entity;
+
+/// Where a ray hit a collider
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit<T: RealField> {
+    pub entity: Entity,
+    /// distance along the ray, in lengths of its direction
+    pub toi: T,
+    pub point: Point<T>,
+    /// surface normal at point
+    pub normal: Vector<T>,
+}
+
+/// Where a shape cast hit a collider
+#[derive(Clone, Copy, Debug)]
+pub struct ShapeHit<T: RealField> {
+    pub entity: Entity,
+    /// time until the shape touches the collider, in lengths of its velocity
+    pub toi: T,
+    /// point of contact on the collider
+    pub point: Point<T>,
+    /// surface normal of the collider at point
+    pub normal: Unit<Vector<T>>,
+}
+
+impl<T: RealField> PhysicsWorld<T> {
+    /// entity the collider belongs to, if it was built by `PhysicsSystem`
+    pub fn collider_entity(&self, handle: DefaultColliderHandle) -> Option<Entity> {
+        self.collider_entities.get(&handle).cloned()
+    }
+
+    /// nearest hit of ray within max_toi
+    pub fn cast_ray(
+        &self,
+        ray: &Ray<T>,
+        max_toi: T,
+        groups: &CollisionGroups,
+    ) -> Option<RayHit<T>> {
+        self.ray_hits(ray, max_toi, groups)
+            .min_by(|a, b| toi_order(a.toi, b.toi))
+    }
+
+    /// all hits of ray within max_toi, nearest first
+    pub fn cast_ray_all(
+        &self,
+        ray: &Ray<T>,
+        max_toi: T,
+        groups: &CollisionGroups,
+    ) -> Vec<RayHit<T>> {
+        let mut hits: Vec<RayHit<T>> = self.ray_hits(ray, max_toi, groups).collect();
+
+        hits.sort_by(|a, b| toi_order(a.toi, b.toi));
+
+        hits
+    }
+
+    fn ray_hits<'a>(
+        &'a self,
+        ray: &'a Ray<T>,
+        max_toi: T,
+        groups: &'a CollisionGroups,
+    ) -> impl Iterator<Item = RayHit<T>> + 'a {
+        self.geometrical_world
+            .interferences_with_ray(&self.colliders, ray, max_toi, groups)
+            // degenerate rays, eq. with a zero direction, can hit at a NaN toi
+            .filter(|(_, _, intersection)| intersection.toi.is_finite())
+            .filter_map(move |(handle, _, intersection)| {
+                self.collider_entity(handle).map(|entity| RayHit {
+                    entity,
+                    toi: intersection.toi,
+                    point: ray.point_at(intersection.toi),
+                    normal: intersection.normal,
+                })
+            })
+    }
+
+    /// first hit of shape moving from position by velocity, for at most max_toi. Colliders the
+    /// shape already overlaps at position are hit at a toi of zero.
+    pub fn cast_shape(
+        &self,
+        shape: &dyn Shape<T>,
+        position: &Isometry<T>,
+        velocity: &Vector<T>,
+        max_toi: T,
+        groups: &CollisionGroups,
+    ) -> Option<ShapeHit<T>> {
+        self.cast_shape_filtered(shape, position, velocity, max_toi, groups, |_| true)
+    }
+
+    /// like `cast_shape`, but only hits entities filter returns true for, eq. to leave out the
+    /// entity the shape belongs to
+    pub fn cast_shape_filtered(
+        &self,
+        shape: &dyn Shape<T>,
+        position: &Isometry<T>,
+        velocity: &Vector<T>,
+        max_toi: T,
+        groups: &CollisionGroups,
+        filter: impl Fn(Entity) -> bool,
+    ) -> Option<ShapeHit<T>> {
+        let mut end = *position;
+        end.translation.vector += velocity * max_toi;
+
+        // everything the shape could touch on the way
+        let swept = shape.aabb(position).merged(&shape.aabb(&end));
+
+        self.geometrical_world
+            .interferences_with_aabb(&self.colliders, &swept, groups)
+            .filter_map(|(handle, collider)| {
+                let entity = self
+                    .collider_entity(handle)
+                    .filter(|entity| filter(*entity))?;
+                let toi = query::time_of_impact(
+                    position,
+                    velocity,
+                    shape,
+                    collider.position(),
+                    &Vector::zeros(),
+                    collider.shape(),
+                    max_toi,
+                    T::zero(),
+                )
+                .filter(|toi| toi.toi.is_finite())?;
+
+                Some(ShapeHit {
+                    entity,
+                    toi: toi.toi,
+                    point: collider.position() * toi.witness2,
+                    normal: collider.position() * toi.normal2,
+                })
+            })
+            .min_by(|a, b| toi_order(a.toi, b.toi))
+    }
+
+    /// entities with a collider containing point
+    pub fn entities_at_point(&self, point: &Point<T>, groups: &CollisionGroups) -> Vec<Entity> {
+        let handles = self
+            .geometrical_world
+            .interferences_with_point(&self.colliders, point, groups)
+            .map(|(handle, _)| handle);
+
+        self.unique_entities(handles)
+    }
+
+    /// entities with a collider whose bounding box overlaps aabb. Only the boxes are tested, not
+    /// the shapes in them.
+    pub fn entities_in_aabb(&self, aabb: &AABB<T>, groups: &CollisionGroups) -> Vec<Entity> {
+        let handles = self
+            .geometrical_world
+            .interferences_with_aabb(&self.colliders, aabb, groups)
+            .map(|(handle, _)| handle);
+
+        self.unique_entities(handles)
+    }
+
+    /// entities of colliders, each once even if it has several of them
+    fn unique_entities(&self, handles: impl Iterator<Item = DefaultColliderHandle>) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = handles
+            .filter_map(|handle| self.collider_entity(handle))
+            .collect();
+
+        entities.sort_by_key(|entity| entity.0);
+        entities.dedup();
+
+        entities
+    }
+}
+
+/// orders tois, nearest first. Hits with NaN tois are left out before they're ordered, so the
+/// fallback is never used.
+fn toi_order<T: RealField>(a: T, b: T) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+#[cfg(all(test, feature = "2d"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::debug::DebugContext;
+    use crate::ecs::component::{Forces, MultibodyLink, Parent, PhysicsBody, Transform, Velocity};
+    use crate::ecs::resource::ReadRes;
+    use crate::ecs::system::physics::{CollisionEvent, PhysicsSystem};
+    use crate::ecs::system::SystemData;
+    use crate::ecs::world::{DefaultWorld, World};
+    use crate::ecs::Dispatcher;
+    use crate::game::IoState;
+    use crate::gfx::RenderContext;
+    use crate::nphysics::ncollide::shape::{Ball, ShapeHandle};
+    use crate::nphysics::object::{BodyStatus, ColliderDesc};
+
+    /// a static ball with a radius of 0.5 at x, y, in groups
+    fn ball(x: f32, y: f32, groups: CollisionGroups) -> (Transform<f32>, PhysicsBody<f32>) {
+        let collider = ColliderDesc::new(ShapeHandle::new(Ball::new(0.5))).collision_groups(groups);
+
+        (
+            Transform(Isometry::translation(x, y)),
+            PhysicsBody {
+                colliders: vec![collider],
+                body_status: BodyStatus::Static,
+                mass: 1.,
+                linear_damping: 0.,
+                angular_damping: 0.,
+                handle: None,
+            },
+        )
+    }
+
+    /// two balls on the x axis, a and b, and one on the y axis in group 1 only, c. Stepped once
+    /// so the balls are in the physics world.
+    fn world() -> (DefaultWorld, [Entity; 3]) {
+        let mut world = DefaultWorld::new()
+            .register::<PhysicsBody<f32>>()
+            .register::<MultibodyLink<f32>>()
+            .register::<Transform<f32>>()
+            .register::<Parent>()
+            .register::<Velocity<f32>>()
+            .register::<Forces<f32>>()
+            .with_resource(PhysicsWorld::new(Vector::zeros()))
+            .with_event_channel::<CollisionEvent>();
+        let mut entities = [Entity(0); 3];
+        let balls = vec![
+            ball(5., 0., CollisionGroups::new()),
+            ball(10., 0., CollisionGroups::new()),
+            ball(0., 5., CollisionGroups::new().with_membership(&[1])),
+        ];
+
+        for (i, (transform, body)) in balls.into_iter().enumerate() {
+            let builder = world
+                .add_entity()
+                .with_component(transform)
+                .with_component(body);
+            entities[i] = builder.entity();
+            world = builder.build();
+        }
+
+        Dispatcher::new()
+            .with(PhysicsSystem::<f32>::new())
+            .dispatch(
+                &world,
+                Duration::from_millis(10),
+                &IoState::default(),
+                &mut RenderContext::null((0, 0)),
+                &mut DebugContext::null(),
+            );
+
+        (world, entities)
+    }
+
+    fn assert_near(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < 1.0e-3,
+            "{} is not near {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn rays_hit_the_nearest_entity_first() {
+        let (world, [a, b, _]) = world();
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(&world);
+        let ray = Ray::new(Point::origin(), Vector::x());
+        let groups = CollisionGroups::new();
+
+        let hit = physics_world.cast_ray(&ray, 100., &groups).unwrap();
+        assert_eq!(hit.entity, a);
+        assert_near(hit.toi, 4.5);
+        assert_near(hit.point.x, 4.5);
+        assert_near(hit.normal.x, -1.);
+
+        let hits = physics_world.cast_ray_all(&ray, 100., &groups);
+        let hits: Vec<(Entity, f32)> = hits.iter().map(|hit| (hit.entity, hit.toi)).collect();
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].0, hits[1].0), (a, b));
+        assert_near(hits[1].1, 9.5);
+
+        // b is out of reach
+        assert_eq!(physics_world.cast_ray_all(&ray, 7., &groups).len(), 1);
+        assert!(physics_world.cast_ray(&ray, 4., &groups).is_none());
+    }
+
+    #[test]
+    fn degenerate_rays_hit_nothing() {
+        let (world, _) = world();
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(&world);
+        let ray = Ray::new(Point::new(5., 0.), Vector::new(f32::NAN, 0.));
+        let groups = CollisionGroups::new();
+
+        assert!(physics_world.cast_ray(&ray, 100., &groups).is_none());
+        assert!(physics_world.cast_ray_all(&ray, 100., &groups).is_empty());
+    }
+
+    #[test]
+    fn shapes_hit_what_they_touch_first() {
+        let (world, [a, b, _]) = world();
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(&world);
+        let shape = Ball::new(0.5);
+        let position = Isometry::identity();
+        let velocity = Vector::x();
+        let groups = CollisionGroups::new();
+
+        let hit = physics_world
+            .cast_shape(&shape, &position, &velocity, 100., &groups)
+            .unwrap();
+        assert_eq!(hit.entity, a);
+        assert_near(hit.toi, 4.);
+        assert_near(hit.point.x, 4.5);
+        assert_near(hit.normal.x, -1.);
+
+        let hit = physics_world
+            .cast_shape_filtered(&shape, &position, &velocity, 100., &groups, |e| e != a)
+            .unwrap();
+        assert_eq!(hit.entity, b);
+        assert_near(hit.toi, 9.);
+
+        assert!(physics_world
+            .cast_shape(&shape, &position, &velocity, 3., &groups)
+            .is_none());
+    }
+
+    #[test]
+    fn points_and_boxes_find_entities() {
+        let (world, [a, b, c]) = world();
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(&world);
+        let groups = CollisionGroups::new();
+
+        assert_eq!(
+            physics_world.entities_at_point(&Point::new(5.2, 0.), &groups),
+            vec![a]
+        );
+        assert!(physics_world
+            .entities_at_point(&Point::new(7., 0.), &groups)
+            .is_empty());
+
+        let aabb = AABB::new(Point::new(4., -1.), Point::new(11., 1.));
+        assert_eq!(physics_world.entities_in_aabb(&aabb, &groups), vec![a, b]);
+
+        let everything = AABB::new(Point::new(-1., -1.), Point::new(11., 6.));
+        assert_eq!(
+            physics_world.entities_in_aabb(&everything, &groups),
+            vec![a, b, c]
+        );
+    }
+
+    #[test]
+    fn collision_groups_filter_hits() {
+        let (world, [a, _, c]) = world();
+        let physics_world: ReadRes<PhysicsWorld<f32>> = SystemData::get(&world);
+        let up = Ray::new(Point::origin(), Vector::y());
+        let group_0 = CollisionGroups::new().with_whitelist(&[0]);
+
+        let hit = physics_world
+            .cast_ray(&up, 100., &CollisionGroups::new())
+            .unwrap();
+        assert_eq!(hit.entity, c);
+
+        // c is in group 1 only
+        assert!(physics_world.cast_ray(&up, 100., &group_0).is_none());
+        assert!(physics_world
+            .entities_at_point(&Point::new(0., 5.), &group_0)
+            .is_empty());
+
+        let everything = AABB::new(Point::new(-1., -1.), Point::new(11., 6.));
+        assert!(!physics_world
+            .entities_in_aabb(&everything, &group_0)
+            .contains(&c));
+
+        let right = Ray::new(Point::origin(), Vector::x());
+        assert_eq!(
+            physics_world
+                .cast_ray(&right, 100., &group_0)
+                .unwrap()
+                .entity,
+            a
+        );
+    }
+}
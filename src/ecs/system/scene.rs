@@ -7,20 +7,24 @@ use std::time::Duration;
 use gltf::camera::Projection;
 use gltf::Semantic;
 use nalgebra::{
-    Isometry3, Matrix4, Point3, Quaternion, Rotation3, Unit, UnitQuaternion, Vector3, Vector4,
+    Isometry3, Matrix4, Point3, Quaternion, Rotation3, UnitQuaternion, Vector3, Vector4,
 };
 use ncollide3d::pipeline::CollisionGroups;
 use ncollide3d::shape::{Ball, ShapeHandle, TriMesh};
 use nphysics3d::material::{BasicMaterial, MaterialHandle};
+use nphysics3d::math;
 use nphysics3d::object::{BodyStatus, ColliderDesc};
 use wgpu::Buffer;
 
 use crate::asset::scene::NodeAttributes;
 use crate::debug::DebugContext;
-use crate::ecs::component::{LightComponent, MeshComponent, OrbitCamera, PhysicsBody, Transform};
+use crate::ecs::component::{
+    Forces, GlobalTransform, LightComponent, MeshComponent, OrbitCamera, Parent, PhysicsBody,
+    PreviousTransform, Transform, Velocity,
+};
 use crate::ecs::system::Read;
 use crate::ecs::world::{World, WorldStorage};
-use crate::ecs::System;
+use crate::ecs::{Entity, Join, System};
 use crate::game::IoState;
 use crate::gfx::light::{DirectionalLight, LightData};
 use crate::gfx::material::Materials;
@@ -28,14 +32,16 @@ use crate::gfx::pass::Pass;
 use crate::gfx::primitives::MVP;
 use crate::gfx::{pass::DefaultPass, DefaultMesh, Mesh, RenderContext, Scene};
 
+/// draws the meshes and lights of the world. Loaded on a null render context it has no gpu
+/// resources, the scene's entities are added without meshes and nothing is drawn.
 pub struct SceneSystem<M: Mesh> {
     meshes: Vec<MeshWrapper<M>>,
     buffers: Vec<Arc<wgpu::Buffer>>,
     materials: Materials,
-    materials_buffer: Arc<wgpu::Buffer>,
+    materials_buffer: Option<Arc<wgpu::Buffer>>,
     camera: MVP,
     lights: Vec<LightData>,
-    pass: DefaultPass,
+    pass: Option<DefaultPass>,
 }
 
 struct MeshWrapper<M: Mesh> {
@@ -89,16 +95,26 @@ impl SceneSystem<DefaultMesh> {
         W: World
             + WorldStorage<MeshComponent<DefaultMesh>>
             + WorldStorage<Transform<f32>>
+            + WorldStorage<PreviousTransform<f32>>
+            + WorldStorage<Parent>
             + WorldStorage<PhysicsBody<f32>>
+            + WorldStorage<Velocity<f32>>
+            + WorldStorage<Forces<f32>>
             + WorldStorage<LightComponent>
             + WorldStorage<OrbitCamera>,
     {
-        let pass = DefaultPass::new(render_ctx);
+        let gpu = !render_ctx.is_null();
+        let pass = if gpu {
+            Some(DefaultPass::new(render_ctx))
+        } else {
+            None
+        };
         // upload buffers to GPU
         let gpu_buffers: Vec<_> = buffers
             .iter()
+            .filter(|_| gpu)
             .map(|b| {
-                Arc::new(render_ctx.device.create_buffer_with_data(
+                Arc::new(render_ctx.device().create_buffer_with_data(
                     &b,
                     wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::INDEX,
                 ))
@@ -107,14 +123,15 @@ impl SceneSystem<DefaultMesh> {
 
         // upload materials to GPU
         let materials: Materials = document.materials().into();
-        let materials_buffer = {
+        let materials_buffer = if gpu {
             use zerocopy::AsBytes;
 
-            Arc::new(
-                render_ctx
-                    .device
-                    .create_buffer_with_data(materials.as_bytes(), wgpu::BufferUsage::UNIFORM),
-            )
+            Some(Arc::new(render_ctx.device().create_buffer_with_data(
+                materials.as_bytes(),
+                wgpu::BufferUsage::UNIFORM,
+            )))
+        } else {
+            None
         };
 
         // setup camera
@@ -158,183 +175,7 @@ impl SceneSystem<DefaultMesh> {
             .expect("no scenes");
 
         for node in scene.nodes() {
-            let (translation, rotation, _) = node.transform().decomposed();
-            let translation_vector: Vector3<f32> = translation.into();
-            let rotation_vector4: Vector4<f32> = rotation.into();
-            let rotation_quaternion: Quaternion<f32> = rotation_vector4.into();
-            let transform = Transform(Isometry3::from_parts(
-                translation_vector.into(),
-                UnitQuaternion::from_quaternion(rotation_quaternion),
-            ));
-            let mut entity_builder = world.add_entity().with_component(transform);
-
-            if let Some(extras) = node.extras() {
-                let attributes: NodeAttributes = serde_json::from_str(extras.get()).unwrap();
-
-                // TODO: implement custom attributes
-                if attributes.ball.unwrap_or(0) > 0 {
-                    let collider_desc = ColliderDesc::new(ShapeHandle::new(Ball::new(0.0234f32)))
-                        .density(1.0)
-                        .ccd_enabled(true)
-                        .collision_groups(CollisionGroups::new().with_membership(&[0, 1]))
-                        .material(MaterialHandle::new(BasicMaterial::new(0.85, 0.4)));
-
-                    let projection = nalgebra::Matrix4::new_perspective(
-                        16. / 9.,
-                        0.4710899940857267,
-                        0.0001,
-                        100.,
-                    );
-
-                    entity_builder = entity_builder
-                        .with_component(PhysicsBody {
-                            body_status: BodyStatus::Dynamic,
-                            colliders: vec![collider_desc],
-                            mass: 0.045,
-                            linear_damping: 0.5,
-                            angular_damping: 0.5,
-                            handle: None,
-                        })
-                        .with_component(OrbitCamera {
-                            distance: 0.5,
-                            max_distance: 1.0,
-                            min_distance: 0.2,
-                            rotation: Rotation3::identity(),
-                            projection,
-                        });
-                }
-
-                if attributes.ground.unwrap_or(0) > 0 {
-                    let mesh = node.mesh().unwrap();
-
-                    let mut vertices = Vec::new();
-                    let mut indices = Vec::new();
-
-                    for primitive in mesh.primitives() {
-                        let index_offset = vertices.len();
-
-                        primitive
-                            .attributes()
-                            .find(|(semantic, _)| match semantic {
-                                Semantic::Positions => true,
-                                _ => false,
-                            })
-                            .map(|(_, accessor)| {
-                                let view = accessor.view().unwrap();
-                                let slice_offset = view.offset();
-                                let slice_len = view.length();
-                                let buffer = &buffers[view.buffer().index()].0;
-                                // FIXME: we assume positions are given as list of [f32; 3] values,
-                                //        but we should support all possible types.
-
-                                for byte_offset in
-                                    (slice_offset..slice_offset + slice_len).step_by(3 * 4)
-                                {
-                                    let x = f32::from_le_bytes(
-                                        (&buffer[byte_offset..byte_offset + 4]).try_into().unwrap(),
-                                    );
-                                    let y = f32::from_le_bytes(
-                                        (&buffer[byte_offset + 4..byte_offset + 8])
-                                            .try_into()
-                                            .unwrap(),
-                                    );
-                                    let z = f32::from_le_bytes(
-                                        (&buffer[byte_offset + 8..byte_offset + 12])
-                                            .try_into()
-                                            .unwrap(),
-                                    );
-
-                                    vertices.push(Point3::new(x, y, z));
-                                }
-                            })
-                            .unwrap();
-
-                        primitive
-                            .indices()
-                            .map(|accessor| {
-                                let view = accessor.view().unwrap();
-                                let slice_offset = view.offset();
-                                let slice_len = view.length();
-                                let buffer = &buffers[view.buffer().index()].0;
-                                // indices are iterated in sets of 3, and collected into a vector
-                                // of Point3's
-
-                                for byte_offset in
-                                    (slice_offset..slice_offset + slice_len).step_by(3 * 2)
-                                {
-                                    let x = u16::from_le_bytes(
-                                        (&buffer[byte_offset..byte_offset + 2]).try_into().unwrap(),
-                                    );
-                                    let y = u16::from_le_bytes(
-                                        (&buffer[byte_offset + 2..byte_offset + 4])
-                                            .try_into()
-                                            .unwrap(),
-                                    );
-                                    let z = u16::from_le_bytes(
-                                        (&buffer[byte_offset + 4..byte_offset + 6])
-                                            .try_into()
-                                            .unwrap(),
-                                    );
-
-                                    indices.push(Point3::new(
-                                        index_offset + x as usize,
-                                        index_offset + y as usize,
-                                        index_offset + z as usize,
-                                    ));
-                                }
-                            })
-                            .unwrap();
-                    }
-
-                    let mesh = TriMesh::new(vertices, indices, None);
-
-                    entity_builder = entity_builder.with_component(PhysicsBody {
-                        colliders: vec![ColliderDesc::new(ShapeHandle::new(mesh))
-                            .ccd_enabled(false)
-                            .collision_groups(CollisionGroups::new().with_membership(&[0]))],
-                        mass: f32::INFINITY,
-                        linear_damping: 0.0,
-                        body_status: BodyStatus::Dynamic,
-                        angular_damping: 0.0,
-                        handle: None,
-                    });
-                }
-            }
-
-            if let Some(mesh) = node.mesh() {
-                let primitives = mesh
-                    .primitives()
-                    .into_iter()
-                    .map(|p| Arc::new(DefaultMesh::from_gltf(p, render_ctx, &gpu_buffers)))
-                    .collect();
-                let mesh_component = MeshComponent { primitives };
-
-                entity_builder = entity_builder.with_component(mesh_component)
-            }
-
-            if let Some(light_desc) = node.light() {
-                match light_desc.kind() {
-                    gltf::khr_lights_punctual::Kind::Directional => {
-                        let rotation: Unit<Quaternion<f32>> = Unit::new_normalize(
-                            Quaternion::from(Vector4::from(node.transform().decomposed().1)),
-                        );
-                        let direction: [f32; 3] =
-                            rotation.transform_vector(&Vector3::new(0., 0., -1.)).into();
-
-                        let light = DirectionalLight::new(
-                            direction,
-                            light_desc.color(),
-                            light_desc.intensity(),
-                        );
-
-                        entity_builder =
-                            entity_builder.with_component(LightComponent { light: light })
-                    }
-                    _ => unimplemented!(),
-                }
-            }
-
-            world = entity_builder.build();
+            world = add_node(node, None, &buffers, &gpu_buffers, world, render_ctx);
         }
 
         (
@@ -352,16 +193,243 @@ impl SceneSystem<DefaultMesh> {
     }
 }
 
+/// adds an entity for node and, recursively, for all its children. parent is the entity of the
+/// parent node and its pose in world space.
+fn add_node<W>(
+    node: gltf::Node,
+    parent: Option<(Entity, Isometry3<f32>)>,
+    buffers: &[gltf::buffer::Data],
+    gpu_buffers: &[Arc<wgpu::Buffer>],
+    world: W,
+    render_ctx: &mut RenderContext,
+) -> W
+where
+    W: World
+        + WorldStorage<MeshComponent<DefaultMesh>>
+        + WorldStorage<Transform<f32>>
+        + WorldStorage<PreviousTransform<f32>>
+        + WorldStorage<Parent>
+        + WorldStorage<PhysicsBody<f32>>
+        + WorldStorage<Velocity<f32>>
+        + WorldStorage<Forces<f32>>
+        + WorldStorage<LightComponent>
+        + WorldStorage<OrbitCamera>,
+{
+    let (translation, rotation, _) = node.transform().decomposed();
+    let translation_vector: Vector3<f32> = translation.into();
+    let rotation_vector4: Vector4<f32> = rotation.into();
+    let rotation_quaternion: Quaternion<f32> = rotation_vector4.into();
+    let local = Isometry3::from_parts(
+        translation_vector.into(),
+        UnitQuaternion::from_quaternion(rotation_quaternion),
+    );
+    let global = match &parent {
+        Some((_, parent_global)) => parent_global * local,
+        None => local,
+    };
+    let attributes: Option<NodeAttributes> = node
+        .extras()
+        .as_ref()
+        .map(|extras| serde_json::from_str(extras.get()).unwrap());
+    let physics = attributes.as_ref().map_or(false, |attributes| {
+        attributes.ball.unwrap_or(0) > 0 || attributes.ground.unwrap_or(0) > 0
+    });
+
+    // physics bodies are placed in world space, so they can't have a parent
+    let mut entity_builder = match parent {
+        Some((parent, _)) if !physics => world
+            .add_entity()
+            .with_component(Transform(local))
+            .with_component(Parent(parent)),
+        _ => world.add_entity().with_component(Transform(global)),
+    };
+    let entity = entity_builder.entity();
+    let previous_transform = PreviousTransform(global);
+
+    if let Some(attributes) = attributes {
+        // TODO: implement custom attributes
+        if attributes.ball.unwrap_or(0) > 0 {
+            let collider_desc = ColliderDesc::new(ShapeHandle::new(Ball::new(0.0234f32)))
+                .density(1.0)
+                .ccd_enabled(true)
+                .collision_groups(CollisionGroups::new().with_membership(&[0, 1]))
+                .material(MaterialHandle::new(BasicMaterial::new(0.85, 0.4)));
+
+            let projection =
+                nalgebra::Matrix4::new_perspective(16. / 9., 0.4710899940857267, 0.0001, 100.);
+
+            entity_builder = entity_builder
+                .with_component(previous_transform)
+                .with_component(PhysicsBody {
+                    body_status: BodyStatus::Dynamic,
+                    colliders: vec![collider_desc],
+                    mass: 0.045,
+                    linear_damping: 0.5,
+                    angular_damping: 0.5,
+                    handle: None,
+                })
+                .with_component(Velocity(math::Velocity::zero()))
+                .with_component(Forces::default())
+                .with_component(OrbitCamera {
+                    distance: 0.5,
+                    max_distance: 1.0,
+                    min_distance: 0.2,
+                    rotation: Rotation3::identity(),
+                    projection,
+                });
+        }
+
+        if attributes.ground.unwrap_or(0) > 0 {
+            let mesh = node.mesh().unwrap();
+
+            let mut vertices = Vec::new();
+            let mut indices = Vec::new();
+
+            for primitive in mesh.primitives() {
+                let index_offset = vertices.len();
+
+                primitive
+                    .attributes()
+                    .find(|(semantic, _)| match semantic {
+                        Semantic::Positions => true,
+                        _ => false,
+                    })
+                    .map(|(_, accessor)| {
+                        let view = accessor.view().unwrap();
+                        let slice_offset = view.offset();
+                        let slice_len = view.length();
+                        let buffer = &buffers[view.buffer().index()].0;
+                        // FIXME: we assume positions are given as list of [f32; 3] values,
+                        //        but we should support all possible types.
+
+                        for byte_offset in (slice_offset..slice_offset + slice_len).step_by(3 * 4) {
+                            let x = f32::from_le_bytes(
+                                (&buffer[byte_offset..byte_offset + 4]).try_into().unwrap(),
+                            );
+                            let y = f32::from_le_bytes(
+                                (&buffer[byte_offset + 4..byte_offset + 8])
+                                    .try_into()
+                                    .unwrap(),
+                            );
+                            let z = f32::from_le_bytes(
+                                (&buffer[byte_offset + 8..byte_offset + 12])
+                                    .try_into()
+                                    .unwrap(),
+                            );
+
+                            vertices.push(Point3::new(x, y, z));
+                        }
+                    })
+                    .unwrap();
+
+                primitive
+                    .indices()
+                    .map(|accessor| {
+                        let view = accessor.view().unwrap();
+                        let slice_offset = view.offset();
+                        let slice_len = view.length();
+                        let buffer = &buffers[view.buffer().index()].0;
+                        // indices are iterated in sets of 3, and collected into a vector
+                        // of Point3's
+
+                        for byte_offset in (slice_offset..slice_offset + slice_len).step_by(3 * 2) {
+                            let x = u16::from_le_bytes(
+                                (&buffer[byte_offset..byte_offset + 2]).try_into().unwrap(),
+                            );
+                            let y = u16::from_le_bytes(
+                                (&buffer[byte_offset + 2..byte_offset + 4])
+                                    .try_into()
+                                    .unwrap(),
+                            );
+                            let z = u16::from_le_bytes(
+                                (&buffer[byte_offset + 4..byte_offset + 6])
+                                    .try_into()
+                                    .unwrap(),
+                            );
+
+                            indices.push(Point3::new(
+                                index_offset + x as usize,
+                                index_offset + y as usize,
+                                index_offset + z as usize,
+                            ));
+                        }
+                    })
+                    .unwrap();
+            }
+
+            let mesh = TriMesh::new(vertices, indices, None);
+
+            entity_builder = entity_builder.with_component(PhysicsBody {
+                colliders: vec![ColliderDesc::new(ShapeHandle::new(mesh))
+                    .ccd_enabled(false)
+                    .collision_groups(CollisionGroups::new().with_membership(&[0]))],
+                mass: f32::INFINITY,
+                linear_damping: 0.0,
+                body_status: BodyStatus::Dynamic,
+                angular_damping: 0.0,
+                handle: None,
+            });
+        }
+    }
+
+    // meshes live on the gpu, on a null render context nodes are loaded without them
+    if let Some(mesh) = node.mesh().filter(|_| !render_ctx.is_null()) {
+        let primitives = mesh
+            .primitives()
+            .into_iter()
+            .map(|p| Arc::new(DefaultMesh::from_gltf(p, render_ctx, gpu_buffers)))
+            .collect();
+        let mesh_component = MeshComponent { primitives };
+
+        entity_builder = entity_builder.with_component(mesh_component)
+    }
+
+    if let Some(light_desc) = node.light() {
+        match light_desc.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => {
+                // lights shine in world space, so nodes under rotated parents are turned too
+                let direction: [f32; 3] = global
+                    .rotation
+                    .transform_vector(&Vector3::new(0., 0., -1.))
+                    .into();
+
+                let light =
+                    DirectionalLight::new(direction, light_desc.color(), light_desc.intensity());
+
+                entity_builder = entity_builder.with_component(LightComponent { light: light })
+            }
+            // point and spot lights aren't supported yet, the node is loaded without them
+            gltf::khr_lights_punctual::Kind::Point
+            | gltf::khr_lights_punctual::Kind::Spot { .. } => {}
+        }
+    }
+
+    let mut world = entity_builder.build();
+
+    for child in node.children() {
+        world = add_node(
+            child,
+            Some((entity, global)),
+            buffers,
+            gpu_buffers,
+            world,
+            render_ctx,
+        );
+    }
+
+    world
+}
+
 impl<W: World, M: 'static + Mesh + Send + Sync> System<W> for SceneSystem<M>
 where
     W: WorldStorage<MeshComponent<M>>
-        + WorldStorage<Transform<f32>>
+        + WorldStorage<GlobalTransform<f32>>
         + WorldStorage<LightComponent>
         + WorldStorage<OrbitCamera>,
 {
     type SystemData<'a> = (
         Read<'a, MeshComponent<M>>,
-        Read<'a, Transform<f32>>,
+        Read<'a, GlobalTransform<f32>>,
         Read<'a, LightComponent>,
         Read<'a, OrbitCamera>,
     );
@@ -381,10 +449,7 @@ where
         self.meshes.clear();
         self.lights.clear();
 
-        for (entity, mesh) in mesh_reader.iter() {
-            let transform = transform_reader
-                .fetch(entity)
-                .expect("should have transform");
+        for (_, (mesh, transform)) in (&mesh_reader, &transform_reader).join() {
             for primitive in &mesh.primitives {
                 self.meshes.push(MeshWrapper {
                     mesh: Arc::clone(primitive),
@@ -425,12 +490,8 @@ where
 
         let camera_isometry: Isometry3<f32> = nalgebra::convert(camera_offset);
 
-        for (entity, light) in light_reader.iter() {
+        for (_, (light, transform)) in (&light_reader, &transform_reader).join() {
             // move directional lights with camera
-            let transform = transform_reader
-                .fetch(entity)
-                .expect("should have transform");
-
             let offset_transform = &camera_isometry * &transform.0;
 
             self.lights
@@ -439,7 +500,11 @@ where
     }
 
     fn draw(&self, render_ctx: &mut RenderContext) {
-        self.pass.render(self, render_ctx);
+        if let Some(ref pass) = self.pass {
+            if !render_ctx.is_null() {
+                pass.render(self, render_ctx);
+            }
+        }
     }
 }
 
@@ -455,7 +520,9 @@ impl<M: Mesh> Scene for SceneSystem<M> {
     }
 
     fn materials(&self) -> &Buffer {
-        self.materials_buffer.as_ref()
+        self.materials_buffer
+            .as_ref()
+            .expect("SceneSystem loaded without a gpu has no materials")
     }
 
     fn lights(&self) -> &[LightData] {
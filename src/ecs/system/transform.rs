@@ -0,0 +1,369 @@
+//! Transform hierarchy systems
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::nphysics::math::Isometry;
+use nalgebra::RealField;
+
+use crate::ecs::component::{Children, GlobalTransform, Parent, PreviousTransform, Transform};
+use crate::ecs::resource::{ReadRes, Time};
+use crate::ecs::system::{ParallelSystem, Read, Write};
+use crate::ecs::world::{World, WorldStorage};
+use crate::ecs::Entity;
+use crate::game::IoState;
+
+/// Maintains `Children` from `Parent` components, and computes the `GlobalTransform` of every
+/// entity with a `Transform`.
+///
+/// Entities without a Parent, or whose parent has no Transform, are roots of the hierarchy, so
+/// their Transform is used as is. Entities in parent cycles get no GlobalTransform.
+///
+/// Entities with a `PreviousTransform` are placed between it and their Transform, by the alpha
+/// of the `Time` resource. Worlds without Time get their Transforms as they are. Use `teleport`
+/// to move such entities without them sliding there.
+///
+/// Should run after all systems that move things, and before the systems that render them.
+pub struct TransformSystem<T: RealField> {
+    marker: PhantomData<T>,
+}
+
+impl<T: RealField> TransformSystem<T> {
+    pub fn new() -> TransformSystem<T> {
+        TransformSystem {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<W: World, T: RealField> ParallelSystem<W> for TransformSystem<T>
+where
+    W: WorldStorage<Transform<T>>
+        + WorldStorage<PreviousTransform<T>>
+        + WorldStorage<GlobalTransform<T>>
+        + WorldStorage<Parent>
+        + WorldStorage<Children>,
+{
+    type SystemData<'a> = (
+        (Read<'a, Transform<T>>, Read<'a, PreviousTransform<T>>),
+        Read<'a, Parent>,
+        Write<'a, Children>,
+        Write<'a, GlobalTransform<T>>,
+        Option<ReadRes<'a, Time>>,
+    );
+
+    fn name(&self) -> &'static str {
+        "TransformSystem"
+    }
+
+    fn update<'f>(
+        &mut self,
+        data: Self::SystemData<'f>,
+        _delta: Duration,
+        _io_state: &IoState,
+    ) -> () {
+        let ((transforms, previous), parents, mut children, mut globals, time) = data;
+
+        let alpha: T = nalgebra::convert(time.map_or(1., |time| time.alpha as f64));
+        let local = |entity: Entity, transform: &Transform<T>| match previous.fetch(entity) {
+            Some(previous) => transform.interpolate(&previous.0, alpha),
+            None => transform.0.clone(),
+        };
+
+        let mut hierarchy: HashMap<Entity, Vec<Entity>> = HashMap::new();
+        for (entity, parent) in parents.iter() {
+            hierarchy.entry(parent.0).or_default().push(entity);
+        }
+
+        // update Children, only touching the ones that actually changed
+        let orphaned: Vec<Entity> = children
+            .iter()
+            .map(|(entity, _)| entity)
+            .filter(|entity| !hierarchy.contains_key(entity))
+            .collect();
+
+        for entity in orphaned {
+            children.unset(entity);
+        }
+
+        for (entity, entity_children) in &hierarchy {
+            if children.fetch(*entity).map(|c| &c.0) != Some(entity_children) {
+                children.set(*entity, Children(entity_children.clone()));
+            }
+        }
+
+        // propagate transforms down from the roots
+        let mut stack: Vec<(Entity, _)> = transforms
+            .iter()
+            .filter(|(entity, _)| match parents.fetch(*entity) {
+                Some(parent) => transforms.fetch(parent.0).is_none(),
+                None => true,
+            })
+            .map(|(entity, transform)| (entity, local(entity, transform)))
+            .collect();
+
+        while let Some((entity, global)) = stack.pop() {
+            for child in hierarchy.get(&entity).into_iter().flatten() {
+                if let Some(transform) = transforms.fetch(*child) {
+                    stack.push((*child, &global * local(*child, transform)));
+                }
+            }
+
+            if globals.fetch(entity).map(|global| &global.0) != Some(&global) {
+                globals.set(entity, GlobalTransform(global));
+            }
+        }
+
+        // entities that lost their Transform shouldn't keep a stale GlobalTransform
+        let stale: Vec<Entity> = globals
+            .iter()
+            .map(|(entity, _)| entity)
+            .filter(|entity| transforms.fetch(*entity).is_none())
+            .collect();
+
+        for entity in stale {
+            globals.unset(entity);
+        }
+    }
+}
+
+/// moves entity to transform, and its `PreviousTransform` too if it has one, so it's drawn there
+/// right away instead of being interpolated there from where it was. For respawns and teleports.
+pub fn teleport<T: RealField>(
+    entity: Entity,
+    transform: Isometry<T>,
+    transforms: &mut Write<Transform<T>>,
+    previous: &mut Write<PreviousTransform<T>>,
+) {
+    if let Some(mut previous) = previous.get_mut(entity) {
+        previous.0 = transform.clone();
+    }
+
+    transforms.set(entity, Transform(transform));
+}
+
+/// Copies `Transform` to `PreviousTransform`, for the entities that have one. Should run at the
+/// start of every fixed tick, before anything moves.
+pub struct TransformHistorySystem<T: RealField> {
+    marker: PhantomData<T>,
+}
+
+impl<T: RealField> TransformHistorySystem<T> {
+    pub fn new() -> TransformHistorySystem<T> {
+        TransformHistorySystem {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<W: World, T: RealField> ParallelSystem<W> for TransformHistorySystem<T>
+where
+    W: WorldStorage<Transform<T>> + WorldStorage<PreviousTransform<T>>,
+{
+    type SystemData<'a> = (Read<'a, Transform<T>>, Write<'a, PreviousTransform<T>>);
+
+    fn name(&self) -> &'static str {
+        "TransformHistorySystem"
+    }
+
+    fn update<'f>(
+        &mut self,
+        (transforms, mut previous): Self::SystemData<'f>,
+        _delta: Duration,
+        _io_state: &IoState,
+    ) -> () {
+        for (entity, mut previous) in previous.iter_mut() {
+            if let Some(transform) = transforms.fetch(entity) {
+                previous.0 = transform.0.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::DebugContext;
+    use crate::ecs::system::{Changed, SystemData};
+    use crate::ecs::world::DefaultWorld;
+    use crate::ecs::Dispatcher;
+    use crate::gfx::RenderContext;
+    use crate::nphysics::math::Vector;
+
+    fn world() -> DefaultWorld {
+        DefaultWorld::new()
+            .register::<Transform<f32>>()
+            .register::<PreviousTransform<f32>>()
+            .register::<GlobalTransform<f32>>()
+            .register::<Parent>()
+            .register::<Children>()
+            .add_entity()
+            .with_component(Transform(at(2.)))
+            .with_component(PreviousTransform(at(0.)))
+            .build()
+    }
+
+    fn at(x: f32) -> Isometry<f32> {
+        Isometry::new(Vector::repeat(x), nalgebra::zero())
+    }
+
+    /// runs a frame with alpha
+    fn update(world: &DefaultWorld, alpha: f32) {
+        let mut render_ctx = RenderContext::null((0, 0));
+        render_ctx.alpha = alpha;
+
+        Dispatcher::new()
+            .with_parallel(TransformSystem::<f32>::new())
+            .dispatch(
+                world,
+                Duration::from_millis(10),
+                &IoState::default(),
+                &mut render_ctx,
+                &mut DebugContext::null(),
+            );
+    }
+
+    /// x of the GlobalTransform of the entity after a frame with alpha
+    fn global_x(world: &DefaultWorld, alpha: f32) -> f32 {
+        update(world, alpha);
+
+        global(world, world.entities()[0]).unwrap()
+    }
+
+    /// x of the GlobalTransform of entity
+    fn global(world: &DefaultWorld, entity: Entity) -> Option<f32> {
+        let globals: Read<GlobalTransform<f32>> = SystemData::get(world);
+
+        globals
+            .fetch(entity)
+            .map(|global| global.translation.vector.x)
+    }
+
+    fn children(world: &DefaultWorld, entity: Entity) -> Option<Vec<Entity>> {
+        let children: Read<Children> = SystemData::get(world);
+
+        children.fetch(entity).map(|children| children.0.clone())
+    }
+
+    fn set_parent(world: &DefaultWorld, entity: Entity, parent: Option<Entity>) {
+        let mut parents: Write<Parent> = SystemData::get(world);
+
+        match parent {
+            Some(parent) => parents.set(entity, Parent(parent)),
+            None => parents.unset(entity),
+        }
+    }
+
+    /// entities at x, in order
+    fn add_entities(world: DefaultWorld, xs: &[f32]) -> (DefaultWorld, Vec<Entity>) {
+        xs.iter()
+            .fold((world, Vec::new()), |(world, mut entities), x| {
+                let builder = world.add_entity().with_component(Transform(at(*x)));
+                entities.push(builder.entity());
+
+                (builder.build(), entities)
+            })
+    }
+
+    #[test]
+    fn interpolates_by_time() {
+        let world = world().with_resource(Time::default());
+
+        assert_eq!(global_x(&world, 0.25), 0.5);
+    }
+
+    #[test]
+    fn uses_transforms_without_time() {
+        assert_eq!(global_x(&world(), 0.25), 2.);
+    }
+
+    #[test]
+    fn teleports_are_not_interpolated() {
+        let world = world().with_resource(Time::default());
+        let entity = world.entities()[0];
+
+        {
+            let (mut transforms, mut previous) = SystemData::get(&world);
+            teleport(entity, at(8.), &mut transforms, &mut previous);
+        }
+
+        assert_eq!(global_x(&world, 0.25), 8.);
+    }
+
+    #[test]
+    fn globals_compose_down_the_hierarchy() {
+        // the grandchild comes first, so it's seen before its parent
+        let (world, entities) = add_entities(world(), &[3., 1., 2.]);
+        let (grandchild, root, child) = (entities[0], entities[1], entities[2]);
+
+        set_parent(&world, child, Some(root));
+        set_parent(&world, grandchild, Some(child));
+        update(&world, 1.);
+
+        assert_eq!(global(&world, root), Some(1.));
+        assert_eq!(global(&world, child), Some(3.));
+        assert_eq!(global(&world, grandchild), Some(6.));
+    }
+
+    #[test]
+    fn children_follow_parents() {
+        let (world, entities) = add_entities(world(), &[1., 2., 3.]);
+        let (a, b, child) = (entities[0], entities[1], entities[2]);
+
+        set_parent(&world, child, Some(a));
+        update(&world, 1.);
+        assert_eq!(children(&world, a), Some(vec![child]));
+        assert_eq!(global(&world, child), Some(4.));
+
+        set_parent(&world, child, Some(b));
+        update(&world, 1.);
+        assert_eq!(children(&world, a), None);
+        assert_eq!(children(&world, b), Some(vec![child]));
+        assert_eq!(global(&world, child), Some(5.));
+
+        set_parent(&world, child, None);
+        update(&world, 1.);
+        assert_eq!(children(&world, b), None);
+        assert_eq!(global(&world, child), Some(3.));
+    }
+
+    #[test]
+    fn cycles_get_no_global_transform() {
+        let (world, entities) = add_entities(world(), &[1., 2., 3.]);
+        let (a, b, child) = (entities[0], entities[1], entities[2]);
+
+        set_parent(&world, a, Some(b));
+        set_parent(&world, b, Some(a));
+        set_parent(&world, child, Some(a));
+        update(&world, 1.);
+
+        assert_eq!(global(&world, a), None);
+        assert_eq!(global(&world, b), None);
+        assert_eq!(global(&world, child), None);
+        assert_eq!(global(&world, world.entities()[0]), Some(2.));
+    }
+
+    #[test]
+    fn unchanged_globals_are_not_written() {
+        let (world, entities) = add_entities(world(), &[1., 2., 3.]);
+        let (root, child, other) = (entities[0], entities[1], entities[2]);
+
+        set_parent(&world, child, Some(root));
+        update(&world, 1.);
+        let world = world.clear_events();
+
+        update(&world, 1.);
+        assert!(Changed::<GlobalTransform<f32>>::get(&world).is_empty());
+
+        {
+            let mut transforms: Write<Transform<f32>> = SystemData::get(&world);
+            transforms.set(root, Transform(at(5.)));
+        }
+        update(&world, 1.);
+
+        let changed = Changed::<GlobalTransform<f32>>::get(&world);
+        assert!(changed.contains(root) && changed.contains(child));
+        assert!(!changed.contains(other));
+    }
+}
@@ -1,12 +1,89 @@
 //! World is the container thing
 
-use crate::ecs::{entity::EntityBuilder, Component, ComponentStorage, Entity};
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::ecs::command::CommandBuffer;
+use crate::ecs::{
+    entity::EntityBuilder, AnyStorage, Component, ComponentStorage, Entity, EntityAllocator, Event,
+    EventChannel, Resources, VecStorage,
+};
 
 pub trait World: Sized {
     fn entities(&self) -> &[Entity];
     fn add_entity(self) -> EntityBuilder<Self>;
+
+    /// reserves an entity to be added later with `add_reserved_entity`, without borrowing the
+    /// World mutably, see `EntityAllocator::reserve`
+    fn reserve_entity(&self) -> Entity;
+
+    /// adds an entity reserved by `reserve_entity`
+    fn add_reserved_entity(self, entity: Entity) -> EntityBuilder<Self>;
+
+    /// releases an entity reserved by `reserve_entity` that won't be added after all, so its
+    /// index can be reused. Entities that were added are left alone.
+    fn release_reserved_entity(self, entity: Entity) -> Self;
+
+    /// returns true if entity has been added to this World and not removed since
+    fn is_alive(&self, entity: Entity) -> bool {
+        self.entities().iter().any(|e| e.is_same(entity))
+    }
+
+    /// removes entity and all its Components from this World, emitting `Event::EntityRemoved`
     fn remove_entity(self, entity: Entity) -> Self;
+
+    /// marks entity as dead. Killed entities stay alive, with all their Components, until
+    /// `remove_dead` is called
+    fn kill_entity(self, entity: Entity) -> Self;
+
+    /// removes all killed entities, see `remove_entity`
     fn remove_dead(self) -> Self;
+
+    /// entity events emitted since last call to `clear_events`
+    fn events(&self) -> &[Event];
+
+    /// clears entity events, ends the frame of the change trackers of all storages, and
+    /// maintains event channels. Should be called once per frame, after all systems have seen
+    /// them.
+    fn clear_events(self) -> Self;
+
+    /// world-level singleton resources
+    fn resources(&self) -> &Resources;
+    fn resources_mut(&mut self) -> &mut Resources;
+
+    /// applies the commands recorded through `Commands`, in the order they were recorded.
+    /// Should be called once per frame after `clear_events`, so the events and changes caused
+    /// by the commands are seen by the systems next frame.
+    fn apply_commands(self) -> Self
+    where
+        Self: 'static,
+    {
+        let commands = match self.resources().read::<CommandBuffer<Self>>() {
+            Some(buffer) => buffer.take(),
+            None => Vec::new(),
+        };
+
+        commands
+            .into_iter()
+            .fold(self, |world, command| command(world))
+    }
+
+    /// drops the commands recorded through `Commands` without applying them, and releases the
+    /// entities reserved by their spawns
+    fn clear_commands(self) -> Self
+    where
+        Self: 'static,
+    {
+        let reserved = match self.resources().read::<CommandBuffer<Self>>() {
+            Some(buffer) => buffer.clear(),
+            None => Vec::new(),
+        };
+
+        reserved
+            .into_iter()
+            .fold(self, |world, entity| world.release_reserved_entity(entity))
+    }
 }
 
 pub trait WorldStorage<C: Component>: World {
@@ -14,3 +91,311 @@ pub trait WorldStorage<C: Component>: World {
 
     fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage;
 }
+
+/// Generic World, usable when you don't want to write your own.
+///
+/// Components are kept in a type map of `VecStorage`s, so every Component type has to be
+/// registered with `register` before it can be used.
+pub struct DefaultWorld {
+    allocator: EntityAllocator,
+    entities: Vec<Entity>,
+    events: Vec<Event>,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    resources: Resources,
+}
+
+impl DefaultWorld {
+    pub fn new() -> DefaultWorld {
+        let world = DefaultWorld {
+            allocator: EntityAllocator::new(),
+            entities: Vec::new(),
+            events: Vec::new(),
+            storages: HashMap::new(),
+            resources: Resources::new(),
+        };
+
+        world.with_resource(CommandBuffer::<DefaultWorld>::new())
+    }
+
+    /// registers a Component type, creating a storage for it. Registering the same Component
+    /// twice does nothing.
+    pub fn register<C: 'static + Component + Debug>(self) -> DefaultWorld {
+        let DefaultWorld { mut storages, .. } = self;
+
+        storages
+            .entry(TypeId::of::<C>())
+            .or_insert_with(|| Box::new(VecStorage::<C>::new()));
+
+        DefaultWorld { storages, ..self }
+    }
+
+    /// inserts a resource, replacing the old resource of same type
+    pub fn with_resource<T: 'static + Send + Sync>(self, resource: T) -> DefaultWorld {
+        let DefaultWorld { mut resources, .. } = self;
+
+        resources.insert(resource);
+
+        DefaultWorld { resources, ..self }
+    }
+
+    /// inserts an empty event channel for events of type T, maintained by `clear_events`
+    pub fn with_event_channel<T: 'static + Send + Sync>(self) -> DefaultWorld {
+        let DefaultWorld { mut resources, .. } = self;
+
+        resources.insert_channel(EventChannel::<T>::new());
+
+        DefaultWorld { resources, ..self }
+    }
+
+    /// returns true if Component C has been registered
+    pub fn is_registered<C: 'static + Component>(&self) -> bool {
+        self.storages.contains_key(&TypeId::of::<C>())
+    }
+}
+
+impl Default for DefaultWorld {
+    fn default() -> Self {
+        DefaultWorld::new()
+    }
+}
+
+impl World for DefaultWorld {
+    fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    fn is_alive(&self, entity: Entity) -> bool {
+        self.allocator.is_alive(entity)
+    }
+
+    fn add_entity(self) -> EntityBuilder<Self> {
+        let DefaultWorld {
+            mut allocator,
+            mut entities,
+            mut events,
+            ..
+        } = self;
+
+        let new_entity = allocator.allocate();
+        entities.push(new_entity);
+        events.push(Event::EntityAdded(new_entity));
+
+        EntityBuilder::new(
+            new_entity,
+            DefaultWorld {
+                allocator,
+                entities,
+                events,
+                ..self
+            },
+        )
+    }
+
+    fn reserve_entity(&self) -> Entity {
+        self.allocator.reserve()
+    }
+
+    fn add_reserved_entity(self, entity: Entity) -> EntityBuilder<Self> {
+        let DefaultWorld {
+            mut allocator,
+            mut entities,
+            mut events,
+            ..
+        } = self;
+
+        allocator.flush();
+        entities.push(entity);
+        events.push(Event::EntityAdded(entity));
+
+        EntityBuilder::new(
+            entity,
+            DefaultWorld {
+                allocator,
+                entities,
+                events,
+                ..self
+            },
+        )
+    }
+
+    fn release_reserved_entity(self, entity: Entity) -> Self {
+        let DefaultWorld { mut allocator, .. } = self;
+
+        if !self.entities.iter().any(|e| e.is_same(entity)) {
+            allocator.free(entity);
+        }
+
+        DefaultWorld { allocator, ..self }
+    }
+
+    fn remove_entity(self, entity: Entity) -> Self {
+        let DefaultWorld {
+            mut allocator,
+            mut entities,
+            mut events,
+            storages,
+            resources,
+        } = self;
+
+        // entity might be a stale handle, in which case there is nothing to remove
+        if allocator.free(entity) {
+            let entity = Entity::with_generation(entity.index(), entity.generation());
+
+            entities.retain(|e| !e.is_same(entity));
+            for storage in storages.values() {
+                storage.remove(entity);
+            }
+            events.push(Event::EntityRemoved(entity));
+        }
+
+        DefaultWorld {
+            allocator,
+            entities,
+            events,
+            storages,
+            resources,
+        }
+    }
+
+    fn kill_entity(self, entity: Entity) -> Self {
+        let DefaultWorld { mut entities, .. } = self;
+
+        if let Some(e) = entities.iter_mut().find(|e| e.is_same(entity)) {
+            *e = e.kill();
+        }
+
+        DefaultWorld { entities, ..self }
+    }
+
+    fn remove_dead(self) -> Self {
+        let dead: Vec<Entity> = self
+            .entities
+            .iter()
+            .filter(|e| e.is_dead())
+            .cloned()
+            .collect();
+
+        dead.into_iter()
+            .fold(self, |world, entity| world.remove_entity(entity))
+    }
+
+    fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    fn clear_events(self) -> Self {
+        let DefaultWorld { mut events, .. } = self;
+
+        events.clear();
+
+        for storage in self.storages.values() {
+            storage.maintain_changes();
+        }
+
+        self.resources.maintain();
+
+        DefaultWorld { events, ..self }
+    }
+
+    fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+}
+
+impl<C: 'static + Component + Debug> WorldStorage<C> for DefaultWorld {
+    type Storage = VecStorage<C>;
+
+    fn storage<'s, 'w: 's>(&'w self) -> &'s Self::Storage {
+        self.storages
+            .get(&TypeId::of::<C>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+            .unwrap_or_else(|| {
+                panic!(
+                    "component {} not registered in world",
+                    std::any::type_name::<C>()
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::system::{Read, SystemData};
+    use crate::ecs::ReadAccess;
+
+    #[derive(Debug, PartialEq)]
+    struct Hp(i32);
+
+    impl Component for Hp {}
+
+    #[derive(Debug, PartialEq)]
+    struct Armor(i32);
+
+    impl Component for Armor {}
+
+    fn world() -> DefaultWorld {
+        DefaultWorld::new()
+            .register::<Hp>()
+            .register::<Armor>()
+            .add_entity()
+            .with_component(Hp(1))
+            .with_component(Armor(3))
+            .build()
+            .add_entity()
+            .with_component(Hp(2))
+            .build()
+    }
+
+    #[test]
+    fn killed_entities_live_until_removed() {
+        let world = world();
+        let (first, second) = (world.entities()[0], world.entities()[1]);
+
+        let world = world.kill_entity(first);
+
+        assert!(world.is_alive(first));
+        assert!(world.entities()[0].is_dead());
+        assert_eq!(Read::<Hp>::get(&world).fetch(first), Some(&Hp(1)));
+
+        let world = world.remove_dead();
+        let hp: Read<Hp> = SystemData::get(&world);
+
+        assert!(!world.is_alive(first));
+        assert!(world.is_alive(second));
+        assert_eq!(world.entities(), &[second]);
+        assert_eq!(hp.fetch(first), None);
+        assert_eq!(hp.fetch(second), Some(&Hp(2)));
+    }
+
+    #[test]
+    fn remove_entity_purges_every_storage() {
+        let world = world().clear_events();
+        let (first, second) = (world.entities()[0], world.entities()[1]);
+
+        let world = world.remove_entity(first);
+
+        assert!(!world.is_alive(first));
+        assert_eq!(world.entities(), &[second]);
+        assert_eq!(world.events(), &[Event::EntityRemoved(first)]);
+
+        {
+            let hp: Read<Hp> = SystemData::get(&world);
+            let armor: Read<Armor> = SystemData::get(&world);
+
+            // fetching through the stale handle would find nothing even without the purge
+            assert_eq!(hp.iter().collect::<Vec<_>>(), vec![(second, &Hp(2))]);
+            assert_eq!(armor.iter().count(), 0);
+        }
+
+        // stale handles remove nothing
+        let world = world.clear_events().remove_entity(first);
+
+        assert!(world.events().is_empty());
+        assert_eq!(world.entities(), &[second]);
+    }
+}
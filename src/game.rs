@@ -3,56 +3,380 @@
 use std::collections::HashMap;
 use std::time::Duration;
 
-use winit::{event::Event, event_loop::ControlFlow};
+use winit::event::{Event, ScanCode};
+use winit::event_loop::ControlFlow;
 
 use crate::debug::DebugContext;
 use crate::gfx::RenderContext;
+use crate::input::{GamepadAxis, GamepadButton, InputEvent};
 
 pub trait Playable: Sized {
     /// Advances this game to next state
     fn update(
         self,
         delta: Duration,
+        io_state: &IoState,
         render_ctx: &mut RenderContext,
         debug_ctx: &mut DebugContext,
     ) -> Self;
 
-    /// Handle window events
+    /// Advances this game by one fixed tick of step, for simulation that should not depend on the
+    /// frame rate, like physics. Run `Settings::tick_rate` times a second, before `update`, so a
+    /// frame can have zero or more ticks. `RenderContext::alpha` tells how far the frame is
+    /// between the last two ticks. Presses and releases stay visible until a tick sees them,
+    /// see `IoState`.
+    fn fixed_update(
+        self,
+        _step: Duration,
+        _io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> Self {
+        self
+    }
+
+    /// Called once before the first frame with the seed of this session, random unless it's a
+    /// replay. Games that want replays to play out the same should take all their randomness
+    /// from it, eq. with `StdRng::seed_from_u64`.
+    fn set_seed(&mut self, _seed: u64) {}
+
+    /// Handle window events. Input events are also applied to the `IoState` given to `update`
+    /// by the application, so this is only needed for events `IoState` doesn't track.
     fn push_event<T>(&mut self, event: &Event<T>) -> Option<ControlFlow>;
 
     /// Renders this game
     fn redraw(&self, render_ctx: &mut RenderContext, debug_ctx: &mut DebugContext) -> ();
 }
 
-//TODO: Fix this
-#[derive(Default)]
+/// State of keyboard, mouse and gamepads, filled by the application from window events and
+/// given to `Playable::update`.
+///
+/// In `Playable::update`, "pressed" and "released" are true for the frame the state changed on.
+/// Fixed ticks compare to the state at the end of the last tick instead, so a change is seen by
+/// exactly one tick, even when it's made on a frame that runs no ticks.
+#[derive(Clone)]
 pub struct IoState {
+    /// cursor position in physical pixels, from the top left corner of the window
     pub mouse_position: [f32; 2],
     pub mouse_buttons: [bool; 3],
-    pub keys: HashMap<winit::event::ScanCode, bool>,
-    pub last_frame_keys: HashMap<winit::event::ScanCode, bool>,
+    pub last_frame_mouse_buttons: [bool; 3],
+    /// raw mouse movement since last frame. Unlike `mouse_position`, keeps going when the cursor
+    /// hits the edge of the screen, so it's what camera controls want.
+    pub mouse_motion: [f32; 2],
+    /// scroll wheel movement since last frame, in lines
+    pub scroll: [f32; 2],
+    pub keys: HashMap<ScanCode, bool>,
+    pub last_frame_keys: HashMap<ScanCode, bool>,
+    /// buttons of all connected gamepads, see `input::Gamepads`
+    pub gamepad_buttons: HashMap<GamepadButton, bool>,
+    pub last_frame_gamepad_buttons: HashMap<GamepadButton, bool>,
+    /// axes of all connected gamepads, from -1 to 1
+    pub gamepad_axes: HashMap<GamepadAxis, f32>,
+    /// whether the window has keyboard focus
+    pub focused: bool,
+    pub last_frame_focused: bool,
+    /// state at the end of the last fixed tick, for "pressed" and "released" in ticks
+    pub last_tick_keys: HashMap<ScanCode, bool>,
+    pub last_tick_mouse_buttons: [bool; 3],
+    pub last_tick_gamepad_buttons: HashMap<GamepadButton, bool>,
+    pub last_tick_focused: bool,
+    /// characters typed since last frame, in order. Includes control characters, like '\u{8}'
+    /// for backspace, so text fields can handle them.
+    pub text: Vec<char>,
+    /// whether a fixed tick is running, see `begin_tick`
+    ticking: bool,
+}
+
+impl Default for IoState {
+    fn default() -> Self {
+        IoState {
+            mouse_position: [0., 0.],
+            mouse_buttons: [false; 3],
+            last_frame_mouse_buttons: [false; 3],
+            mouse_motion: [0., 0.],
+            scroll: [0., 0.],
+            keys: HashMap::new(),
+            last_frame_keys: HashMap::new(),
+            gamepad_buttons: HashMap::new(),
+            last_frame_gamepad_buttons: HashMap::new(),
+            gamepad_axes: HashMap::new(),
+            focused: true,
+            last_frame_focused: true,
+            last_tick_keys: HashMap::new(),
+            last_tick_mouse_buttons: [false; 3],
+            last_tick_gamepad_buttons: HashMap::new(),
+            last_tick_focused: true,
+            text: Vec::new(),
+            ticking: false,
+        }
+    }
 }
 
 impl IoState {
-    pub fn set_key(&mut self, key: winit::event::ScanCode, state: bool) {
+    /// applies a window or device event. Events this doesn't track are ignored.
+    pub fn push_event<T>(&mut self, event: &Event<T>) {
+        if let Some(input) = InputEvent::from_event(event) {
+            self.apply(&input);
+        }
+    }
+
+    /// applies an input event, live or replayed
+    pub fn apply(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::Key { scancode, pressed } => self.set_key(scancode, pressed),
+            InputEvent::MouseButton { button, pressed } => self.set_mouse_button(button, pressed),
+            InputEvent::CursorMoved { x, y } => self.mouse_position = [x, y],
+            InputEvent::Scroll { x, y } => self.add_scroll(x, y),
+            InputEvent::MouseMotion { x, y } => {
+                // device events come even when some other window has focus
+                if self.focused {
+                    self.add_mouse_motion(x, y);
+                }
+            }
+            InputEvent::Text(character) => self.text.push(character),
+            InputEvent::Focused(focused) => self.set_focused(focused),
+            InputEvent::GamepadButton { button, pressed } => {
+                self.set_gamepad_button(button, pressed)
+            }
+            InputEvent::GamepadAxis { axis, value } => self.set_gamepad_axis(axis, value),
+            InputEvent::GamepadDisconnected => {
+                // don't leave buttons of a gamepad that's gone held down
+                self.gamepad_buttons.clear();
+                self.gamepad_axes.clear();
+            }
+        }
+    }
+
+    pub fn set_key(&mut self, key: ScanCode, state: bool) {
         self.keys.insert(key, state);
     }
 
-    pub fn is_down(&self, key: winit::event::ScanCode) -> bool {
+    /// sets the state of mouse button, 0 being left, 1 right and 2 middle
+    pub fn set_mouse_button(&mut self, button: usize, state: bool) {
+        if let Some(mouse_button) = self.mouse_buttons.get_mut(button) {
+            *mouse_button = state;
+        }
+    }
+
+    pub fn add_mouse_motion(&mut self, x: f32, y: f32) {
+        self.mouse_motion[0] += x;
+        self.mouse_motion[1] += y;
+    }
+
+    pub fn add_scroll(&mut self, x: f32, y: f32) {
+        self.scroll[0] += x;
+        self.scroll[1] += y;
+    }
+
+    pub fn set_gamepad_button(&mut self, button: GamepadButton, state: bool) {
+        self.gamepad_buttons.insert(button, state);
+    }
+
+    pub fn set_gamepad_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.gamepad_axes.insert(axis, value);
+    }
+
+    /// sets whether the window has focus. Losing focus releases all keys and mouse buttons, as
+    /// the window won't hear about them being released.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+
+        if !focused {
+            self.keys.values_mut().for_each(|state| *state = false);
+            self.mouse_buttons = [false; 3];
+        }
+    }
+
+    pub fn is_down(&self, key: ScanCode) -> bool {
         *self.keys.get(&key).unwrap_or(&false)
     }
 
-    pub fn pressed(&self, key: winit::event::ScanCode) -> bool {
-        self.last_frame_keys
-            .get(&key)
-            .and_then(|last_state| {
-                let cur_state = self.keys.get(&key).unwrap_or(&false);
-                Some(*last_state == false && *cur_state == true)
-            })
-            .unwrap_or_else(|| *self.keys.get(&key).unwrap_or(&false))
+    pub(crate) fn was_down(&self, key: ScanCode) -> bool {
+        let keys = if self.ticking {
+            &self.last_tick_keys
+        } else {
+            &self.last_frame_keys
+        };
+
+        *keys.get(&key).unwrap_or(&false)
+    }
+
+    /// true on the frame key went down
+    pub fn pressed(&self, key: ScanCode) -> bool {
+        self.is_down(key) && !self.was_down(key)
+    }
+
+    /// true on the frame key went up
+    pub fn released(&self, key: ScanCode) -> bool {
+        !self.is_down(key) && self.was_down(key)
     }
 
+    pub fn is_mouse_button_down(&self, button: usize) -> bool {
+        *self.mouse_buttons.get(button).unwrap_or(&false)
+    }
+
+    pub(crate) fn was_mouse_button_down(&self, button: usize) -> bool {
+        let mouse_buttons = if self.ticking {
+            &self.last_tick_mouse_buttons
+        } else {
+            &self.last_frame_mouse_buttons
+        };
+
+        *mouse_buttons.get(button).unwrap_or(&false)
+    }
+
+    /// true on the frame mouse button went down
+    pub fn mouse_pressed(&self, button: usize) -> bool {
+        self.is_mouse_button_down(button) && !self.was_mouse_button_down(button)
+    }
+
+    /// true on the frame mouse button went up
+    pub fn mouse_released(&self, button: usize) -> bool {
+        !self.is_mouse_button_down(button) && self.was_mouse_button_down(button)
+    }
+
+    pub fn is_gamepad_button_down(&self, button: GamepadButton) -> bool {
+        *self.gamepad_buttons.get(&button).unwrap_or(&false)
+    }
+
+    pub(crate) fn was_gamepad_button_down(&self, button: GamepadButton) -> bool {
+        let gamepad_buttons = if self.ticking {
+            &self.last_tick_gamepad_buttons
+        } else {
+            &self.last_frame_gamepad_buttons
+        };
+
+        *gamepad_buttons.get(&button).unwrap_or(&false)
+    }
+
+    pub fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        *self.gamepad_axes.get(&axis).unwrap_or(&0.)
+    }
+
+    fn was_focused(&self) -> bool {
+        if self.ticking {
+            self.last_tick_focused
+        } else {
+            self.last_frame_focused
+        }
+    }
+
+    /// true on the frame the window got focus
+    pub fn focus_gained(&self) -> bool {
+        self.focused && !self.was_focused()
+    }
+
+    /// true on the frame the window lost focus
+    pub fn focus_lost(&self) -> bool {
+        !self.focused && self.was_focused()
+    }
+
+    /// starts a fixed tick: until `end_tick`, "pressed" and "released" compare to the state at the
+    /// end of the last tick
+    pub(crate) fn begin_tick(&mut self) {
+        self.ticking = true;
+    }
+
+    /// ends a fixed tick, and remembers its state for the "pressed" and "released" of the next one
+    pub(crate) fn end_tick(&mut self) {
+        self.last_tick_keys = self.keys.clone();
+        self.last_tick_mouse_buttons = self.mouse_buttons;
+        self.last_tick_gamepad_buttons = self.gamepad_buttons.clone();
+        self.last_tick_focused = self.focused;
+        self.ticking = false;
+    }
+
+    /// ends the frame: remembers this frame's state for "pressed" and "released", and clears
+    /// motion, scroll and text. Called by the application after every update.
     pub fn update(&mut self) {
         self.last_frame_keys = self.keys.clone();
+        self.last_frame_mouse_buttons = self.mouse_buttons;
+        self.last_frame_gamepad_buttons = self.gamepad_buttons.clone();
+        self.last_frame_focused = self.focused;
+        self.mouse_motion = [0., 0.];
+        self.scroll = [0., 0.];
+        self.text.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACE: ScanCode = 57;
+
+    /// runs ticks fixed ticks, reporting whether space was pressed and released in each
+    fn ticks(io_state: &mut IoState, ticks: usize) -> Vec<(bool, bool)> {
+        (0..ticks)
+            .map(|_| {
+                io_state.begin_tick();
+                let edges = (io_state.pressed(SPACE), io_state.released(SPACE));
+                io_state.end_tick();
+
+                edges
+            })
+            .collect()
+    }
+
+    #[test]
+    fn presses_wait_for_a_tick() {
+        let mut io_state = IoState::default();
+
+        // a frame without ticks
+        io_state.set_key(SPACE, true);
+        assert!(io_state.pressed(SPACE));
+        io_state.update();
+
+        assert!(!io_state.pressed(SPACE));
+        assert_eq!(ticks(&mut io_state, 2), vec![(true, false), (false, false)]);
+        io_state.update();
+
+        // a frame with two ticks only presses in the first one
+        io_state.set_key(SPACE, false);
+        assert_eq!(ticks(&mut io_state, 2), vec![(false, true), (false, false)]);
+        assert!(io_state.released(SPACE), "the frame still sees the release");
+    }
+
+    #[test]
+    fn releases_wait_for_a_tick() {
+        let mut io_state = IoState::default();
+
+        io_state.set_key(SPACE, true);
+        ticks(&mut io_state, 1);
+        io_state.update();
+
+        io_state.set_key(SPACE, false);
+        assert!(io_state.released(SPACE));
+        io_state.update();
+        io_state.update();
+
+        assert!(!io_state.released(SPACE));
+        assert_eq!(ticks(&mut io_state, 1), vec![(false, true)]);
+    }
+
+    #[test]
+    fn losing_focus_releases_keys_for_ticks() {
+        let mut io_state = IoState::default();
+
+        io_state.set_key(SPACE, true);
+        io_state.set_mouse_button(0, true);
+        ticks(&mut io_state, 1);
+        io_state.update();
+
+        io_state.set_focused(false);
+        assert!(io_state.focus_lost());
+        io_state.update();
+
+        assert!(!io_state.focus_lost());
+        io_state.begin_tick();
+        assert!(io_state.focus_lost());
+        assert!(io_state.released(SPACE));
+        assert!(io_state.mouse_released(0));
+        io_state.end_tick();
+
+        io_state.begin_tick();
+        assert!(!io_state.focus_lost());
+        assert!(!io_state.released(SPACE));
+        io_state.end_tick();
     }
 }
@@ -31,13 +31,103 @@ mod spritebatch;
 pub type Texture = Rc<wgpu::Texture>;
 
 /// All the stuff that is needed to draw to screen
+///
+/// A null context, see `RenderContext::null`, has no gpu to draw with. Games run by
+/// `HeadlessRunner::without_rendering` get one. `Spritebatch` and `SceneSystem` skip their
+/// uploads and draws on it; anything else touching the gpu in update should check `is_null`
+/// first.
 pub struct RenderContext<'s, 'p, 'd, 'w> {
-    pub frame: &'s wgpu::TextureView,
-    pub encoder: wgpu::CommandEncoder,
-    pub device: &'d wgpu::Device,
-    pub pipelines: &'p DefaultPipelines,
+    gpu: Option<Gpu<'s, 'p, 'd>>,
     pub screen_size: (u32, u32),
-    pub window: &'w winit::window::Window,
+    /// None when running headless, see `HeadlessRunner`
+    pub window: Option<&'w winit::window::Window>,
+    /// how far this frame is from the previous fixed tick to the latest one, from 0 to 1. Always
+    /// 1 without fixed ticks. See `Playable::fixed_update`.
+    pub alpha: f32,
+}
+
+struct Gpu<'s, 'p, 'd> {
+    frame: &'s wgpu::TextureView,
+    encoder: wgpu::CommandEncoder,
+    device: &'d wgpu::Device,
+    pipelines: &'p DefaultPipelines,
+}
+
+impl<'s, 'p, 'd, 'w> RenderContext<'s, 'p, 'd, 'w> {
+    pub fn new(
+        frame: &'s wgpu::TextureView,
+        encoder: wgpu::CommandEncoder,
+        device: &'d wgpu::Device,
+        pipelines: &'p DefaultPipelines,
+        screen_size: (u32, u32),
+        window: Option<&'w winit::window::Window>,
+    ) -> RenderContext<'s, 'p, 'd, 'w> {
+        RenderContext {
+            gpu: Some(Gpu {
+                frame,
+                encoder,
+                device,
+                pipelines,
+            }),
+            screen_size,
+            window,
+            alpha: 1.,
+        }
+    }
+
+    /// creates a context without a gpu, for running updates only. `frame`, `encoder`, `device`
+    /// and `pipelines` panic on it, so gpu resources like textures can't be created.
+    pub fn null(screen_size: (u32, u32)) -> RenderContext<'s, 'p, 'd, 'w> {
+        RenderContext {
+            gpu: None,
+            screen_size,
+            window: None,
+            alpha: 1.,
+        }
+    }
+
+    /// true if this context has no gpu, see `RenderContext::null`
+    pub fn is_null(&self) -> bool {
+        self.gpu.is_none()
+    }
+
+    /// texture of the frame being drawn
+    pub fn frame(&self) -> &'s wgpu::TextureView {
+        self.gpu().frame
+    }
+
+    pub fn encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        &mut self.gpu_mut().encoder
+    }
+
+    pub fn device(&self) -> &'d wgpu::Device {
+        self.gpu().device
+    }
+
+    pub fn pipelines(&self) -> &'p DefaultPipelines {
+        self.gpu().pipelines
+    }
+
+    /// swaps the encoder for another one, returning the old one
+    pub(crate) fn replace_encoder(
+        &mut self,
+        encoder: wgpu::CommandEncoder,
+    ) -> wgpu::CommandEncoder {
+        mem::replace(&mut self.gpu_mut().encoder, encoder)
+    }
+
+    /// consumes the context, returning its encoder
+    pub(crate) fn into_encoder(self) -> Option<wgpu::CommandEncoder> {
+        self.gpu.map(|gpu| gpu.encoder)
+    }
+
+    fn gpu(&self) -> &Gpu<'s, 'p, 'd> {
+        self.gpu.as_ref().expect("null RenderContext has no gpu")
+    }
+
+    fn gpu_mut(&mut self) -> &mut Gpu<'s, 'p, 'd> {
+        self.gpu.as_mut().expect("null RenderContext has no gpu")
+    }
 }
 
 pub struct DefaultPipelines {
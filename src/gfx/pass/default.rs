@@ -20,37 +20,41 @@ pub struct DefaultPass {
 impl DefaultPass {
     pub fn new(render_ctx: &mut RenderContext) -> DefaultPass {
         let (pipeline, global_bind_group_layout, model_bind_group_layout) =
-            default_flat_pipeline(render_ctx.device);
-
-        let depth_texture = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: render_ctx.screen_size.0,
-                height: render_ctx.screen_size.1,
-                depth: 1,
-            },
-            array_layer_count: 1,
-            mip_level_count: 1,
-            sample_count: 4,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-        });
-
-        let multisample_texture = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: render_ctx.screen_size.0,
-                height: render_ctx.screen_size.1,
-                depth: 1,
-            },
-            array_layer_count: 1,
-            mip_level_count: 1,
-            sample_count: 4,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-        });
+            default_flat_pipeline(render_ctx.device());
+
+        let depth_texture = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: render_ctx.screen_size.0,
+                    height: render_ctx.screen_size.1,
+                    depth: 1,
+                },
+                array_layer_count: 1,
+                mip_level_count: 1,
+                sample_count: 4,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Depth32Float,
+                usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            });
+
+        let multisample_texture = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: render_ctx.screen_size.0,
+                    height: render_ctx.screen_size.1,
+                    depth: 1,
+                },
+                array_layer_count: 1,
+                mip_level_count: 1,
+                sample_count: 4,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            });
 
         DefaultPass {
             pipeline,
@@ -72,7 +76,7 @@ impl DefaultPass {
         use zerocopy::AsBytes;
 
         let transforms_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_with_data(&camera.as_bytes(), wgpu::BufferUsage::UNIFORM);
 
         #[derive(AsBytes)]
@@ -94,13 +98,13 @@ impl DefaultPass {
         }
 
         let light_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_with_data(&lights.as_bytes(), wgpu::BufferUsage::UNIFORM);
 
         let (shadow_view, shadow_sampler) = self.shadow_pass.shadow_view();
 
         render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
                 layout: &self.global_bind_group_layout,
                 bindings: &[
@@ -157,11 +161,11 @@ impl DefaultPass {
         };
 
         let model_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_with_data(&model_data.as_bytes(), wgpu::BufferUsage::UNIFORM);
 
         render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
                 layout: &self.model_bind_group_layout,
                 bindings: &[wgpu::Binding {
@@ -221,12 +225,13 @@ where
             });
         }
 
+        let frame = render_ctx.frame();
         let mut rpass = render_ctx
-            .encoder
+            .encoder()
             .begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
                     attachment: &self.multisample_texture,
-                    resolve_target: Some(&render_ctx.frame),
+                    resolve_target: Some(frame),
                     load_op: wgpu::LoadOp::Clear,
                     store_op: wgpu::StoreOp::Store,
                     clear_color: wgpu::Color::BLACK,
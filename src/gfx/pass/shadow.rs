@@ -50,15 +50,15 @@ impl ShadowPass {
         let fs_source = include_bytes!("../../../assets/shader/shadow.frag.spv");
 
         let vs_module = render_ctx
-            .device
+            .device()
             .create_shader_module(&wgpu::read_spirv(std::io::Cursor::new(&vs_source[..])).unwrap());
         let fs_module = render_ctx
-            .device
+            .device()
             .create_shader_module(&wgpu::read_spirv(std::io::Cursor::new(&fs_source[..])).unwrap());
 
         let light_bind_group_layout =
             render_ctx
-                .device
+                .device()
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     bindings: &[wgpu::BindGroupLayoutEntry {
                         binding: 0,
@@ -70,7 +70,7 @@ impl ShadowPass {
 
         let model_bind_group_layout =
             render_ctx
-                .device
+                .device()
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     bindings: &[wgpu::BindGroupLayoutEntry {
                         binding: 0,
@@ -82,14 +82,14 @@ impl ShadowPass {
 
         let pipeline_layout =
             render_ctx
-                .device
+                .device()
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     bind_group_layouts: &[&light_bind_group_layout, &model_bind_group_layout],
                 });
 
         (
             render_ctx
-                .device
+                .device()
                 .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                     layout: &pipeline_layout,
                     vertex_stage: wgpu::ProgrammableStageDescriptor {
@@ -147,11 +147,11 @@ impl ShadowPass {
         use zerocopy::AsBytes;
 
         let transforms_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_with_data(&light.view_matrix.as_bytes(), wgpu::BufferUsage::UNIFORM);
 
         render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
                 layout: &self.light_bind_group_layout,
                 bindings: &[wgpu::Binding {
@@ -182,11 +182,11 @@ impl ShadowPass {
         };
 
         let model_buffer = render_ctx
-            .device
+            .device()
             .create_buffer_with_data(&model_data.as_bytes(), wgpu::BufferUsage::UNIFORM);
 
         render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
                 layout: &self.model_bind_group_layout,
                 bindings: &[wgpu::Binding {
@@ -203,32 +203,36 @@ impl ShadowPass {
     fn prepare_shadow_views(
         render_ctx: &mut RenderContext,
     ) -> (wgpu::Sampler, wgpu::TextureView, Vec<wgpu::TextureView>) {
-        let shadow_sampler = render_ctx.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare: wgpu::CompareFunction::LessEqual,
-        });
-
-        let shadow_texture = render_ctx.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: ShadowPass::SHADOW_RES,
-                height: ShadowPass::SHADOW_RES,
-                depth: 1,
-            },
-            array_layer_count: ShadowPass::MAX_LIGHTS as u32,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
-        });
+        let shadow_sampler = render_ctx
+            .device()
+            .create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare: wgpu::CompareFunction::LessEqual,
+            });
+
+        let shadow_texture = render_ctx
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: ShadowPass::SHADOW_RES,
+                    height: ShadowPass::SHADOW_RES,
+                    depth: 1,
+                },
+                array_layer_count: ShadowPass::MAX_LIGHTS as u32,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Depth32Float,
+                usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            });
 
         let shadow_view = shadow_texture.create_default_view();
         let light_views = (0..ShadowPass::MAX_LIGHTS)
@@ -289,7 +293,7 @@ where
             let light_bind_group = self.light_bind_group(light, render_ctx);
 
             let mut rpass = render_ctx
-                .encoder
+                .encoder()
                 .begin_render_pass(&wgpu::RenderPassDescriptor {
                     color_attachments: &[],
                     depth_stencil_attachment: Some(
@@ -298,7 +298,7 @@ where
 
         self.primitives = primitives;
 
-        self.vertex_buffer = Some(render_ctx.device.create_buffer_init(
+        self.vertex_buffer = Some(render_ctx.device().create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: None,
                 contents: &geometry_buffer.vertices.as_bytes(),
@@ -306,7 +306,7 @@ where
             },
         ));
 
-        self.index_buffer = Some(render_ctx.device.create_buffer_init(
+        self.index_buffer = Some(render_ctx.device().create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: None,
                 contents: &geometry_buffer.indices.as_bytes(),
@@ -332,7 +332,7 @@ where
 
         let global_buffer =
             render_ctx
-                .device
+                .device()
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: None,
                     contents: &vp.as_bytes(),
@@ -340,9 +340,9 @@ where
                 });
 
         let global_bind_group = render_ctx
-            .device
+            .device()
             .create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &render_ctx.pipelines.primitives.1,
+                layout: &render_ctx.pipelines().primitives.1,
                 label: None,
                 entries: &[wgpu::BindGroupEntry {
                     binding: 0,
@@ -351,18 +351,20 @@ where
             });
 
         {
+            let frame = render_ctx.frame();
+            let pipelines = render_ctx.pipelines();
             let mut pass = render_ctx
-                .encoder
+                .encoder()
                 .begin_render_pass(&wgpu::RenderPassDescriptor {
                     color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                        attachment: &render_ctx.frame,
+                        attachment: frame,
                         resolve_target: None,
                         ops: Default::default(),
                     }],
                     depth_stencil_attachment: None,
                 });
 
-            pass.set_pipeline(&render_ctx.pipelines.primitives.0);
+            pass.set_pipeline(&pipelines.primitives.0);
             pass.set_bind_group(0, &global_bind_group, &[]);
             pass.set_index_buffer(self.index_buffer.as_ref().unwrap().slice(..));
             pass.set_vertex_buffer(0, self.vertex_buffer.as_ref().unwrap().slice(..));
@@ -57,7 +57,7 @@ impl DefaultScene<DefaultMesh> {
         let buffers: Vec<_> = buffers
             .into_iter()
             .map(|b| {
-                Arc::new(render_ctx.device.create_buffer_with_data(
+                Arc::new(render_ctx.device().create_buffer_with_data(
                     &b,
                     wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::INDEX,
                 ))
@@ -71,7 +71,7 @@ impl DefaultScene<DefaultMesh> {
 
             Arc::new(
                 render_ctx
-                    .device
+                    .device()
                     .create_buffer_with_data(materials.as_bytes(), wgpu::BufferUsage::UNIFORM),
             )
         };
@@ -7,7 +7,7 @@ use crate::gfx::{RenderContext, Texture};
 use wgpu::util::DeviceExt;
 
 pub struct Spritebatch {
-    texture: Texture,
+    texture: Option<Texture>,
     vertices: Vec<Vertex2D>,
     indices: Vec<u16>,
     dirty: bool,
@@ -18,7 +18,16 @@ pub struct Spritebatch {
 impl Spritebatch {
     pub fn new(texture: Texture) -> Spritebatch {
         Spritebatch {
-            texture,
+            texture: Some(texture),
+            ..Spritebatch::headless()
+        }
+    }
+
+    /// creates a spritebatch without a texture, for games run without rendering. It batches
+    /// quads like any other but is never uploaded or drawn.
+    pub fn headless() -> Spritebatch {
+        Spritebatch {
+            texture: None,
             vertices: Vec::new(),
             indices: Vec::new(),
             dirty: false,
@@ -27,6 +36,15 @@ impl Spritebatch {
         }
     }
 
+    /// the number of quads in the batch
+    pub fn len(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
     pub fn add_quad(&mut self, quad: &Quad, position: [f32; 2]) {
         self.add_quad_colored(quad, position, [1., 1., 1., 1.]);
     }
@@ -60,16 +78,17 @@ impl Spritebatch {
         //       For now, we recreate them each frame.
         //       See alse https://github.com/gfx-rs/wgpu-rs/issues/9
 
-        let vertex_buf = render_ctx
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: &self.vertices.as_bytes(),
-                usage: wgpu::BufferUsage::VERTEX,
-            });
+        let vertex_buf =
+            render_ctx
+                .device()
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: &self.vertices.as_bytes(),
+                    usage: wgpu::BufferUsage::VERTEX,
+                });
 
         let index_buf = render_ctx
-            .device
+            .device()
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: None,
                 contents: &self.indices.as_bytes(),
@@ -86,23 +105,26 @@ impl Spritebatch {
         // TODO: get rid of zerobytes
         use zerocopy::AsBytes;
 
-        let texture_view = self
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
-        let sampler = render_ctx.device.create_sampler(&wgpu::SamplerDescriptor {
-            label: None,
-            address_mode_u: Default::default(),
-            address_mode_v: Default::default(),
-            address_mode_w: Default::default(),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: Default::default(),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare: Some(wgpu::CompareFunction::Never),
-            anisotropy_clamp: None,
-        });
+        let texture_view = match self.texture {
+            Some(ref texture) => texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            None => return,
+        };
+
+        let sampler = render_ctx
+            .device()
+            .create_sampler(&wgpu::SamplerDescriptor {
+                label: None,
+                address_mode_u: Default::default(),
+                address_mode_v: Default::default(),
+                address_mode_w: Default::default(),
+                mag_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: Default::default(),
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare: Some(wgpu::CompareFunction::Never),
+                anisotropy_clamp: None,
+            });
 
         let transformations: [[f32; 4]; 4] = nalgebra::Matrix4::new_nonuniform_scaling(
             &nalgebra::Vector3::new(1. / 768., 1. / 576., 1.),
@@ -113,7 +135,7 @@ impl Spritebatch {
 
         let transforms_buffer =
             render_ctx
-                .device
+                .device()
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: None,
                     contents: &transformations.as_bytes(),
@@ -122,10 +144,10 @@ impl Spritebatch {
 
         self.bind_group = Some((
             render_ctx
-                .device
+                .device()
                 .create_bind_group(&wgpu::BindGroupDescriptor {
                     label: None,
-                    layout: &render_ctx.pipelines.pixel.1,
+                    layout: &render_ctx.pipelines().pixel.1,
                     entries: &[
                         wgpu::BindGroupEntry {
                             binding: 0,
@@ -148,7 +170,12 @@ impl Spritebatch {
         ));
     }
 
+    /// uploads the batch to the gpu. Does nothing on a null render context.
     pub fn update(&mut self, render_ctx: &mut RenderContext) {
+        if render_ctx.is_null() || self.texture.is_none() {
+            return;
+        }
+
         if self.dirty {
             self.update_buffer(render_ctx);
         }
@@ -159,7 +186,7 @@ impl Spritebatch {
     }
 
     pub fn draw(&self, _transform: &nalgebra::Matrix4<f32>, render_ctx: &mut RenderContext) {
-        if self.dirty {
+        if self.dirty || render_ctx.is_null() {
             return;
         }
 
@@ -170,7 +197,10 @@ impl Spritebatch {
         };
 
         // bind group is set here
-        let (bind_group, _) = self.bind_group.as_ref().unwrap();
+        let (bind_group, _) = match self.bind_group {
+            Some(ref bind_group) => bind_group,
+            None => return,
+        };
         //        let transform_data: [[f32; 4]; 4] = transform.clone().into();
 
         //        transform_buffer.map_write_async(0, std::mem::size_of::<[[f32; 4]; 4]>() as u64, move |buf| {
@@ -179,18 +209,20 @@ impl Spritebatch {
         //
         //        transform_buffer.unmap();
 
+        let frame = render_ctx.frame();
+        let pipelines = render_ctx.pipelines();
         let mut rpass = render_ctx
-            .encoder
+            .encoder()
             .begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &render_ctx.frame,
+                    attachment: frame,
                     resolve_target: None,
                     ops: Default::default(),
                 }],
                 depth_stencil_attachment: None,
             });
 
-        rpass.set_pipeline(&render_ctx.pipelines.pixel.0);
+        rpass.set_pipeline(&pipelines.pixel.0);
         rpass.set_bind_group(0, bind_group, &[]);
         rpass.set_index_buffer(index_buf.slice(..));
         rpass.set_vertex_buffer(0, vertex_buf.slice(..));
@@ -203,7 +235,7 @@ impl Spritebatch {
         view: &wgpu::TextureView,
         render_ctx: &mut RenderContext,
     ) {
-        if self.dirty {
+        if self.dirty || render_ctx.is_null() {
             return;
         }
 
@@ -214,7 +246,10 @@ impl Spritebatch {
         };
 
         // bind group is set here
-        let (bind_group, _) = self.bind_group.as_ref().unwrap();
+        let (bind_group, _) = match self.bind_group {
+            Some(ref bind_group) => bind_group,
+            None => return,
+        };
         //        let transform_data: [[f32; 4]; 4] = transform.clone().into();
 
         //        transform_buffer.map_write_async(0, std::mem::size_of::<[[f32; 4]; 4]>() as u64, move |buf| {
@@ -223,8 +258,9 @@ impl Spritebatch {
         //
         //        transform_buffer.unmap();
 
+        let pipelines = render_ctx.pipelines();
         let mut rpass = render_ctx
-            .encoder
+            .encoder()
             .begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
                     attachment: view,
@@ -234,7 +270,7 @@ impl Spritebatch {
                 depth_stencil_attachment: None,
             });
 
-        rpass.set_pipeline(&render_ctx.pipelines.pixel.0);
+        rpass.set_pipeline(&pipelines.pixel.0);
         rpass.set_bind_group(0, bind_group, &[]);
         rpass.set_index_buffer(index_buf.slice(..));
         rpass.set_vertex_buffer(0, vertex_buf.slice(..));
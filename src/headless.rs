@@ -0,0 +1,312 @@
+//! Running games without a window
+//!
+//! ```ignore
+//! // hold W from frame 10 to frame 40
+//! let game = HeadlessRunner::new(MyGame::new())
+//!     .with_key(10, 0x11, true)
+//!     .with_key(40, 0x11, false)
+//!     .run_frames(120);
+//!
+//! assert!(game.player_position().y > 0.);
+//! ```
+
+use std::iter::Peekable;
+use std::time::Duration;
+use std::vec::IntoIter;
+
+use futures::executor::block_on;
+use winit::event::Event;
+use winit::event_loop::ControlFlow;
+
+use crate::application::{run_frame, update_frame, Settings, TickTimer};
+use crate::debug::DebugContext;
+use crate::game::{IoState, Playable};
+use crate::gfx::{default_render_pipelines, RenderContext};
+use crate::input::replay::{push_input, Replay};
+use crate::input::InputEvent;
+
+/// Runs a game without a window, with a simulated clock and scripted input events, for tests
+/// and dedicated servers.
+///
+/// Every frame takes exactly `frame_time`, no matter how long it really took. Rendering goes to
+/// an offscreen texture nobody looks at, so no window or display is needed, but a wgpu adapter
+/// still is. A software one, like Mesa's lavapipe, is enough. `RenderContext::window` is None.
+///
+/// Without rendering, see `HeadlessRunner::without_rendering`, no adapter is needed at all, only
+/// updates are run, with a null `RenderContext` and `DebugContext`.
+///
+/// The seed given to `Playable::set_seed` is 0, or the seed of the replay.
+pub struct HeadlessRunner<G: Playable> {
+    game: G,
+    settings: Settings,
+    frame_time: Duration,
+    /// scripted events, with the frame they are pushed on
+    events: Vec<(u64, Event<'static, ()>)>,
+    replay: Option<Replay>,
+    rendering: bool,
+}
+
+impl<G: Playable> HeadlessRunner<G> {
+    /// creates a runner with default `Settings`, running at 60 frames per second
+    pub fn new(game: G) -> HeadlessRunner<G> {
+        HeadlessRunner {
+            game,
+            settings: Settings::default(),
+            frame_time: Duration::from_secs_f64(1. / 60.),
+            events: Vec::new(),
+            replay: None,
+            rendering: true,
+        }
+    }
+
+    /// sets the settings, of which the window size and tick settings are used
+    pub fn with_settings(self, settings: Settings) -> HeadlessRunner<G> {
+        HeadlessRunner { settings, ..self }
+    }
+
+    /// sets the simulated duration of every frame
+    pub fn with_frame_time(self, frame_time: Duration) -> HeadlessRunner<G> {
+        HeadlessRunner { frame_time, ..self }
+    }
+
+    /// pushes event to the game before frame is run. Events on the same frame are pushed in the
+    /// order they were added.
+    pub fn with_event(self, frame: u64, event: Event<'static, ()>) -> HeadlessRunner<G> {
+        let HeadlessRunner { mut events, .. } = self;
+
+        events.push((frame, event));
+
+        HeadlessRunner { events, ..self }
+    }
+
+    /// presses or releases key before frame is run
+    pub fn with_key(self, frame: u64, scancode: u32, pressed: bool) -> HeadlessRunner<G> {
+        let event = InputEvent::Key { scancode, pressed }
+            .to_event()
+            .expect("keys are window events");
+
+        self.with_event(frame, event)
+    }
+
+    /// plays replay back from the first frame, with its frame deltas instead of `frame_time`
+    /// until it runs out. Scripted events of a frame are pushed before the replayed ones.
+    pub fn with_replay(self, replay: Replay) -> HeadlessRunner<G> {
+        HeadlessRunner {
+            replay: Some(replay),
+            ..self
+        }
+    }
+
+    /// runs fixed updates and updates only, with null contexts, so no gpu is needed. Redraw is
+    /// never called.
+    pub fn without_rendering(self) -> HeadlessRunner<G> {
+        HeadlessRunner {
+            rendering: false,
+            ..self
+        }
+    }
+
+    /// runs frames frames, or until the game asks to exit, and returns the game
+    pub fn run_frames(self, frames: u64) -> G {
+        let HeadlessRunner {
+            mut game,
+            settings,
+            frame_time,
+            events,
+            replay,
+            rendering,
+        } = self;
+
+        game.set_seed(replay.as_ref().map_or(0, Replay::seed));
+
+        let mut input = ScriptedInput::new(events, replay, frame_time);
+
+        if !rendering {
+            return run_updates(game, &settings, &mut input, frames);
+        }
+
+        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::Default,
+            compatible_surface: None,
+        }))
+        .expect("No graphics adapter found, a software one will do");
+
+        let (device, mut queue) = block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: Default::default(),
+                limits: wgpu::Limits::default(),
+                shader_validation: false,
+            },
+            None,
+        ))
+        .expect("Failed to get rendering device");
+
+        let render_pipelines = default_render_pipelines(&device);
+
+        let screen_size = (
+            settings.window_size[0] as u32,
+            settings.window_size[1] as u32,
+        );
+        let format = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: screen_size.0,
+                height: screen_size.1,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        });
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut imgui_ctx = imgui::Context::create();
+        imgui_ctx.set_ini_filename(None);
+        imgui_ctx.io_mut().display_size = settings.window_size;
+        imgui_ctx
+            .fonts()
+            .add_font(&[imgui::FontSource::DefaultFontData { config: None }]);
+
+        let mut imgui_renderer =
+            imgui_wgpu::Renderer::new(&mut imgui_ctx, &device, &mut queue, format);
+
+        let mut tick_timer = TickTimer::new(&settings);
+        let mut io_state = IoState::default();
+
+        for frame in 0..frames {
+            let delta = match input.push(frame, &mut game, &mut io_state) {
+                Some(delta) => delta,
+                None => return game,
+            };
+
+            let update_encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            let draw_encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+            imgui_ctx.io_mut().update_delta_time(delta);
+
+            let render_ctx = RenderContext::new(
+                &target_view,
+                update_encoder,
+                &device,
+                &render_pipelines,
+                screen_size,
+                None,
+            );
+
+            let debug_ctx = DebugContext::new(imgui_ctx.frame(), &mut imgui_renderer);
+
+            let (update_buffer, draw_buffer) = run_frame(
+                &mut game,
+                delta,
+                &mut io_state,
+                &mut tick_timer,
+                render_ctx,
+                draw_encoder,
+                debug_ctx,
+                &queue,
+            );
+
+            queue.submit(vec![update_buffer, draw_buffer]);
+            device.poll(wgpu::Maintain::Poll);
+        }
+
+        game
+    }
+}
+
+/// runs frames frames of updates only, with null contexts
+fn run_updates<G: Playable>(
+    mut game: G,
+    settings: &Settings,
+    input: &mut ScriptedInput,
+    frames: u64,
+) -> G {
+    let screen_size = (
+        settings.window_size[0] as u32,
+        settings.window_size[1] as u32,
+    );
+
+    let mut tick_timer = TickTimer::new(settings);
+    let mut io_state = IoState::default();
+
+    for frame in 0..frames {
+        let delta = match input.push(frame, &mut game, &mut io_state) {
+            Some(delta) => delta,
+            None => return game,
+        };
+
+        let mut render_ctx = RenderContext::null(screen_size);
+        let mut debug_ctx = DebugContext::null();
+
+        update_frame(
+            &mut game,
+            delta,
+            &mut io_state,
+            &mut tick_timer,
+            &mut render_ctx,
+            &mut debug_ctx,
+        );
+    }
+
+    game
+}
+
+/// Scripted events and replay of a run
+struct ScriptedInput {
+    events: Peekable<IntoIter<(u64, Event<'static, ()>)>>,
+    replay: Option<Replay>,
+    frame_time: Duration,
+}
+
+impl ScriptedInput {
+    fn new(
+        mut events: Vec<(u64, Event<'static, ()>)>,
+        replay: Option<Replay>,
+        frame_time: Duration,
+    ) -> ScriptedInput {
+        events.sort_by_key(|(frame, _)| *frame);
+
+        ScriptedInput {
+            events: events.into_iter().peekable(),
+            replay,
+            frame_time,
+        }
+    }
+
+    /// pushes the events of frame to game and io_state, returns the delta of the frame, or None
+    /// if the game asked to exit
+    fn push<G: Playable>(
+        &mut self,
+        frame: u64,
+        game: &mut G,
+        io_state: &mut IoState,
+    ) -> Option<Duration> {
+        while let Some((_, event)) = self.events.peek().filter(|(at, _)| *at <= frame) {
+            io_state.push_event(event);
+
+            if let Some(ControlFlow::Exit) = game.push_event(event) {
+                return None;
+            }
+
+            self.events.next();
+        }
+
+        let replay_frame = self.replay.as_mut().and_then(Replay::next_frame);
+
+        for event in replay_frame.iter().flat_map(|frame| &frame.events) {
+            if let Some(ControlFlow::Exit) = push_input(game, io_state, event) {
+                return None;
+            }
+        }
+
+        Some(replay_frame.map_or(self.frame_time, |frame| frame.delta))
+    }
+}
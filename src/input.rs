@@ -0,0 +1,540 @@
+//! Named actions and axes, bound to keys, mouse and gamepads
+//!
+//! Bindings are usually loaded from `input.json`, next to `settings.json`:
+//!
+//! ```json
+//! {
+//!     "actions": {
+//!         "jump": [{ "key": 57 }, { "gamepad_button": "south" }]
+//!     },
+//!     "axes": {
+//!         "move_x": [
+//!             { "buttons": { "negative": { "key": 30 }, "positive": { "key": 32 } } },
+//!             { "gamepad_axis": "left_stick_x", "dead_zone": 0.2 }
+//!         ],
+//!         "look_x": [{ "mouse_motion": "x", "scale": 0.01 }]
+//!     }
+//! }
+//! ```
+//!
+//! Key bindings use scancodes, so they stay in the same place on every keyboard layout.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalPosition;
+use winit::event::{
+    DeviceEvent, DeviceId, ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+    MouseScrollDelta, ScanCode, TouchPhase, WindowEvent,
+};
+use winit::window::WindowId;
+
+use crate::asset::AssetError;
+use crate::game::IoState;
+
+#[cfg(feature = "gamepad")]
+pub use gamepad::Gamepads;
+
+#[cfg(feature = "gamepad")]
+mod gamepad;
+pub mod replay;
+
+/// how many pixels of touchpad scrolling count as one line of scroll wheel
+const PIXELS_PER_LINE: f64 = 20.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadButton {
+    /// A on Xbox controllers, cross on PlayStation controllers
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    LeftZ,
+    RightStickX,
+    RightStickY,
+    RightZ,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    X,
+    Y,
+}
+
+/// Something that can be held down
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Key(ScanCode),
+    /// 0 being left, 1 right and 2 middle
+    MouseButton(usize),
+    GamepadButton(GamepadButton),
+}
+
+impl Button {
+    fn is_down(&self, io_state: &IoState) -> bool {
+        match *self {
+            Button::Key(key) => io_state.is_down(key),
+            Button::MouseButton(button) => io_state.is_mouse_button_down(button),
+            Button::GamepadButton(button) => io_state.is_gamepad_button_down(button),
+        }
+    }
+
+    fn was_down(&self, io_state: &IoState) -> bool {
+        match *self {
+            Button::Key(key) => io_state.was_down(key),
+            Button::MouseButton(button) => io_state.was_mouse_button_down(button),
+            Button::GamepadButton(button) => io_state.was_gamepad_button_down(button),
+        }
+    }
+}
+
+/// Something with an analog value
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis {
+    /// -1 while negative is held, 1 while positive is held, 0 while both or neither are
+    Buttons { negative: Button, positive: Button },
+    /// mouse movement since last frame, in pixels
+    MouseMotion(Direction),
+    /// scroll wheel movement since last frame, in lines
+    Scroll(Direction),
+    /// from -1 to 1
+    GamepadAxis(GamepadAxis),
+}
+
+impl Axis {
+    fn value(&self, io_state: &IoState) -> f32 {
+        let direction = |direction: &Direction, values: [f32; 2]| match direction {
+            Direction::X => values[0],
+            Direction::Y => values[1],
+        };
+
+        match self {
+            Axis::Buttons { negative, positive } => {
+                let negative = if negative.is_down(io_state) { 1. } else { 0. };
+                let positive = if positive.is_down(io_state) { 1. } else { 0. };
+
+                positive - negative
+            }
+            Axis::MouseMotion(d) => direction(d, io_state.mouse_motion),
+            Axis::Scroll(d) => direction(d, io_state.scroll),
+            Axis::GamepadAxis(axis) => io_state.gamepad_axis(*axis),
+        }
+    }
+}
+
+fn default_scale() -> f32 {
+    1.
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    #[serde(flatten)]
+    pub axis: Axis,
+    /// multiplier for the value of axis, negative to invert it
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// values closer to zero than this count as zero, for sticks that don't quite center
+    #[serde(default)]
+    pub dead_zone: f32,
+}
+
+impl AxisBinding {
+    pub fn new(axis: Axis) -> AxisBinding {
+        AxisBinding {
+            axis,
+            scale: 1.,
+            dead_zone: 0.,
+        }
+    }
+
+    fn value(&self, io_state: &IoState) -> f32 {
+        let value = self.axis.value(io_state);
+
+        if value.abs() <= self.dead_zone {
+            0.
+        } else {
+            value * self.scale
+        }
+    }
+}
+
+/// Maps action and axis names to their bindings. Names that aren't bound are never held, and
+/// their axes are always 0.
+///
+/// Usually kept as a World resource, so systems can query it with the `IoState` they get.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InputMap {
+    #[serde(default)]
+    actions: BTreeMap<String, Vec<Button>>,
+    #[serde(default)]
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+    pub fn new() -> InputMap {
+        InputMap::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<InputMap, AssetError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// loads bindings from a json file, like `input.json`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InputMap, AssetError> {
+        let json = std::fs::read_to_string(path)?;
+
+        InputMap::from_json(&json)
+    }
+
+    /// binds button to action, in addition to the buttons already bound to it
+    pub fn with_action(self, action: &str, button: Button) -> InputMap {
+        let InputMap { mut actions, .. } = self;
+
+        actions.entry(action.to_string()).or_default().push(button);
+
+        InputMap { actions, ..self }
+    }
+
+    /// binds axis to an axis name, in addition to the axes already bound to it
+    pub fn with_axis(self, name: &str, axis: Axis) -> InputMap {
+        self.with_axis_binding(name, AxisBinding::new(axis))
+    }
+
+    /// like `with_axis`, with scale and dead zone
+    pub fn with_axis_binding(self, name: &str, binding: AxisBinding) -> InputMap {
+        let InputMap { mut axes, .. } = self;
+
+        axes.entry(name.to_string()).or_default().push(binding);
+
+        InputMap { axes, ..self }
+    }
+
+    fn buttons(&self, action: &str) -> &[Button] {
+        self.actions.get(action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// true while any button bound to action is down
+    pub fn held(&self, action: &str, io_state: &IoState) -> bool {
+        self.buttons(action)
+            .iter()
+            .any(|button| button.is_down(io_state))
+    }
+
+    /// true on the frame action became held
+    pub fn pressed(&self, action: &str, io_state: &IoState) -> bool {
+        let buttons = self.buttons(action);
+
+        buttons.iter().any(|button| button.is_down(io_state))
+            && !buttons.iter().any(|button| button.was_down(io_state))
+    }
+
+    /// true on the frame action stopped being held
+    pub fn released(&self, action: &str, io_state: &IoState) -> bool {
+        let buttons = self.buttons(action);
+
+        !buttons.iter().any(|button| button.is_down(io_state))
+            && buttons.iter().any(|button| button.was_down(io_state))
+    }
+
+    /// value of the axis, from the binding furthest from zero
+    pub fn axis(&self, name: &str, io_state: &IoState) -> f32 {
+        self.axes
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|binding| binding.value(io_state))
+            .fold(0., |value, other| {
+                if other.abs() > value.abs() {
+                    other
+                } else {
+                    value
+                }
+            })
+    }
+}
+
+/// An input event `IoState` tracks, in a form that can be saved, see `replay`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Key {
+        scancode: ScanCode,
+        pressed: bool,
+    },
+    /// 0 being left, 1 right and 2 middle
+    MouseButton {
+        button: usize,
+        pressed: bool,
+    },
+    /// in physical pixels, from the top left corner of the window
+    CursorMoved {
+        x: f32,
+        y: f32,
+    },
+    /// in lines
+    Scroll {
+        x: f32,
+        y: f32,
+    },
+    /// raw mouse movement
+    MouseMotion {
+        x: f32,
+        y: f32,
+    },
+    Text(char),
+    Focused(bool),
+    GamepadButton {
+        button: GamepadButton,
+        pressed: bool,
+    },
+    GamepadAxis {
+        axis: GamepadAxis,
+        value: f32,
+    },
+    GamepadDisconnected,
+}
+
+impl InputEvent {
+    /// converts a window or device event, None if it's not one `IoState` tracks
+    pub fn from_event<T>(event: &Event<T>) -> Option<InputEvent> {
+        let pressed = |state: &ElementState| *state == ElementState::Pressed;
+
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput { input, .. } => Some(InputEvent::Key {
+                    scancode: input.scancode,
+                    pressed: pressed(&input.state),
+                }),
+                WindowEvent::MouseInput { button, state, .. } => {
+                    let button = match button {
+                        MouseButton::Left => 0,
+                        MouseButton::Right => 1,
+                        MouseButton::Middle => 2,
+                        MouseButton::Other(_) => return None,
+                    };
+
+                    Some(InputEvent::MouseButton {
+                        button,
+                        pressed: pressed(state),
+                    })
+                }
+                WindowEvent::CursorMoved { position, .. } => Some(InputEvent::CursorMoved {
+                    x: position.x as f32,
+                    y: position.y as f32,
+                }),
+                WindowEvent::MouseWheel { delta, .. } => Some(match delta {
+                    MouseScrollDelta::LineDelta(x, y) => InputEvent::Scroll { x: *x, y: *y },
+                    MouseScrollDelta::PixelDelta(position) => InputEvent::Scroll {
+                        x: (position.x / PIXELS_PER_LINE) as f32,
+                        y: (position.y / PIXELS_PER_LINE) as f32,
+                    },
+                }),
+                WindowEvent::ReceivedCharacter(character) => Some(InputEvent::Text(*character)),
+                WindowEvent::Focused(focused) => Some(InputEvent::Focused(*focused)),
+                _ => None,
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => Some(InputEvent::MouseMotion {
+                x: delta.0 as f32,
+                y: delta.1 as f32,
+            }),
+            _ => None,
+        }
+    }
+
+    /// converts back to a winit event, for `Playable::push_event`. Gamepad events have no winit
+    /// counterpart, and keys lose their virtual keycode.
+    pub fn to_event(&self) -> Option<Event<'static, ()>> {
+        let state = |pressed: bool| {
+            if pressed {
+                ElementState::Pressed
+            } else {
+                ElementState::Released
+            }
+        };
+
+        // there is no real window or device, but games don't look at the ids anyway
+        let window_id = unsafe { WindowId::dummy() };
+        let device_id = unsafe { DeviceId::dummy() };
+
+        #[allow(deprecated)]
+        let event = match *self {
+            InputEvent::Key { scancode, pressed } => WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode,
+                    state: state(pressed),
+                    virtual_keycode: None,
+                    modifiers: ModifiersState::empty(),
+                },
+                is_synthetic: true,
+            },
+            InputEvent::MouseButton { button, pressed } => WindowEvent::MouseInput {
+                device_id,
+                state: state(pressed),
+                button: match button {
+                    0 => MouseButton::Left,
+                    1 => MouseButton::Right,
+                    2 => MouseButton::Middle,
+                    _ => return None,
+                },
+                modifiers: ModifiersState::empty(),
+            },
+            InputEvent::CursorMoved { x, y } => WindowEvent::CursorMoved {
+                device_id,
+                position: PhysicalPosition::new(x as f64, y as f64),
+                modifiers: ModifiersState::empty(),
+            },
+            InputEvent::Scroll { x, y } => WindowEvent::MouseWheel {
+                device_id,
+                delta: MouseScrollDelta::LineDelta(x, y),
+                phase: TouchPhase::Moved,
+                modifiers: ModifiersState::empty(),
+            },
+            InputEvent::MouseMotion { x, y } => {
+                return Some(Event::DeviceEvent {
+                    device_id,
+                    event: DeviceEvent::MouseMotion {
+                        delta: (x as f64, y as f64),
+                    },
+                })
+            }
+            InputEvent::Text(character) => WindowEvent::ReceivedCharacter(character),
+            InputEvent::Focused(focused) => WindowEvent::Focused(focused),
+            InputEvent::GamepadButton { .. }
+            | InputEvent::GamepadAxis { .. }
+            | InputEvent::GamepadDisconnected => return None,
+        };
+
+        Some(Event::WindowEvent { window_id, event })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_map() -> InputMap {
+        InputMap::from_json(
+            r#"{
+                "actions": {
+                    "jump": [{ "key": 57 }, { "gamepad_button": "south" }]
+                },
+                "axes": {
+                    "move_x": [
+                        { "buttons": { "negative": { "key": 30 }, "positive": { "key": 32 } } },
+                        { "gamepad_axis": "left_stick_x", "scale": -2, "dead_zone": 0.2 }
+                    ]
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_flattened_axis_bindings() {
+        let input_map = input_map();
+
+        assert_eq!(
+            input_map.axes["move_x"],
+            vec![
+                AxisBinding::new(Axis::Buttons {
+                    negative: Button::Key(30),
+                    positive: Button::Key(32),
+                }),
+                AxisBinding {
+                    axis: Axis::GamepadAxis(GamepadAxis::LeftStickX),
+                    scale: -2.,
+                    dead_zone: 0.2,
+                },
+            ]
+        );
+        assert_eq!(
+            input_map.actions["jump"],
+            vec![Button::Key(57), Button::GamepadButton(GamepadButton::South)]
+        );
+    }
+
+    #[test]
+    fn axes_apply_scale_and_dead_zone() {
+        let input_map = input_map();
+        let mut io_state = IoState::default();
+
+        io_state.set_gamepad_axis(GamepadAxis::LeftStickX, 0.1);
+        assert_eq!(input_map.axis("move_x", &io_state), 0.);
+
+        io_state.set_gamepad_axis(GamepadAxis::LeftStickX, 0.25);
+        assert_eq!(input_map.axis("move_x", &io_state), -0.5);
+
+        // the keys are further from zero than the stick, so they win
+        io_state.set_key(32, true);
+        assert_eq!(input_map.axis("move_x", &io_state), 1.);
+
+        io_state.set_gamepad_axis(GamepadAxis::LeftStickX, 1.);
+        assert_eq!(input_map.axis("move_x", &io_state), -2.);
+    }
+
+    #[test]
+    fn pressed_and_released_across_bindings() {
+        let input_map = input_map();
+        let mut io_state = IoState::default();
+
+        io_state.set_key(57, true);
+        assert!(input_map.pressed("jump", &io_state));
+        io_state.update();
+
+        // a second binding going down while the first is held doesn't press again
+        io_state.set_gamepad_button(GamepadButton::South, true);
+        assert!(input_map.held("jump", &io_state));
+        assert!(!input_map.pressed("jump", &io_state));
+        io_state.update();
+
+        // nor does one of them going up release it
+        io_state.set_key(57, false);
+        assert!(input_map.held("jump", &io_state));
+        assert!(!input_map.released("jump", &io_state));
+        io_state.update();
+
+        io_state.set_gamepad_button(GamepadButton::South, false);
+        assert!(input_map.released("jump", &io_state));
+        io_state.update();
+
+        assert!(!input_map.released("jump", &io_state));
+
+        // both going down on the same frame press it once, both going up release it once
+        io_state.set_key(57, true);
+        io_state.set_gamepad_button(GamepadButton::South, true);
+        assert!(input_map.pressed("jump", &io_state));
+        io_state.update();
+
+        io_state.set_key(57, false);
+        io_state.set_gamepad_button(GamepadButton::South, false);
+        assert!(input_map.released("jump", &io_state));
+    }
+}
@@ -0,0 +1,93 @@
+//! Gamepad support through gilrs
+
+use gilrs::{EventType, Gilrs};
+
+use crate::game::IoState;
+use crate::input::{GamepadAxis, GamepadButton, InputEvent};
+
+/// Connected gamepads. Input from all of them is merged into the same `IoState`.
+pub struct Gamepads {
+    gilrs: Gilrs,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Gamepads, gilrs::Error> {
+        Ok(Gamepads {
+            gilrs: Gilrs::new()?,
+        })
+    }
+
+    /// applies gamepad events since the last poll to io_state, and returns them for recording.
+    /// `Application` does this before every frame when the gamepad feature is on.
+    pub fn poll(&mut self, io_state: &mut IoState) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        while let Some(gilrs::Event { event, .. }) = self.gilrs.next_event() {
+            let event = match event {
+                EventType::ButtonPressed(button, _) => {
+                    map_button(button).map(|button| InputEvent::GamepadButton {
+                        button,
+                        pressed: true,
+                    })
+                }
+                EventType::ButtonReleased(button, _) => {
+                    map_button(button).map(|button| InputEvent::GamepadButton {
+                        button,
+                        pressed: false,
+                    })
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    map_axis(axis).map(|axis| InputEvent::GamepadAxis { axis, value })
+                }
+                EventType::Disconnected => Some(InputEvent::GamepadDisconnected),
+                _ => None,
+            };
+
+            if let Some(event) = event {
+                io_state.apply(&event);
+                events.push(event);
+            }
+        }
+
+        events
+    }
+}
+
+fn map_button(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftTrigger,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger2,
+        Button::RightTrigger => GamepadButton::RightTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger2,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftThumb,
+        Button::RightThumb => GamepadButton::RightThumb,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+fn map_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    use gilrs::Axis;
+
+    Some(match axis {
+        Axis::LeftStickX => GamepadAxis::LeftStickX,
+        Axis::LeftStickY => GamepadAxis::LeftStickY,
+        Axis::LeftZ => GamepadAxis::LeftZ,
+        Axis::RightStickX => GamepadAxis::RightStickX,
+        Axis::RightStickY => GamepadAxis::RightStickY,
+        Axis::RightZ => GamepadAxis::RightZ,
+        _ => return None,
+    })
+}
@@ -0,0 +1,261 @@
+//! Recording input and replaying it
+//!
+//! A recording is the seed of the session and, for every frame, its delta and the input events
+//! that came before it. Replaying gives the game the same events and deltas, so as long as all of
+//! its randomness comes from `Playable::set_seed`, it does exactly the same things again.
+//!
+//! ```ignore
+//! // record, to a file that's written as the game runs, so crashes are caught too
+//! Application::new(MyGame::new(), "My game").record_to("bug.replay").run();
+//!
+//! // replay it with a window, taking over when the recording ends
+//! let replay = Replay::load("bug.replay")?;
+//! Application::new(MyGame::new(), "My game").with_replay(replay).run();
+//!
+//! // or without one, see `HeadlessRunner`
+//! let replay = Replay::load("bug.replay")?;
+//! let frames = replay.len() as u64;
+//! let game = HeadlessRunner::new(MyGame::new()).with_replay(replay).run_frames(frames);
+//! ```
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use winit::event_loop::ControlFlow;
+
+use crate::game::{IoState, Playable};
+use crate::input::InputEvent;
+
+/// bumped whenever the format changes, old recordings can't be replayed after that
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    IoError(std::io::Error),
+    BincodeError(bincode::Error),
+    /// recording was made by a different version of mela
+    UnsupportedVersion(u32),
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(err: std::io::Error) -> Self {
+        ReplayError::IoError(err)
+    }
+}
+
+impl From<bincode::Error> for ReplayError {
+    fn from(err: bincode::Error) -> Self {
+        ReplayError::BincodeError(err)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    seed: u64,
+}
+
+/// One recorded frame
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Frame {
+    pub delta: Duration,
+    /// input events pushed before the frame was run, in order
+    pub events: Vec<InputEvent>,
+}
+
+/// Writes input events and frame deltas to a file as they happen
+pub struct Recorder {
+    writer: BufWriter<File>,
+    events: Vec<InputEvent>,
+}
+
+impl Recorder {
+    /// creates or truncates the file at path, and starts a recording of a session with seed
+    pub fn create<P: AsRef<Path>>(path: P, seed: u64) -> Result<Recorder, ReplayError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        bincode::serialize_into(
+            &mut writer,
+            &Header {
+                version: VERSION,
+                seed,
+            },
+        )?;
+
+        Ok(Recorder {
+            writer,
+            events: Vec::new(),
+        })
+    }
+
+    /// records event for the next frame
+    pub fn record(&mut self, event: InputEvent) {
+        self.events.push(event);
+    }
+
+    /// writes the frame with the events recorded since the last one. Flushes right away, so
+    /// nothing is lost if the game crashes on the next frame.
+    pub fn end_frame(&mut self, delta: Duration) -> Result<(), ReplayError> {
+        let frame = Frame {
+            delta,
+            events: std::mem::take(&mut self.events),
+        };
+
+        bincode::serialize_into(&mut self.writer, &frame)?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// A loaded recording, consumed a frame at a time
+pub struct Replay {
+    seed: u64,
+    frames: VecDeque<Frame>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, frames: Vec<Frame>) -> Replay {
+        Replay {
+            seed,
+            frames: frames.into(),
+        }
+    }
+
+    /// loads a recording written by `Recorder`. A frame cut short, eq. by a crash while it was
+    /// being written, ends the replay.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let Header { version, seed } = bincode::deserialize_from(&mut reader)?;
+
+        if version != VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let mut frames = VecDeque::new();
+
+        loop {
+            match bincode::deserialize_from(&mut reader) {
+                Ok(frame) => frames.push_back(frame),
+                Err(err) => match *err {
+                    bincode::ErrorKind::Io(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
+                        break
+                    }
+                    _ => return Err(err.into()),
+                },
+            }
+        }
+
+        Ok(Replay { seed, frames })
+    }
+
+    /// seed the recorded session ran with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// frames left to replay
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        self.frames.pop_front()
+    }
+}
+
+/// applies a recorded event to io_state and pushes it to the game, like the application does
+/// with live ones
+pub(crate) fn push_input<G: Playable>(
+    game: &mut G,
+    io_state: &mut IoState,
+    event: &InputEvent,
+) -> Option<ControlFlow> {
+    io_state.apply(event);
+
+    event.to_event().and_then(|event| game.push_event(&event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// records three frames to a file named name in the temp dir, returns its path
+    fn record(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut recorder = Recorder::create(&path, 7).unwrap();
+
+        recorder.record(InputEvent::Key {
+            scancode: 57,
+            pressed: true,
+        });
+        recorder.end_frame(Duration::from_millis(16)).unwrap();
+        recorder.end_frame(Duration::from_millis(17)).unwrap();
+        recorder.record(InputEvent::Text('x'));
+        recorder.end_frame(Duration::from_millis(18)).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn loads_recorded_frames() {
+        let mut replay = Replay::load(record("mela_loads_recorded_frames.replay")).unwrap();
+
+        assert_eq!(replay.seed(), 7);
+        assert_eq!(replay.len(), 3);
+
+        let frame = replay.next_frame().unwrap();
+        assert_eq!(frame.delta, Duration::from_millis(16));
+        assert_eq!(
+            frame.events,
+            vec![InputEvent::Key {
+                scancode: 57,
+                pressed: true,
+            }]
+        );
+
+        assert!(replay.next_frame().unwrap().events.is_empty());
+        assert_eq!(
+            replay.next_frame().unwrap().events,
+            vec![InputEvent::Text('x')]
+        );
+        assert!(replay.next_frame().is_none());
+    }
+
+    #[test]
+    fn truncated_frames_end_the_replay() {
+        let path = record("mela_truncated_frames.replay");
+        let bytes = std::fs::read(&path).unwrap();
+
+        std::fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+
+        let mut replay = Replay::load(&path).unwrap();
+        assert_eq!(replay.len(), 2);
+        assert_eq!(
+            replay.next_frame().unwrap().delta,
+            Duration::from_millis(16)
+        );
+    }
+
+    #[test]
+    fn truncated_headers_are_errors() {
+        let path = record("mela_truncated_headers.replay");
+        let bytes = std::fs::read(&path).unwrap();
+
+        std::fs::write(&path, &bytes[..6]).unwrap();
+
+        assert!(matches!(
+            Replay::load(&path),
+            Err(ReplayError::BincodeError(_))
+        ));
+    }
+}
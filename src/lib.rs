@@ -20,6 +20,8 @@ pub mod debug;
 pub mod ecs;
 pub mod game;
 pub mod gfx;
+pub mod headless;
+pub mod input;
 //pub mod profiler;
 pub mod state;
 
@@ -0,0 +1,274 @@
+use std::time::Duration;
+
+use mela::application::Settings;
+use mela::debug::DebugContext;
+use mela::ecs::system::Read;
+use mela::ecs::world::{DefaultWorld, World};
+use mela::ecs::{Component, Dispatcher, System, WriteRes};
+use mela::game::{IoState, Playable};
+use mela::gfx::primitives::Quad;
+use mela::gfx::{RenderContext, Spritebatch};
+use mela::headless::HeadlessRunner;
+use mela::input::replay::{Recorder, Replay};
+use mela::input::InputEvent;
+use mela::winit::event::{Event, WindowEvent};
+use mela::winit::event_loop::ControlFlow;
+use mela::winit::window::WindowId;
+
+const SPACE: u32 = 57;
+
+#[derive(Debug, Default, PartialEq)]
+struct Counter {
+    seed: u64,
+    updates: u32,
+    ticks: u32,
+    jumps: u32,
+    tick_jumps: u32,
+    null_contexts: bool,
+}
+
+impl Playable for Counter {
+    fn update(
+        self,
+        _delta: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) -> Self {
+        Counter {
+            updates: self.updates + 1,
+            jumps: self.jumps + io_state.pressed(SPACE) as u32,
+            null_contexts: render_ctx.is_null() && debug_ctx.ui.is_none(),
+            ..self
+        }
+    }
+
+    fn fixed_update(
+        self,
+        _step: Duration,
+        io_state: &IoState,
+        _render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) -> Self {
+        Counter {
+            ticks: self.ticks + 1,
+            tick_jumps: self.tick_jumps + io_state.pressed(SPACE) as u32,
+            ..self
+        }
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn push_event<T>(&mut self, event: &Event<T>) -> Option<ControlFlow> {
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => Some(ControlFlow::Exit),
+            _ => None,
+        }
+    }
+
+    fn redraw(&self, _render_ctx: &mut RenderContext, _debug_ctx: &mut DebugContext) -> () {
+        panic!("nothing is drawn without rendering");
+    }
+}
+
+#[derive(Debug)]
+struct Sprite([f32; 2]);
+
+impl Component for Sprite {}
+
+/// quads in the spritebatch after the last update
+struct Batched(usize);
+
+struct SpriteSystem {
+    spritebatch: Spritebatch,
+}
+
+impl System<DefaultWorld> for SpriteSystem {
+    type SystemData<'a> = (Read<'a, Sprite>, WriteRes<'a, Batched>);
+
+    fn name(&self) -> &'static str {
+        "SpriteSystem"
+    }
+
+    fn update<'f>(
+        &mut self,
+        (sprites, mut batched): Self::SystemData<'f>,
+        _delta: Duration,
+        _io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        _debug_ctx: &mut DebugContext,
+    ) {
+        let quad = Quad::new(0., 0., 16., 16., 64., 64.);
+
+        self.spritebatch.clear();
+
+        for (_, Sprite(position)) in sprites.iter() {
+            self.spritebatch.add_quad(&quad, *position);
+        }
+
+        self.spritebatch.update(render_ctx);
+        batched.0 = self.spritebatch.len();
+    }
+
+    fn draw(&self, render_ctx: &mut RenderContext) {
+        self.spritebatch
+            .draw(&nalgebra::Matrix4::identity(), render_ctx);
+    }
+}
+
+struct Sprites {
+    world: DefaultWorld,
+    dispatcher: Dispatcher<DefaultWorld>,
+}
+
+impl Playable for Sprites {
+    fn update(
+        mut self,
+        delta: Duration,
+        io_state: &IoState,
+        render_ctx: &mut RenderContext,
+        debug_ctx: &mut DebugContext,
+    ) -> Self {
+        self.dispatcher
+            .dispatch(&self.world, delta, io_state, render_ctx, debug_ctx);
+        self
+    }
+
+    fn push_event<T>(&mut self, _event: &Event<T>) -> Option<ControlFlow> {
+        None
+    }
+
+    fn redraw(&self, render_ctx: &mut RenderContext, _debug_ctx: &mut DebugContext) {
+        self.dispatcher.render(render_ctx);
+    }
+}
+
+fn settings() -> Settings {
+    Settings {
+        tick_rate: Some(50),
+        ..Settings::default()
+    }
+}
+
+#[test]
+fn runs_updates_and_ticks_without_rendering() {
+    let game = HeadlessRunner::new(Counter::default())
+        .with_settings(settings())
+        .with_frame_time(Duration::from_millis(40))
+        .without_rendering()
+        .run_frames(30);
+
+    assert_eq!(game.updates, 30);
+    assert_eq!(game.ticks, 60);
+    assert!(game.null_contexts);
+}
+
+#[test]
+fn scripted_keys_reach_update() {
+    let game = HeadlessRunner::new(Counter::default())
+        .with_key(5, SPACE, true)
+        .with_key(6, SPACE, false)
+        .with_key(10, SPACE, true)
+        .without_rendering()
+        .run_frames(20);
+
+    assert_eq!(game.jumps, 2);
+}
+
+#[test]
+fn stops_when_game_exits() {
+    let close = Event::WindowEvent {
+        window_id: unsafe { WindowId::dummy() },
+        event: WindowEvent::CloseRequested,
+    };
+
+    let game = HeadlessRunner::new(Counter::default())
+        .with_event(10, close)
+        .without_rendering()
+        .run_frames(20);
+
+    assert_eq!(game.updates, 10);
+}
+
+#[test]
+fn replays_reach_the_recorded_state() {
+    let path = std::env::temp_dir().join("mela_headless_replay.replay");
+    let frame_time = Duration::from_millis(10);
+    let keys = [(4, true), (7, false), (10, true), (30, false)];
+
+    // record the same input the scripted run gets
+    let mut recorder = Recorder::create(&path, 42).unwrap();
+    let mut scripted = HeadlessRunner::new(Counter::default())
+        .with_settings(settings())
+        .with_frame_time(frame_time);
+
+    for frame in 0..40 {
+        for &(_, pressed) in keys.iter().filter(|(at, _)| *at == frame) {
+            recorder.record(InputEvent::Key {
+                scancode: SPACE,
+                pressed,
+            });
+            scripted = scripted.with_key(frame, SPACE, pressed);
+        }
+
+        recorder.end_frame(frame_time).unwrap();
+    }
+
+    drop(recorder);
+
+    let recorded = scripted.without_rendering().run_frames(40);
+
+    let replay = Replay::load(&path).unwrap();
+    let frames = replay.len() as u64;
+
+    // at the default frame time, so only the deltas of the replay can get the ticks right
+    let replayed = HeadlessRunner::new(Counter::default())
+        .with_settings(settings())
+        .with_replay(replay)
+        .without_rendering()
+        .run_frames(frames);
+
+    assert_eq!(frames, 40);
+    assert_eq!(replayed.seed, 42);
+    assert_eq!(
+        Counter {
+            seed: 0,
+            ..replayed
+        },
+        recorded
+    );
+    assert_eq!(
+        (recorded.jumps, recorded.tick_jumps, recorded.ticks),
+        (2, 2, 20)
+    );
+}
+
+#[test]
+fn spritebatch_systems_run_without_rendering() {
+    let world = DefaultWorld::new()
+        .register::<Sprite>()
+        .with_resource(Batched(0))
+        .add_entity()
+        .with_component(Sprite([0., 0.]))
+        .build()
+        .add_entity()
+        .with_component(Sprite([16., 0.]))
+        .build();
+
+    let game = Sprites {
+        world,
+        dispatcher: Dispatcher::new().with(SpriteSystem {
+            spritebatch: Spritebatch::headless(),
+        }),
+    };
+
+    let game = HeadlessRunner::new(game).without_rendering().run_frames(3);
+
+    let batched = game.world.resources().read::<Batched>().unwrap().0;
+    assert_eq!(batched, 2);
+}